@@ -1,5 +1,4 @@
-use std::io::Write;
-
+use bicoro::executor::{run_io, RunOutcome};
 use bicoro::*;
 mod turnstile;
 use turnstile::create;
@@ -35,7 +34,7 @@ pub fn needs_input() -> Coroutine<'static, Input, Output, turnstile::Input> {
         }
     }
     // kicks off the loop
-    let initial_prompt = format!("What do you do?: ");
+    let initial_prompt = "What do you do?: ".to_string();
     send(Output::StdOut(initial_prompt))
         .and_then(|()| send(Output::Flush))
         .and_then(|()| receive())
@@ -46,7 +45,7 @@ pub fn needs_input() -> Coroutine<'static, Input, Output, turnstile::Input> {
 pub fn on_output(o: turnstile::Output) -> Coroutine<'static, Input, Output, ()> {
     send(Output::StdOut(format!(
         "Turnstile responds with: '{}'\r\n",
-        o.to_string()
+        o
     )))
 }
 
@@ -55,32 +54,27 @@ pub fn main() {
 
     // we can run a child routine inside this routine, with the provided functiosn to convert
     // the inputs and outputs. Result remains the same.
-    let mut composed = send(Output::StdOut(
+    let composed = send(Output::StdOut(
         "You are stopped by a turnstile!\r\n".to_string(),
     ))
     .and_then(|()| send(Output::Flush))
     .and_then(|()| subroutine(needs_input, on_output, turnstile));
+    // Our terminal is just lines in and text out, which run_io handles for us
+    let composed = map_input(composed, Input);
+    let composed = map_output(composed, |output| match output {
+        Output::StdOut(o) => o,
+        Output::StdErr(e) => e,
+        // run_io flushes before every read anyway
+        Output::Flush => String::new(),
+    });
 
-    // This is the main loop. Notice it's really only concerned
-    // with handling inputs and outputs of the termninal.
-    loop {
-        match run_step(composed) {
-            StepResult::Done(_) => unreachable!(), // just for this case, as its a non-exiting coroutine
-            StepResult::Yield { output, next } => {
-                match output {
-                    Output::StdOut(o) => print!("{}", o),
-                    Output::StdErr(e) => print!("{}", e),
-                    Output::Flush => std::io::stdout().flush().unwrap(),
-                }
-                composed = *next;
-            }
-            StepResult::Next(fun) => {
-                // Prompts only appear when needed here
-                let mut buf = String::new();
-                std::io::stdin().read_line(&mut buf).unwrap();
-                let input = Input(buf);
-                composed = fun(input);
-            }
-        }
+    // This is the main loop.
+    // Prompts only appear when needed, as inputs are only read when asked for
+    let stdin = std::io::stdin();
+    match run_io(composed, stdin.lock(), std::io::stdout()) {
+        // it's a non-exiting coroutine, so it never finishes
+        RunOutcome::Completed(never) => match never {},
+        RunOutcome::Paused(_) => println!("\r\nThe turnstile is left behind"),
+        RunOutcome::Failed(error) => eprintln!("The terminal failed: {}", error),
     }
 }
@@ -0,0 +1,309 @@
+//! A lightweight actor system built from coroutines
+//!
+//! Each actor is a coroutine that receives messages of type M,
+//! and emits envelopes. An envelope is either addressed to another actor
+//! in the system, or is an external output to be handled by the executor.
+//!
+//! The system itself is just another coroutine, so it can be run
+//! with any executor, or nested inside other coroutines.
+//! ```
+//! use bicoro::*;
+//! use bicoro::actors::*;
+//! use bicoro::iterator::*;
+//!
+//! let mut system = ActorSystem::new();
+//!
+//! // doubles whatever it is sent, and reports it to the outside world
+//! let doubler = system.spawn(receive().and_then(|i: i32| send(Envelope::External(i * 2))));
+//!
+//! // forwards its input to the doubler
+//! let forwarder = system.spawn(
+//!     receive().and_then(move |i: i32| send(Envelope::To { address: doubler, message: i })),
+//! );
+//!
+//! let inputs = vec![(forwarder, 21)];
+//! let outputs = as_iterator(system.run(), inputs.into_iter()).collect::<Vec<_>>();
+//! assert_eq!(outputs, vec![42]);
+//! ```
+use std::{fmt::Debug, hash::Hash, marker::PhantomData};
+
+use crate::{
+    bind,
+    mailbox::{Delivery, Mailbox},
+    receive, result, right, send,
+    slot::Slot,
+    Coroutine, Never,
+};
+
+/// The address of an actor in a system
+///
+/// Addresses are cheap to copy, so they can be sent inside messages
+/// allowing actors to discover each other at runtime.
+pub struct Address<M> {
+    id: usize,
+    message: PhantomData<fn(M)>,
+}
+
+impl<M> Address<M> {
+    fn new(id: usize) -> Self {
+        let message = PhantomData;
+        Address { id, message }
+    }
+
+    /// The index of the actor in the system, in spawn order
+    pub fn id(&self) -> usize {
+        self.id
+    }
+}
+
+impl<M> Clone for Address<M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M> Copy for Address<M> {}
+
+impl<M> PartialEq for Address<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<M> Eq for Address<M> {}
+
+impl<M> Hash for Address<M> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state)
+    }
+}
+
+impl<M> Debug for Address<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Address").field(&self.id).finish()
+    }
+}
+
+/// What an actor emits
+///
+/// Either a message for another actor, or an output
+/// that leaves the system
+pub enum Envelope<M, O> {
+    /// Deliver the message to the actor at address
+    To { address: Address<M>, message: M },
+    /// Emit this value from the system
+    External(O),
+}
+
+/// An actor, that receives M, and emits envelopes
+pub type Actor<'a, M, O, R> = Coroutine<'a, M, Envelope<M, O>, R>;
+
+/// The coroutine that runs the actor system
+///
+/// Inputs are delivered to the addressed actor, and completes
+/// with every actors result, in spawn order, once they have all finished
+pub type ActorSystemRoutine<'a, M, O, R> = Coroutine<'a, (Address<M>, M), O, Vec<R>>;
+
+/// A collection of actors that can message each other
+///
+/// Spawn actors into the system, then call run to turn
+/// it into a coroutine.
+pub struct ActorSystem<'a, M, O, R> {
+    mailbox: Mailbox<'a, M, Envelope<M, O>, R>,
+}
+
+impl<'a, M, O, R> Default for ActorSystem<'a, M, O, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, M, O, R> ActorSystem<'a, M, O, R> {
+    /// Creates a system with no actors
+    pub fn new() -> Self {
+        ActorSystem {
+            mailbox: Mailbox::new(Vec::new()),
+        }
+    }
+
+    /// Adds the actor to the system, returning its address
+    pub fn spawn(&mut self, actor: Actor<'a, M, O, R>) -> Address<M> {
+        self.spawn_with(|_| actor)
+    }
+
+    /// Adds an actor that needs to know its own address
+    ///
+    /// This is useful for actors that send their address
+    /// to others so that they can be replied to
+    pub fn spawn_with<F>(&mut self, create: F) -> Address<M>
+    where
+        F: FnOnce(Address<M>) -> Actor<'a, M, O, R>,
+    {
+        let address = Address::new(self.mailbox.slots.len());
+        self.mailbox.slots.push(Slot::Running(create(address)));
+        address
+    }
+
+    /// Queues a message for delivery before any external input
+    pub fn tell(&mut self, address: Address<M>, message: M) {
+        self.mailbox.post(address.id, message);
+    }
+
+    /// Runs the actor system
+    ///
+    /// Messages between actors are delivered in the order they were sent.
+    /// Only once every message has been delivered, and every actor is
+    /// waiting, will the system ask for an external input.
+    /// Messages addressed to actors that have finished are dropped.
+    pub fn run(self) -> ActorSystemRoutine<'a, M, O, R>
+    where
+        M: Send,
+        O: Send,
+        R: Send,
+    {
+        advance(self, |output| output, None)
+    }
+
+    /// Runs the actor system, sending Quiescent whenever it goes idle
+    ///
+    /// The system is quiescent when every message has been delivered, and
+    /// every actor is waiting for an external input or has finished. Nothing will
+    /// happen until another input arrives, so it is a safe point to checkpoint,
+    /// or to stop feeding inputs.
+    /// ```
+    /// use bicoro::*;
+    /// use bicoro::actors::*;
+    /// use bicoro::iterator::*;
+    ///
+    /// let mut system = ActorSystem::new();
+    /// let echo: Actor<i32, i32, ()> = receive().and_then(|i| send(Envelope::External(i)));
+    /// let address = system.spawn(echo);
+    ///
+    /// let inputs = vec![(address, 1)];
+    /// let outputs = as_iterator(system.run_with_quiescence(), inputs.into_iter()).collect::<Vec<_>>();
+    /// assert_eq!(outputs, vec![SystemOutput::Quiescent, SystemOutput::Output(1)]);
+    /// ```
+    pub fn run_with_quiescence(self) -> QuiescentSystemRoutine<'a, M, O, R>
+    where
+        M: Send,
+        O: Send,
+        R: Send,
+    {
+        advance(self, SystemOutput::Output, Some(|| SystemOutput::Quiescent))
+    }
+}
+
+/// An output of an actor system run with quiescence detection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SystemOutput<O> {
+    /// An external output from an actor
+    Output(O),
+    /// Every actor is idle, waiting for an external input
+    Quiescent,
+}
+
+/// The actor system, also sending when it is quiescent
+pub type QuiescentSystemRoutine<'a, M, O, R> =
+    Coroutine<'a, (Address<M>, M), SystemOutput<O>, Vec<R>>;
+
+// Outputs are wrapped, so the same scheduler can report quiescence or not
+fn advance<'a, M, O, R, T>(
+    mut system: ActorSystem<'a, M, O, R>,
+    wrap: fn(O) -> T,
+    quiescent: Option<fn() -> T>,
+) -> Coroutine<'a, (Address<M>, M), T, Vec<R>>
+where
+    M: Send,
+    O: Send,
+    R: Send,
+    T: Send,
+{
+    let delivered = system
+        .mailbox
+        .settle::<_, Never>(|_, envelope| match envelope {
+            Envelope::To { address, message } => Delivery::To(address.id, message),
+            Envelope::External(output) => Delivery::External(output),
+        });
+    match delivered {
+        Some(Ok(output)) => {
+            let output = send(wrap(output));
+            return bind(output, move |()| advance(system, wrap, quiescent));
+        }
+        Some(Err(never)) => match never {},
+        None => {}
+    }
+
+    if system.mailbox.is_finished() {
+        let results = system
+            .mailbox
+            .slots
+            .into_iter()
+            .filter_map(|slot| slot.into_result().ok())
+            .collect();
+        result(results)
+    } else {
+        let on_input = move |(address, message): (Address<M>, M)| {
+            system.tell(address, message);
+            advance(system, wrap, quiescent)
+        };
+        let next = bind(receive(), on_input);
+        match quiescent {
+            Some(quiescent) => right(send(quiescent()), next),
+            None => next,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iterator::as_iterator;
+
+    type Co<I, O, R> = Coroutine<'static, I, O, R>;
+
+    enum Message {
+        Ping(Address<Message>),
+        Pong,
+    }
+
+    #[test]
+    fn actors_can_reply_to_each_other() {
+        let mut system = ActorSystem::new();
+        let ponger: Co<Message, Envelope<Message, &str>, ()> =
+            receive().and_then(|message| match message {
+                Message::Ping(reply) => send(Envelope::To {
+                    address: reply,
+                    message: Message::Pong,
+                }),
+                Message::Pong => result(()),
+            });
+        let ponger = system.spawn(ponger);
+        system.spawn_with(|me| {
+            send(Envelope::To {
+                address: ponger,
+                message: Message::Ping(me),
+            })
+            .and_then(|()| receive())
+            .and_then(|message| match message {
+                Message::Pong => send(Envelope::External("pong")),
+                Message::Ping(_) => result(()),
+            })
+        });
+
+        let mut it = as_iterator(system.run(), std::iter::empty());
+        let outputs = it.by_ref().collect::<Vec<_>>();
+        assert_eq!(outputs, vec!["pong"]);
+        let (result, _) = it.finish();
+        assert!(matches!(result, Ok(results) if results.len() == 2));
+    }
+
+    #[test]
+    fn waits_for_external_input() {
+        let mut system: ActorSystem<i32, i32, i32> = ActorSystem::new();
+        let echo = system.spawn(receive());
+        let it = as_iterator(system.run(), std::iter::empty());
+        let (result, _) = it.finish();
+        assert!(result.is_err());
+        assert_eq!(echo.id(), 0);
+    }
+}
@@ -0,0 +1,162 @@
+//! Synthetic workloads, to measure how fast coroutines run on a host
+//!
+//! A workload reads a number of inputs, sends a number of outputs for each,
+//! and can be nested inside layers of subroutines, like a real protocol.
+//! Running it drives the workload with an executor [Driver], the way a host
+//! would, and reports how many steps were taken, how long they took, and the
+//! output latencies the driver recorded with the metrics feature.
+//! Allocations can't be seen by the library, so they are only counted if the
+//! host gives a probe, such as a counter kept by its global allocator.
+//! ```
+//! use bicoro::bench::*;
+//!
+//! let report = Workload::new(100).outputs_per_input(2).nested(3).run();
+//! assert_eq!(report.inputs, 100);
+//! assert_eq!(report.outputs, 200);
+//! assert!(report.steps_per_sec() > 0.0);
+//! assert_eq!(report.latencies.count(), 200);
+//! ```
+use std::time::{Duration, Instant};
+
+use crate::{
+    bind, executor::Driver, metrics::LatencyHistogram, receive, result, send, subroutine, Coroutine,
+};
+
+/// The shape of a synthetic workload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Workload {
+    inputs: usize,
+    outputs_per_input: usize,
+    depth: usize,
+}
+
+/// What happened when a workload was run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchReport {
+    /// Times the coroutine was stepped
+    pub steps: u64,
+    /// Inputs given to the coroutine
+    pub inputs: u64,
+    /// Outputs the coroutine sent
+    pub outputs: u64,
+    /// How long it took, from building the coroutine until it finished
+    pub elapsed: Duration,
+    /// Allocations made, if a probe was given
+    pub allocations: Option<u64>,
+    /// How long outputs waited to be taken, as recorded by the driver
+    pub latencies: LatencyHistogram,
+}
+
+impl BenchReport {
+    /// Steps taken per second
+    pub fn steps_per_sec(&self) -> f64 {
+        self.steps as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+impl Workload {
+    /// Reads this many inputs, sending one output for each
+    pub fn new(inputs: usize) -> Self {
+        Workload {
+            inputs,
+            outputs_per_input: 1,
+            depth: 0,
+        }
+    }
+
+    /// Sends this many outputs for each input
+    pub fn outputs_per_input(self, outputs_per_input: usize) -> Self {
+        Workload {
+            outputs_per_input,
+            ..self
+        }
+    }
+
+    /// Runs the workload inside this many layers of subroutines
+    pub fn nested(self, depth: usize) -> Self {
+        Workload { depth, ..self }
+    }
+
+    /// The coroutine for the workload, finishing with the sum of its inputs
+    pub fn build(&self) -> Coroutine<'static, u64, u64, u64> {
+        (0..self.depth).fold(work(self.inputs, self.outputs_per_input, 0), |co, _| {
+            subroutine(receive, send, co)
+        })
+    }
+
+    /// Runs the workload to the end
+    pub fn run(&self) -> BenchReport {
+        self.measure(None::<fn() -> u64>)
+    }
+
+    /// Runs the workload, also counting allocations
+    ///
+    /// The probe returns the number of allocations made so far, it is read
+    /// before and after the run.
+    pub fn run_counting_allocations<P>(&self, probe: P) -> BenchReport
+    where
+        P: Fn() -> u64,
+    {
+        self.measure(Some(probe))
+    }
+
+    fn measure<P>(&self, probe: Option<P>) -> BenchReport
+    where
+        P: Fn() -> u64,
+    {
+        let before = probe.as_ref().map(|probe| probe());
+        let start = Instant::now();
+
+        // Outputs are acknowledged as each feed returns, so the latencies show
+        // how long an output waited for the rest of its input to be handled
+        let mut driver = Driver::new(self.build());
+        let mut outputs = driver.poll().len();
+        driver.acknowledge(outputs);
+        let mut inputs = 0;
+        for input in 0..self.inputs as u64 {
+            let made = match driver.feed(input) {
+                Ok(made) => made.len(),
+                Err(finished) => {
+                    outputs += finished.outputs.len();
+                    break;
+                }
+            };
+            driver.acknowledge(made);
+            outputs += made;
+            inputs += 1;
+        }
+
+        let elapsed = start.elapsed();
+        let allocations = probe.zip(before).map(|(probe, before)| probe() - before);
+        BenchReport {
+            // Each input and output is a step, and so is the last one,
+            // which finished or found no more inputs
+            steps: (inputs + outputs) as u64 + 1,
+            inputs: inputs as u64,
+            outputs: outputs as u64,
+            elapsed,
+            allocations,
+            latencies: driver.latencies().clone(),
+        }
+    }
+}
+
+// Reads the inputs, sending each one out again repeatedly
+fn work(inputs: usize, outputs_per_input: usize, total: u64) -> Coroutine<'static, u64, u64, u64> {
+    if inputs == 0 {
+        return result(total);
+    }
+    bind(receive(), move |input| {
+        let outputs = repeat(input, outputs_per_input);
+        bind(outputs, move |()| {
+            work(inputs - 1, outputs_per_input, total + input)
+        })
+    })
+}
+
+fn repeat(output: u64, times: usize) -> Coroutine<'static, u64, u64, ()> {
+    if times == 0 {
+        return result(());
+    }
+    bind(send(output), move |()| repeat(output, times - 1))
+}
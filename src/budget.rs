@@ -0,0 +1,113 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// A shared limit on how much buffering combinators may hold
+///
+/// Each combinator can have its own buffer size, but many small buffers
+/// can still add up. A budget is shared by cloning it into each combinator,
+/// and they reserve from it before buffering anything. When the budget is
+/// used up, a combinator treats its buffer as full, and applies its overflow policy.
+///
+/// Only combinators that can do something when full take a budget, like
+/// broadcast_all_budgeted. Those that only hold inputs the coroutine still
+/// needs can't drop them or send them early, so they don't take one.
+/// ```
+/// use bicoro::*;
+///
+/// let budget = Budget::new(2);
+/// let first = budget.try_reserve(1).unwrap();
+/// let second = budget.clone().try_reserve(1).unwrap();
+/// assert!(budget.try_reserve(1).is_none());
+///
+/// drop(first);
+/// assert_eq!(budget.used(), 1);
+/// # drop(second);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Budget {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    capacity: usize,
+    used: AtomicUsize,
+}
+
+impl Budget {
+    /// Creates a budget that allows up to capacity units
+    pub fn new(capacity: usize) -> Self {
+        let used = AtomicUsize::new(0);
+        let inner = Arc::new(Inner { capacity, used });
+        Budget { inner }
+    }
+
+    /// Reserves the amount, if it fits in what is left
+    ///
+    /// The amount is given back when the reservation is dropped
+    pub fn try_reserve(&self, amount: usize) -> Option<Reservation> {
+        let capacity = self.inner.capacity;
+        self.inner
+            .used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(amount).filter(|total| *total <= capacity)
+            })
+            .ok()
+            .map(|_| self.reservation(amount))
+    }
+
+    /// Reserves the amount, even if that goes over capacity
+    ///
+    /// For when dropping isn't an option
+    pub fn reserve(&self, amount: usize) -> Reservation {
+        self.inner.used.fetch_add(amount, Ordering::AcqRel);
+        self.reservation(amount)
+    }
+
+    /// The total that can be reserved
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity
+    }
+
+    /// How much is currently reserved
+    pub fn used(&self) -> usize {
+        self.inner.used.load(Ordering::Acquire)
+    }
+
+    /// How much can still be reserved
+    pub fn remaining(&self) -> usize {
+        self.capacity().saturating_sub(self.used())
+    }
+
+    fn reservation(&self, amount: usize) -> Reservation {
+        let budget = self.clone();
+        Reservation { budget, amount }
+    }
+}
+
+/// Part of a budget held by a buffered value
+///
+/// Gives the amount back to the budget when dropped
+#[derive(Debug)]
+pub struct Reservation {
+    budget: Budget,
+    amount: usize,
+}
+
+impl Reservation {
+    /// The amount reserved
+    pub fn amount(&self) -> usize {
+        self.amount
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget
+            .inner
+            .used
+            .fetch_sub(self.amount, Ordering::AcqRel);
+    }
+}
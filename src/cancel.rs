@@ -0,0 +1,54 @@
+use crate::{bind, receive, result, run_step, send, Coroutine, StepResult};
+
+/// An input for a coroutine that can be cancelled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cancellable<I> {
+    /// An input for the coroutine
+    Input(I),
+    /// Stops the coroutine, the next time it waits for input
+    Cancel,
+}
+
+/// How a cancellable coroutine ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cancelled<R> {
+    /// The coroutine finished by itself
+    Finished(R),
+    /// The coroutine was cancelled before it finished
+    Cancelled,
+}
+
+/// Lets the coroutine be stopped from outside
+///
+/// Coroutines can only be stopped while waiting for input, so a cancel
+/// takes effect at the next point the coroutine would receive. Anything
+/// it was in the middle of is dropped.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let echo: Coroutine<i32, i32, Never> = forever(|| receive().and_then(send));
+/// let co = cancellable(echo);
+///
+/// let inputs = vec![Cancellable::Input(1), Cancellable::Cancel, Cancellable::Input(2)];
+/// let mut it = as_iterator(co, inputs.into_iter());
+/// assert_eq!(it.by_ref().collect::<Vec<_>>(), vec![1]);
+/// assert!(matches!(it.finish(), (Ok(Cancelled::Cancelled), _)));
+/// ```
+pub fn cancellable<'a, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+) -> Coroutine<'a, Cancellable<I>, O, Cancelled<R>>
+where
+    I: 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    match run_step(co) {
+        StepResult::Done(value) => result(Cancelled::Finished(value)),
+        StepResult::Yield { output, next } => bind(send(output), move |()| cancellable(*next)),
+        StepResult::Next(next) => bind(receive(), move |input| match input {
+            Cancellable::Input(input) => cancellable(next(input)),
+            Cancellable::Cancel => result(Cancelled::Cancelled),
+        }),
+    }
+}
@@ -1,4 +1,9 @@
-use crate::{bind, receive, result, right, run_step, send, suspend, Coroutine};
+use std::ops::Shr;
+
+use crate::{
+    bind, cancellable, failable::ResultCoroutine, map, receive, result, right, run_step, send,
+    suspend, time, Cancellable, Cancelled, Coroutine, Timed, TimedOut,
+};
 
 /// Generated by chain, represents feeding
 /// inputs from one routine into another
@@ -69,3 +74,388 @@ where
         },
     }
 }
+
+/// Generated by pipe, holds whichever end finished
+/// and the remaining routine of the other end
+pub enum PipeResult<'a, I, M, O, R1, R2> {
+    /// The upstream finished, so the downstream can't get more inputs
+    Upstream {
+        value: R1,
+        downstream: Coroutine<'a, M, O, R2>,
+    },
+    /// The downstream finished, the upstream can still be ran
+    Downstream {
+        value: R2,
+        upstream: Coroutine<'a, I, M, R1>,
+    },
+}
+
+impl<'a, I, M, O, R1, R2> From<ChainResult<'a, I, M, O, R1, R2>>
+    for PipeResult<'a, I, M, O, R1, R2>
+{
+    fn from(value: ChainResult<'a, I, M, O, R1, R2>) -> Self {
+        match value {
+            ChainResult::OutOfInputs { first, second } => PipeResult::Upstream {
+                value: first,
+                downstream: second,
+            },
+            ChainResult::Finished { second, first } => PipeResult::Downstream {
+                value: second,
+                upstream: first,
+            },
+        }
+    }
+}
+
+/// Connects the outputs of upstream to the inputs of downstream
+///
+/// Every yield of upstream is given to downstream as its next input.
+/// Inputs come from the outside whenever upstream needs one,
+/// and outputs are whatever downstream yields. This is the same as chain,
+/// but names the ends in the result.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// // upstream parses strings, downstream doubles the numbers
+/// let upstream: Coroutine<&str, i32, ()> =
+///     receive().and_then(|s: &str| send(s.parse::<i32>().unwrap()));
+/// let downstream: Coroutine<i32, i32, ()> = receive().and_then(|i: i32| send(i * 2));
+///
+/// let piped = pipe(upstream, downstream);
+/// let outputs = as_iterator(piped, vec!["21"].into_iter()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![42]);
+/// ```
+pub fn pipe<'a, I, M, O, R1, R2>(
+    upstream: Coroutine<'a, I, M, R1>,
+    downstream: Coroutine<'a, M, O, R2>,
+) -> Coroutine<'a, I, O, PipeResult<'a, I, M, O, R1, R2>>
+where
+    M: Send,
+    O: Send,
+    R1: Send,
+    R2: Send,
+{
+    map(chain(upstream, downstream), PipeResult::from)
+}
+
+/// Builds a pipeline of stages, where each stage's outputs feed the next stage
+///
+/// Each stage can change the type flowing through the pipeline,
+/// but all stages share the same result type. The pipeline finishes as
+/// soon as any stage finishes, with that stage's result.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let parse: Coroutine<&str, i32, ()> =
+///     receive().and_then(|s: &str| send(s.parse::<i32>().unwrap()));
+/// let double: Coroutine<i32, i32, ()> = receive().and_then(|i: i32| send(i * 2));
+/// let format: Coroutine<i32, String, ()> = receive().and_then(|i: i32| send(i.to_string()));
+///
+/// let co = Pipeline::new(parse).then(double).then(format).build();
+/// let outputs = as_iterator(co, vec!["21"].into_iter()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec!["42"]);
+/// ```
+pub struct Pipeline<'a, I, O, R> {
+    co: Coroutine<'a, I, O, R>,
+}
+
+impl<'a, I, O, R> Pipeline<'a, I, O, R> {
+    /// Starts a pipeline with the first stage
+    pub fn new(stage: Coroutine<'a, I, O, R>) -> Self {
+        Pipeline { co: stage }
+    }
+
+    /// Feeds the outputs of the pipeline so far into the stage
+    pub fn then<O2>(self, stage: Coroutine<'a, O, O2, R>) -> Pipeline<'a, I, O2, R>
+    where
+        O: Send,
+        O2: Send,
+        R: Send,
+    {
+        let co = map(pipe(self.co, stage), |piped| match piped {
+            PipeResult::Upstream { value, .. } => value,
+            PipeResult::Downstream { value, .. } => value,
+        });
+        Pipeline { co }
+    }
+
+    /// Fuses the stages into a single coroutine
+    pub fn build(self) -> Coroutine<'a, I, O, R> {
+        self.co
+    }
+}
+
+/// Pipes the left coroutine's outputs into the right coroutine
+///
+/// This is the same as a two stage pipeline, so both must share
+/// the same result, and it finishes when either finishes.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let producer: Coroutine<(), i32, ()> = send(1).and_then(|()| send(2));
+/// let transformer: Coroutine<i32, i32, ()> =
+///     receive().and_then(|a: i32| receive().and_then(move |b: i32| send(a + b)));
+/// let consumer: Coroutine<i32, String, ()> = receive().and_then(|i: i32| send(i.to_string()));
+///
+/// let co = producer >> transformer >> consumer;
+/// let outputs = as_iterator(co, std::iter::empty()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec!["3"]);
+/// ```
+impl<'a, I, M, O, R> Shr<Coroutine<'a, M, O, R>> for Coroutine<'a, I, M, R>
+where
+    M: Send,
+    O: Send,
+    R: Send,
+{
+    type Output = Coroutine<'a, I, O, R>;
+
+    fn shr(self, rhs: Coroutine<'a, M, O, R>) -> Self::Output {
+        Pipeline::new(self).then(rhs).build()
+    }
+}
+
+/// Builds a workflow of stages that run one after another
+///
+/// Unlike a pipeline, each stage runs once the last has finished, and gets
+/// its result. Every stage can have a different result type. Each stage is
+/// a separate call, so their types are inferred one at a time, rather than
+/// through nested closures.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let co: Coroutine<&str, String, usize> = stages(receive())
+///     .then(|name: &str| send(format!("hello {}", name)).and_then(move |()| result(name.len())))
+///     .then(|length| receive().and_then(move |surname: &str| result(length + surname.len())))
+///     .build();
+///
+/// let mut it = as_iterator(co, vec!["ada", "lovelace"].into_iter());
+/// assert_eq!(it.by_ref().collect::<Vec<_>>(), vec!["hello ada"]);
+/// assert!(matches!(it.finish(), (Ok(11), _)));
+/// ```
+pub struct Stages<'a, I, O, R> {
+    co: Coroutine<'a, I, O, R>,
+}
+
+/// Starts a workflow with the first stage
+///
+/// see [Stages]
+pub fn stages<'a, I, O, R>(first: Coroutine<'a, I, O, R>) -> Stages<'a, I, O, R> {
+    Stages { co: first }
+}
+
+impl<'a, I, O, R> Stages<'a, I, O, R> {
+    /// Runs the stage made from the result so far
+    pub fn then<R2, F>(self, stage: F) -> Stages<'a, I, O, R2>
+    where
+        F: FnOnce(R) -> Coroutine<'a, I, O, R2> + Send + 'a,
+    {
+        let co = bind(self.co, stage);
+        Stages { co }
+    }
+
+    /// Maps the result so far, without another stage
+    pub fn map<R2, F>(self, f: F) -> Stages<'a, I, O, R2>
+    where
+        F: FnOnce(R) -> R2 + Send + 'a,
+    {
+        let co = map(self.co, f);
+        Stages { co }
+    }
+
+    /// Fuses the stages into a single coroutine
+    pub fn build(self) -> Coroutine<'a, I, O, R> {
+        self.co
+    }
+}
+
+/// The input of guarded stages
+///
+/// Ticks count towards the running stage's timeout, and cancels stop it if
+/// it is cancellable. Stages that aren't guarded ignore them.
+pub type StageInput<I> = Timed<Cancellable<I>>;
+
+/// Why a guarded stage stopped early
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StageFailure {
+    /// The stage was still running after its timeout
+    TimedOut,
+    /// The stage was cancelled
+    Cancelled,
+}
+
+/// Which stage stopped early, and why
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StageError {
+    /// The stage's position, the first stage is 0
+    pub stage: usize,
+    pub failure: StageFailure,
+}
+
+#[derive(Clone, Copy)]
+struct Guard {
+    stage: usize,
+    timeout: Option<usize>,
+    cancellable: bool,
+}
+
+type GuardedRoutine<'a, I, O, R> =
+    Box<dyn FnOnce(Guard) -> Coroutine<'a, StageInput<I>, O, Result<R, StageError>> + Send + 'a>;
+
+/// Builds a workflow of stages, where each stage can time out or be cancelled
+///
+/// Like [Stages], but inputs are [StageInput]s, so the host can send ticks
+/// and cancellations as well as inputs. with_timeout and cancellable apply to
+/// the stage added last, using [time::deadline] and [cancellable](function@cancellable),
+/// and the error says which stage stopped.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let co = guarded_stages(receive())
+///     .then(|a: i32| receive().and_then(move |b: i32| result(a + b)))
+///     .with_timeout(2)
+///     .build();
+///
+/// let inputs = vec![Timed::Input(Cancellable::Input(1)), Timed::Tick, Timed::Tick];
+/// let mut it = as_iterator(co.to_coroutine(), inputs.into_iter());
+/// let _: Vec<()> = it.by_ref().collect();
+/// let error = StageError { stage: 1, failure: StageFailure::TimedOut };
+/// assert!(matches!(it.finish(), (Ok(Err(e)), _) if e == error));
+/// ```
+pub struct GuardedStages<'a, I, O, R> {
+    co: GuardedRoutine<'a, I, O, R>,
+    // The options for the stage added last
+    guard: Guard,
+}
+
+/// Starts a guarded workflow with the first stage
+///
+/// see [GuardedStages]
+pub fn guarded_stages<'a, I, O, R>(first: Coroutine<'a, I, O, R>) -> GuardedStages<'a, I, O, R>
+where
+    I: 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    let guard = Guard {
+        stage: 0,
+        timeout: None,
+        cancellable: false,
+    };
+    GuardedStages {
+        co: Box::new(move |guard| guarded(first, guard)),
+        guard,
+    }
+}
+
+impl<'a, I, O, R> GuardedStages<'a, I, O, R>
+where
+    I: 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    /// Runs the stage made from the result so far
+    pub fn then<R2, F>(self, stage: F) -> GuardedStages<'a, I, O, R2>
+    where
+        F: FnOnce(R) -> Coroutine<'a, I, O, R2> + Send + 'a,
+        R2: Send + 'a,
+    {
+        let GuardedStages { co, guard } = self;
+        let next = Guard {
+            stage: guard.stage + 1,
+            timeout: None,
+            cancellable: false,
+        };
+        let co: GuardedRoutine<'a, I, O, R2> = Box::new(move |next| {
+            bind(co(guard), move |value| match value {
+                Ok(value) => guarded(stage(value), next),
+                Err(error) => result(Err(error)),
+            })
+        });
+        GuardedStages { co, guard: next }
+    }
+
+    /// Fails the last stage on its nth tick
+    pub fn with_timeout(mut self, ticks: usize) -> Self {
+        self.guard.timeout = Some(ticks);
+        self
+    }
+
+    /// Lets the last stage be cancelled
+    pub fn cancellable(mut self) -> Self {
+        self.guard.cancellable = true;
+        self
+    }
+
+    /// Fuses the stages into a single coroutine
+    pub fn build(self) -> ResultCoroutine<'a, StageInput<I>, O, R, StageError> {
+        ResultCoroutine::new((self.co)(self.guard))
+    }
+}
+
+// Wraps a stage in the timers its guard asks for, ignoring the inputs it doesn't
+fn guarded<'a, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+    guard: Guard,
+) -> Coroutine<'a, StageInput<I>, O, Result<R, StageError>>
+where
+    I: 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    let co = if guard.cancellable {
+        cancellable(co)
+    } else {
+        let co = skip_inputs(co, |input| match input {
+            Cancellable::Input(input) => Some(input),
+            Cancellable::Cancel => None,
+        });
+        map(co, Cancelled::Finished)
+    };
+    let co = match guard.timeout {
+        Some(ticks) => time::deadline(co, ticks),
+        None => {
+            let co = skip_inputs(co, |input| match input {
+                Timed::Input(input) => Some(input),
+                Timed::Tick => None,
+            });
+            map(co, Ok)
+        }
+    };
+    let fail = move |failure| StageError {
+        stage: guard.stage,
+        failure,
+    };
+    map(co, move |value| match value {
+        Ok(Cancelled::Finished(value)) => Ok(value),
+        Ok(Cancelled::Cancelled) => Err(fail(StageFailure::Cancelled)),
+        Err(TimedOut) => Err(fail(StageFailure::TimedOut)),
+    })
+}
+
+// Drops the inputs that select turns into None, the coroutine never sees them
+fn skip_inputs<'a, I, IA, O, R>(
+    co: Coroutine<'a, IA, O, R>,
+    select: fn(I) -> Option<IA>,
+) -> Coroutine<'a, I, O, R>
+where
+    I: 'a,
+    IA: 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    match run_step(co) {
+        crate::StepResult::Done(value) => result(value),
+        crate::StepResult::Yield { output, next } => {
+            bind(send(output), move |()| skip_inputs(*next, select))
+        }
+        crate::StepResult::Next(next) => bind(receive(), move |input| match select(input) {
+            Some(input) => skip_inputs(next(input), select),
+            None => skip_inputs(suspend(next), select),
+        }),
+    }
+}
@@ -0,0 +1,165 @@
+//! Executors that read inputs from crossbeam channels
+//!
+//! An event loop often waits on several sources at once, such as
+//! network messages, timer ticks and commands from a ui. These executors
+//! block until any of the channels has an input, and tell the coroutine
+//! which channel it came from. Only compiled with the crossbeam feature.
+//! ```
+//! use bicoro::*;
+//! use bicoro::channels::*;
+//! use crossbeam_channel::unbounded;
+//!
+//! // reports where each input came from
+//! fn report() -> Coroutine<'static, (&'static str, i32), String, ()> {
+//!     receive().and_then(|(source, i): (&str, i32)| send(format!("{} {}", source, i)))
+//!         .and_then(|()| report())
+//! }
+//!
+//! let (keys, key_inputs) = unbounded();
+//! let (ticks, tick_inputs) = unbounded();
+//! keys.send(1).unwrap();
+//! drop(keys);
+//! drop(ticks);
+//!
+//! let mut outputs = Vec::new();
+//! let sources = vec![("keys", key_inputs), ("ticks", tick_inputs)];
+//! let outcome = run_channels(report(), sources, |o| outputs.push(o));
+//! assert_eq!(outputs, vec!["keys 1".to_string()]);
+//! // every channel disconnected, so the coroutine is handed back
+//! assert!(!outcome.is_completed());
+//! ```
+use std::convert::Infallible;
+
+use crossbeam_channel::{never, select, Receiver, Select};
+
+use crate::{executor::RunOutcome, run_step, suspend, Coroutine, StepResult, UnicastSelect};
+
+/// How a coroutine run from channels ended
+///
+/// It is paused if every channel disconnected while it was waiting
+pub type ChannelOutcome<'a, I, O, R> = RunOutcome<R, Coroutine<'a, I, O, R>, Infallible>;
+
+/// Runs the coroutine, receiving from whichever channel is ready first
+///
+/// Each input is tagged with the key of the channel it came from.
+/// Disconnected channels are skipped from then on, and once all of them
+/// have disconnected the waiting coroutine is returned.
+pub fn run_channels<'a, K, I, O, R, F>(
+    mut co: Coroutine<'a, (K, I), O, R>,
+    mut sources: Vec<(K, Receiver<I>)>,
+    mut on_output: F,
+) -> ChannelOutcome<'a, (K, I), O, R>
+where
+    K: Clone,
+    F: FnMut(O),
+{
+    loop {
+        match run_step(co) {
+            StepResult::Done(value) => return RunOutcome::Completed(value),
+            StepResult::Yield { output, next } => {
+                on_output(output);
+                co = *next;
+            }
+            StepResult::Next(next) => loop {
+                if sources.is_empty() {
+                    return RunOutcome::Paused(suspend(next));
+                }
+                let (index, received) = {
+                    let mut select = Select::new();
+                    for (_, receiver) in &sources {
+                        select.recv(receiver);
+                    }
+                    let operation = select.select();
+                    let index = operation.index();
+                    (index, operation.recv(&sources[index].1))
+                };
+                match received {
+                    Ok(input) => {
+                        co = next((sources[index].0.clone(), input));
+                        break;
+                    }
+                    Err(_) => {
+                        sources.remove(index);
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// Runs the coroutine from two channels of different inputs
+///
+/// Inputs from the left channel are sent as UnicastSelect::Left, and from
+/// the right as UnicastSelect::Right. Like run_channels, the coroutine
+/// is returned once both channels have disconnected.
+/// ```
+/// use bicoro::*;
+/// use bicoro::channels::*;
+/// use crossbeam_channel::unbounded;
+///
+/// // adds up numbers, until told to stop
+/// fn total(sum: i32) -> Coroutine<'static, UnicastSelect<i32, ()>, (), i32> {
+///     receive().and_then(move |i| match i {
+///         UnicastSelect::Left(n) => total(sum + n),
+///         UnicastSelect::Right(()) => result(sum),
+///     })
+/// }
+///
+/// let (numbers, number_inputs) = unbounded();
+/// let (stop, stop_inputs) = unbounded();
+/// numbers.send(1).unwrap();
+/// numbers.send(2).unwrap();
+/// drop(numbers);
+/// stop.send(()).unwrap();
+///
+/// let outcome = run_select(total(0), number_inputs, stop_inputs, |()| {});
+/// assert!(outcome.is_completed());
+/// ```
+pub fn run_select<'a, A, B, O, R, F>(
+    mut co: Coroutine<'a, UnicastSelect<A, B>, O, R>,
+    mut left: Receiver<A>,
+    mut right: Receiver<B>,
+    mut on_output: F,
+) -> ChannelOutcome<'a, UnicastSelect<A, B>, O, R>
+where
+    F: FnMut(O),
+{
+    let mut open = 2;
+    loop {
+        match run_step(co) {
+            StepResult::Done(value) => return RunOutcome::Completed(value),
+            StepResult::Yield { output, next } => {
+                on_output(output);
+                co = *next;
+            }
+            StepResult::Next(next) => loop {
+                if open == 0 {
+                    return RunOutcome::Paused(suspend(next));
+                }
+                // A disconnected channel is swapped for one that never receives
+                let input = select! {
+                    recv(left) -> received => match received {
+                        Ok(a) => Some(UnicastSelect::Left(a)),
+                        Err(_) => {
+                            left = never();
+                            open -= 1;
+                            None
+                        }
+                    },
+                    recv(right) -> received => match received {
+                        Ok(b) => Some(UnicastSelect::Right(b)),
+                        Err(_) => {
+                            right = never();
+                            open -= 1;
+                            None
+                        }
+                    },
+                };
+                if let Some(input) = input {
+                    co = next(input);
+                    break;
+                }
+            },
+        }
+    }
+}
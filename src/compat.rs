@@ -43,9 +43,9 @@ impl<'a, I, O, R> Coroutine<'a, I, O, R> {
     /// Chains coroutines
     ///
     /// see [bind](function@bind)  
-    pub fn and_then<F: 'a, B>(self, f: F) -> Coroutine<'a, I, O, B>
+    pub fn and_then<F, B>(self, f: F) -> Coroutine<'a, I, O, B>
     where
-        F: FnOnce(R) -> Coroutine<'a, I, O, B> + Send,
+        F: FnOnce(R) -> Coroutine<'a, I, O, B> + Send + 'a,
     {
         bind(self, f)
     }
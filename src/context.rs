@@ -0,0 +1,63 @@
+use crate::{bind, result, run_step, send, suspend, Coroutine, StepResult};
+
+/// A value, with the context it was caused by
+///
+/// The context is usually a trace or correlation id,
+/// so outputs can be tied back to the input that caused them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WithContext<C, T> {
+    pub context: C,
+    pub value: T,
+}
+
+impl<C, T> WithContext<C, T> {
+    /// Attaches the context to the value
+    pub fn new(context: C, value: T) -> Self {
+        WithContext { context, value }
+    }
+}
+
+/// Carries the context of each input through to the outputs it causes
+///
+/// The coroutine itself never sees the context. Every output sent
+/// after an input, and before the next one is received, is caused by that input,
+/// so it gets that input's context. Outputs sent before the first input
+/// get the initial context.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let echo: Coroutine<i32, i32, ()> = send(0).and_then(|()| receive()).and_then(|i| send(i).and_then(move |()| send(i * 2)));
+/// let co = propagate_context("startup", echo);
+///
+/// let inputs = vec![WithContext::new("request-1", 5)];
+/// let outputs = as_iterator(co, inputs.into_iter()).collect::<Vec<_>>();
+/// assert_eq!(
+///     outputs,
+///     vec![
+///         WithContext::new("startup", 0),
+///         WithContext::new("request-1", 5),
+///         WithContext::new("request-1", 10),
+///     ]
+/// );
+/// ```
+pub fn propagate_context<'a, C, I, O, R>(
+    initial: C,
+    co: Coroutine<'a, I, O, R>,
+) -> Coroutine<'a, WithContext<C, I>, WithContext<C, O>, R>
+where
+    C: Clone + Send + 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    match run_step(co) {
+        StepResult::Done(value) => result(value),
+        StepResult::Yield { output, next } => {
+            let output = WithContext::new(initial.clone(), output);
+            bind(send(output), move |()| propagate_context(initial, *next))
+        }
+        StepResult::Next(next) => suspend(move |input: WithContext<C, I>| {
+            propagate_context(input.context, next(input.value))
+        }),
+    }
+}
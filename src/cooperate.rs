@@ -1,6 +1,7 @@
 use crate::{
-    bind, inject, receive, result, right, run_step, send, suspend, Coroutine, StepResult,
-    UnicastSelect,
+    bind, inject, map, receive, result, right,
+    routed::{Exchanges, Livelock},
+    run_step, send, suspend, Coroutine, StepResult, UnicastSelect,
 };
 
 /// Represents the result of running the left and right coroutines
@@ -15,6 +16,17 @@ pub enum CooperateResult<'a, IA, IB, OA, OB, A, B> {
         remaining: Coroutine<'a, IA, OA, A>,
     },
 }
+/// What cooperate_with_budget returns, the continuations if they livelock
+pub type BudgetedCooperation<'a, I, O, IA, OA, IB, OB, A, B> = Coroutine<
+    'a,
+    I,
+    O,
+    Result<
+        CooperateResult<'a, IA, IB, OA, OB, A, B>,
+        Livelock<Coroutine<'a, IA, OA, A>, Coroutine<'a, IB, OB, B>>,
+    >,
+>;
+
 pub fn cooperate<'a, I, O, IA, OA, IB, OB, MA, MB, A, B, S>(
     selector: S,
     map_first: MA,
@@ -22,6 +34,78 @@ pub fn cooperate<'a, I, O, IA, OA, IB, OB, MA, MB, A, B, S>(
     first: Coroutine<'a, IA, OA, A>,
     second: Coroutine<'a, IB, OB, B>,
 ) -> Coroutine<'a, I, O, CooperateResult<'a, IA, IB, OA, OB, A, B>>
+where
+    S: Fn(I) -> UnicastSelect<IA, IB> + Send + 'a,
+    MA: Fn(OA) -> UnicastSelect<IB, O> + Send + 'a,
+    MB: Fn(OB) -> UnicastSelect<IA, O> + Send + 'a,
+    OA: Send,
+    OB: Send,
+    B: Send,
+    A: Send,
+    O: Send,
+{
+    let exchanges = Exchanges::unlimited();
+    let co = cooperating(selector, map_first, map_second, first, second, exchanges);
+    map(co, |r| match r {
+        Ok(r) => r,
+        Err(_) => unreachable!("unlimited exchanges never livelock"),
+    })
+}
+
+/// Cooperate, but stops when the coroutines livelock
+///
+/// Like routed_with_budget, at most budget exchanges can happen in a row
+/// before an output is sent or an input is received.
+/// ```
+/// use bicoro::*;
+///
+/// // passes a number back and forth, adding one each time
+/// fn bump() -> Coroutine<'static, u32, u32, ()> {
+///     receive().and_then(|n: u32| send(n + 1)).and_then(|()| bump())
+/// }
+///
+/// let first = send(0).and_then(|()| bump());
+/// let co = cooperate_with_budget(
+///     |i: u32| UnicastSelect::<u32, u32>::Left(i),
+///     |o: u32| UnicastSelect::<u32, u32>::Left(o),
+///     |o: u32| UnicastSelect::<u32, u32>::Left(o),
+///     first,
+///     bump(),
+///     10,
+/// );
+/// let (_, value) = bicoro::executor::run_until_input(co);
+/// assert!(matches!(value, Ok(Err(Livelock { .. }))));
+/// ```
+pub fn cooperate_with_budget<'a, I, O, IA, OA, IB, OB, MA, MB, A, B, S>(
+    selector: S,
+    map_first: MA,
+    map_second: MB,
+    first: Coroutine<'a, IA, OA, A>,
+    second: Coroutine<'a, IB, OB, B>,
+    budget: usize,
+) -> BudgetedCooperation<'a, I, O, IA, OA, IB, OB, A, B>
+where
+    S: Fn(I) -> UnicastSelect<IA, IB> + Send + 'a,
+    MA: Fn(OA) -> UnicastSelect<IB, O> + Send + 'a,
+    MB: Fn(OB) -> UnicastSelect<IA, O> + Send + 'a,
+    OA: Send,
+    OB: Send,
+    B: Send,
+    A: Send,
+    O: Send,
+{
+    let exchanges = Exchanges::limited(budget);
+    cooperating(selector, map_first, map_second, first, second, exchanges)
+}
+
+fn cooperating<'a, I, O, IA, OA, IB, OB, MA, MB, A, B, S>(
+    selector: S,
+    map_first: MA,
+    map_second: MB,
+    first: Coroutine<'a, IA, OA, A>,
+    second: Coroutine<'a, IB, OB, B>,
+    exchanges: Exchanges,
+) -> BudgetedCooperation<'a, I, O, IA, OA, IB, OB, A, B>
 where
     S: Fn(I) -> UnicastSelect<IA, IB> + Send + 'a,
     MA: Fn(OA) -> UnicastSelect<IB, O> + Send + 'a,
@@ -34,6 +118,7 @@ where
 {
     let sr1 = run_step(first);
     let sr2 = run_step(second);
+    let outside = exchanges.reset();
 
     match (sr1, sr2) {
         (StepResult::Done(value), StepResult::Done(remaining)) => {
@@ -41,22 +126,22 @@ where
                 value,
                 remaining: result(remaining),
             };
-            result(coop)
+            result(Ok(coop))
         }
         (StepResult::Done(value), StepResult::Yield { output, next }) => {
             let remaining = bind(send(output), |()| *next);
             let coop = CooperateResult::Left { value, remaining };
-            result(coop)
+            result(Ok(coop))
         }
         (StepResult::Done(value), StepResult::Next(next)) => {
             let remaining = suspend(next);
             let coop = CooperateResult::Left { value, remaining };
-            result(coop)
+            result(Ok(coop))
         }
         (StepResult::Yield { output, next }, StepResult::Done(value)) => {
             let remaining = bind(send(output), |()| *next);
             let coop = CooperateResult::Right { value, remaining };
-            result(coop)
+            result(Ok(coop))
         }
         (
             StepResult::Yield {
@@ -74,27 +159,38 @@ where
                 (UnicastSelect::Left(ib), UnicastSelect::Left(ia)) => {
                     let first = inject(ia, *na);
                     let second = inject(ib, *nb);
-                    cooperate(selector, map_first, map_second, first, second)
+                    match exchanges.exchange() {
+                        Some(exchanges) => {
+                            cooperating(selector, map_first, map_second, first, second, exchanges)
+                        }
+                        None => result(Err(Livelock { first, second })),
+                    }
                 }
                 (UnicastSelect::Left(ib), UnicastSelect::Right(o)) => {
                     let first = *na;
                     let second = inject(ib, *nb);
                     let output = send(o);
-                    let next = |()| cooperate(selector, map_first, map_second, first, second);
+                    let next = move |()| {
+                        cooperating(selector, map_first, map_second, first, second, outside)
+                    };
                     bind(output, next)
                 }
                 (UnicastSelect::Right(o), UnicastSelect::Left(ia)) => {
                     let first = inject(ia, *na);
                     let second = *nb;
                     let output = send(o);
-                    let next = |()| cooperate(selector, map_first, map_second, first, second);
+                    let next = move |()| {
+                        cooperating(selector, map_first, map_second, first, second, outside)
+                    };
                     bind(output, next)
                 }
                 (UnicastSelect::Right(o1), UnicastSelect::Right(o2)) => {
                     let first = *na;
                     let second = *nb;
                     let output = right(send(o1), send(o2));
-                    let next = |()| cooperate(selector, map_first, map_second, first, second);
+                    let next = move |()| {
+                        cooperating(selector, map_first, map_second, first, second, outside)
+                    };
                     bind(output, next)
                 }
             }
@@ -105,13 +201,20 @@ where
                 UnicastSelect::Left(ib) => {
                     let first = *next;
                     let second = input(ib);
-                    cooperate(selector, map_first, map_second, first, second)
+                    match exchanges.exchange() {
+                        Some(exchanges) => {
+                            cooperating(selector, map_first, map_second, first, second, exchanges)
+                        }
+                        None => result(Err(Livelock { first, second })),
+                    }
                 }
                 UnicastSelect::Right(o) => {
                     let output = send(o);
                     let first = *next;
                     let second = suspend(input);
-                    let next = |()| cooperate(selector, map_first, map_second, first, second);
+                    let next = move |()| {
+                        cooperating(selector, map_first, map_second, first, second, outside)
+                    };
                     bind(output, next)
                 }
             }
@@ -119,7 +222,7 @@ where
         (StepResult::Next(input), StepResult::Done(value)) => {
             let remaining = suspend(input);
             let coop = CooperateResult::Right { value, remaining };
-            result(coop)
+            result(Ok(coop))
         }
         (StepResult::Next(input), StepResult::Yield { output, next }) => {
             let output: UnicastSelect<IA, O> = map_second(output);
@@ -127,28 +230,35 @@ where
                 UnicastSelect::Left(ia) => {
                     let first = input(ia);
                     let second = *next;
-                    cooperate(selector, map_first, map_second, first, second)
+                    match exchanges.exchange() {
+                        Some(exchanges) => {
+                            cooperating(selector, map_first, map_second, first, second, exchanges)
+                        }
+                        None => result(Err(Livelock { first, second })),
+                    }
                 }
                 UnicastSelect::Right(o) => {
                     let first = suspend(input);
                     let second = *next;
-                    let next = |()| cooperate(selector, map_first, map_second, first, second);
+                    let next = move |()| {
+                        cooperating(selector, map_first, map_second, first, second, outside)
+                    };
                     let output = send(o);
                     bind(output, next)
                 }
             }
         }
         (StepResult::Next(input_a), StepResult::Next(input_b)) => {
-            let on_input = |input: I| match selector(input) {
+            let on_input = move |input: I| match selector(input) {
                 UnicastSelect::Left(ia) => {
                     let first = input_a(ia);
                     let second = suspend(input_b);
-                    cooperate(selector, map_first, map_second, first, second)
+                    cooperating(selector, map_first, map_second, first, second, outside)
                 }
                 UnicastSelect::Right(ib) => {
                     let first = suspend(input_a);
                     let second = input_b(ib);
-                    cooperate(selector, map_first, map_second, first, second)
+                    cooperating(selector, map_first, map_second, first, second, outside)
                 }
             };
             bind(receive(), on_input)
@@ -1,3 +1,5 @@
+use crate::machine::{Machine, Transition};
+
 /// A structure describing a co-routine supporting sends (inputs),
 /// yields (outputs), and a final termination (result)
 ///
@@ -107,6 +109,44 @@ where
     }
 }
 
+/// Takes a coutine outputting A, and makes it output B
+///
+/// This is a specialization of intercept_output, when we
+/// don't need to have any affects. Outputs are mapped as they
+/// are yielded, so they are never kept in a closure and don't need to be Send
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use std::rc::Rc;
+///
+/// let co: Coroutine<(), i32, ()> = send(1);
+/// let shared = map_output(co, Rc::new);
+///
+/// let outputs = as_iterator(shared, std::iter::empty()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![Rc::new(1)]);
+/// ```
+pub fn map_output<'a, Input, OutputA, OutputB, Result, MapFn>(
+    co: Coroutine<'a, Input, OutputA, Result>,
+    map_fn: MapFn,
+) -> Coroutine<'a, Input, OutputB, Result>
+where
+    MapFn: Fn(OutputA) -> OutputB + Send + 'a,
+{
+    let resume = match co.resume {
+        CoroutineState::Done(value) => CoroutineState::Done(value),
+        CoroutineState::Yield(output, next) => {
+            let output = map_fn(output);
+            let state = move || map_output(next(), map_fn);
+            CoroutineState::Yield(output, Box::new(state))
+        }
+        CoroutineState::Await(next) => {
+            let state = move |input| map_output(next(input), map_fn);
+            CoroutineState::Await(Box::new(state))
+        }
+    };
+    Coroutine { resume }
+}
+
 /// A step wise evalution of the coroutine
 ///
 /// this allows you to 'iterate' through until you need to provide input
@@ -120,7 +160,6 @@ where
 ///
 /// In the coroutine is finished, it will be in the done case, so the return
 /// value can be extracted
-
 pub enum StepResult<'a, Input, Output, Result> {
     /// The final value
     Done(Result),
@@ -154,3 +193,43 @@ pub fn run_step<I, O, R>(routine: Coroutine<I, O, R>) -> StepResult<I, O, R> {
         },
     }
 }
+
+/// Coroutines are machines too, so code written for machines can run them
+///
+/// Unlike a hand written machine, each step still allocates
+/// ```
+/// use bicoro::*;
+/// use bicoro::machine::*;
+///
+/// // the first output of any machine
+/// fn first<M: Machine>(machine: M) -> Option<M::Output> {
+///     match machine.step() {
+///         Transition::Yield(output, _) => Some(output),
+///         _ => None,
+///     }
+/// }
+///
+/// let co: Coroutine<(), i32, ()> = send(1);
+/// assert_eq!(first(co), Some(1));
+/// ```
+impl<'a, I, O, R> Machine for Coroutine<'a, I, O, R> {
+    type Input = I;
+    type Output = O;
+    type Result = R;
+
+    fn step(self) -> Transition<Self> {
+        match run_step(self) {
+            StepResult::Done(value) => Transition::Done(value),
+            StepResult::Yield { output, next } => Transition::Yield(output, *next),
+            StepResult::Next(next) => Transition::Await(suspend(next)),
+        }
+    }
+
+    fn receive(self, input: I) -> Self {
+        match self.resume {
+            CoroutineState::Await(next) => next(input),
+            // Only a waiting coroutine takes the input
+            resume => Coroutine { resume },
+        }
+    }
+}
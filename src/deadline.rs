@@ -0,0 +1,191 @@
+//! Deadlines and timeouts, measured in ticks
+//!
+//! An input can carry a deadline, the number of ticks the coroutine has to
+//! send an output in response, or a coroutine can be given a timeout for each
+//! wait. Ticks are inputs like any other, so the host decides how long a
+//! tick is, and tests can send them directly.
+use crate::{bind, receive, result, run_step, send, suspend, Coroutine, StepResult};
+
+/// An input for a coroutine with deadlines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeadlineInput<I> {
+    /// An input without a deadline, such as a reply to a request
+    Input(I),
+    /// An input that must be answered with an output within the ticks
+    Deadline(I, usize),
+    /// Time has passed
+    Tick,
+}
+
+/// An input wasn't answered before its deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineMissed;
+
+/// The outputs of a coroutine that warns about missed deadlines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeadlineOutput<O> {
+    /// The coroutine sent an output
+    Output(O),
+    /// An input wasn't answered before its deadline
+    Missed,
+}
+
+/// Fails the coroutine if an input isn't answered before its deadline
+///
+/// An input is answered by the next output the coroutine sends. If another
+/// deadline arrives before then, the sooner of the two is kept.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// // asks for a lookup, and answers once the reply arrives
+/// let co: Coroutine<i32, i32, ()> = receive().and_then(|_: i32| receive()).and_then(send);
+/// let co = with_deadlines(co);
+///
+/// let inputs = vec![DeadlineInput::Deadline(1, 2), DeadlineInput::Tick, DeadlineInput::Tick];
+/// let mut it = as_iterator(co, inputs.into_iter());
+/// it.by_ref().for_each(drop);
+/// assert!(matches!(it.finish(), (Ok(Err(DeadlineMissed)), _)));
+/// ```
+pub fn with_deadlines<'a, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+) -> Coroutine<'a, DeadlineInput<I>, O, Result<R, DeadlineMissed>>
+where
+    I: 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    track(co, None, |output| output, None)
+}
+
+/// Sends a warning if an input isn't answered before its deadline
+///
+/// Like [with_deadlines], but the coroutine carries on after a missed deadline.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let co: Coroutine<i32, i32, ()> = receive().and_then(|_: i32| receive()).and_then(send);
+/// let co = warn_on_deadlines(co);
+///
+/// let inputs = vec![DeadlineInput::Deadline(1, 1), DeadlineInput::Tick, DeadlineInput::Input(2)];
+/// let outputs = as_iterator(co, inputs.into_iter()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![DeadlineOutput::Missed, DeadlineOutput::Output(2)]);
+/// ```
+pub fn warn_on_deadlines<'a, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+) -> Coroutine<'a, DeadlineInput<I>, DeadlineOutput<O>, R>
+where
+    I: 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    let co = track(
+        co,
+        None,
+        DeadlineOutput::Output,
+        Some(|| DeadlineOutput::Missed),
+    );
+    bind(co, |value| match value {
+        Ok(value) => result(value),
+        Err(DeadlineMissed) => unreachable!("missed deadlines are only warned about"),
+    })
+}
+
+// Runs the coroutine, counting down the ticks left until it must send an output
+fn track<'a, I, O, T, R>(
+    co: Coroutine<'a, I, O, R>,
+    left: Option<usize>,
+    wrap: fn(O) -> T,
+    warning: Option<fn() -> T>,
+) -> Coroutine<'a, DeadlineInput<I>, T, Result<R, DeadlineMissed>>
+where
+    I: 'a,
+    O: Send + 'a,
+    T: Send + 'a,
+    R: Send + 'a,
+{
+    match run_step(co) {
+        StepResult::Done(value) => result(Ok(value)),
+        StepResult::Yield { output, next } => bind(send(wrap(output)), move |()| {
+            track(*next, None, wrap, warning)
+        }),
+        StepResult::Next(next) => bind(receive(), move |input| match input {
+            DeadlineInput::Input(input) => track(next(input), left, wrap, warning),
+            DeadlineInput::Deadline(input, ticks) => {
+                let left = Some(left.map_or(ticks, |left| left.min(ticks)));
+                track(next(input), left, wrap, warning)
+            }
+            DeadlineInput::Tick => match (left, warning) {
+                (Some(0..=1), None) => result(Err(DeadlineMissed)),
+                (Some(0..=1), Some(warning)) => bind(send(warning()), move |()| {
+                    track(suspend(next), None, wrap, Some(warning))
+                }),
+                (left, _) => track(suspend(next), left.map(|left| left - 1), wrap, warning),
+            },
+        }),
+    }
+}
+
+/// An input for a coroutine with a timeout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Timed<I> {
+    /// Time has passed
+    Tick,
+    /// An input for the coroutine
+    Input(I),
+}
+
+/// The coroutine waited too long for an input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedOut;
+
+/// Fails the coroutine if it waits for an input for too many ticks
+///
+/// The count starts again whenever the coroutine is given an input, so
+/// this limits each wait, not the whole run.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let co: Coroutine<i32, (), i32> = receive().and_then(|a: i32| receive().and_then(move |b: i32| result(a + b)));
+/// let co = with_timeout(co, 2);
+///
+/// let inputs = vec![Timed::Tick, Timed::Input(1), Timed::Tick, Timed::Tick];
+/// let mut it = as_iterator(co, inputs.into_iter());
+/// it.by_ref().for_each(drop);
+/// assert!(matches!(it.finish(), (Ok(Err(TimedOut)), _)));
+/// ```
+pub fn with_timeout<'a, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+    ticks: usize,
+) -> Coroutine<'a, Timed<I>, O, Result<R, TimedOut>>
+where
+    I: 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    fn waiting<'a, I, O, R>(
+        co: Coroutine<'a, I, O, R>,
+        ticks: usize,
+        left: usize,
+    ) -> Coroutine<'a, Timed<I>, O, Result<R, TimedOut>>
+    where
+        I: 'a,
+        O: Send + 'a,
+        R: Send + 'a,
+    {
+        match run_step(co) {
+            StepResult::Done(value) => result(Ok(value)),
+            StepResult::Yield { output, next } => {
+                bind(send(output), move |()| waiting(*next, ticks, left))
+            }
+            StepResult::Next(next) => bind(receive(), move |input| match input {
+                Timed::Input(input) => waiting(next(input), ticks, ticks),
+                Timed::Tick if left <= 1 => result(Err(TimedOut)),
+                Timed::Tick => waiting(suspend(next), ticks, left - 1),
+            }),
+        }
+    }
+    waiting(co, ticks, ticks)
+}
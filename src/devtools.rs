@@ -0,0 +1,173 @@
+//! Tools for stepping through a coroutine by hand
+//!
+//! Only compiled with the devtools feature.
+use std::{
+    collections::VecDeque,
+    fmt::{Debug, Display},
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    executor::{AlreadyFinished, Driver},
+    testing::{Exchange, Transcript},
+    Coroutine,
+};
+
+/// Whether the coroutine can take more inputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status<'r, R> {
+    /// Waiting for the next input
+    Awaiting,
+    /// Finished with the result
+    Finished(&'r R),
+}
+
+/// Runs a coroutine one input at a time, keeping everything that happened
+///
+/// Outputs are kept until they are taken, so they can be looked at
+/// between inputs.
+/// ```
+/// use bicoro::*;
+/// use bicoro::devtools::*;
+///
+/// let co: Coroutine<i32, i32, i32> = receive().and_then(|i: i32| send(i * 2).and_then(move |()| result(i)));
+/// let mut debugger = Debugger::new(co);
+///
+/// assert_eq!(debugger.status(), Status::Awaiting);
+/// debugger.feed(21).unwrap();
+/// assert_eq!(debugger.take_outputs(), vec![42]);
+/// assert_eq!(debugger.status(), Status::Finished(&21));
+/// ```
+pub struct Debugger<'a, I, O, R> {
+    driver: Driver<'a, I, O, R>,
+    pending: VecDeque<O>,
+    entries: Vec<Exchange<I, O>>,
+}
+
+impl<'a, I, O, R> Debugger<'a, I, O, R>
+where
+    I: Clone,
+    O: Clone,
+{
+    /// Loads the coroutine, running it until it first needs an input
+    pub fn new(co: Coroutine<'a, I, O, R>) -> Self {
+        let mut debugger = Debugger {
+            driver: Driver::new(co),
+            pending: VecDeque::new(),
+            entries: Vec::new(),
+        };
+        let outputs = debugger.driver.poll();
+        debugger.keep(outputs);
+        debugger
+    }
+
+    /// Gives the coroutine an input, running it until it needs another
+    pub fn feed(&mut self, input: I) -> Result<(), AlreadyFinished<I, O>> {
+        let outputs = self.driver.feed(input.clone())?;
+        self.entries.push(Exchange::Input(input));
+        self.keep(outputs);
+        Ok(())
+    }
+
+    /// The outputs that have not been taken yet
+    pub fn pending_outputs(&self) -> impl Iterator<Item = &O> {
+        self.pending.iter()
+    }
+
+    /// Takes the outputs that have not been taken yet
+    pub fn take_outputs(&mut self) -> Vec<O> {
+        self.pending.drain(..).collect()
+    }
+
+    /// Whether the coroutine is waiting or finished
+    pub fn status(&self) -> Status<'_, R> {
+        match self.driver.result() {
+            Some(value) => Status::Finished(value),
+            None => Status::Awaiting,
+        }
+    }
+
+    /// Every input and output so far
+    pub fn transcript(&self) -> Transcript<I, O> {
+        Transcript::from(self.entries.clone())
+    }
+
+    fn keep(&mut self, outputs: Vec<O>) {
+        for output in outputs {
+            self.entries.push(Exchange::Output(output.clone()));
+            self.pending.push_back(output);
+        }
+    }
+}
+
+const HELP: &str = "commands: feed <input>, outputs, status, transcript, help, quit";
+
+/// Runs a prompt for debugging the coroutine
+///
+/// Commands are read a line at a time, with parse turning
+/// the text after feed into an input. Returns once quit is
+/// entered or the commands run out.
+/// ```
+/// use bicoro::*;
+/// use bicoro::devtools::*;
+///
+/// let co: Coroutine<i32, i32, ()> = receive().and_then(|i: i32| send(i + 1));
+/// let commands = "feed 1\noutputs\nstatus\n";
+/// let mut screen = Vec::new();
+///
+/// let parse = |text: &str| text.parse::<i32>().map_err(|e| e.to_string());
+/// repl(Debugger::new(co), parse, commands.as_bytes(), &mut screen).unwrap();
+/// let screen = String::from_utf8(screen).unwrap();
+/// assert!(screen.contains("> 2\n"));
+/// assert!(screen.contains("finished with ()"));
+/// ```
+pub fn repl<'a, I, O, R, P, B, W>(
+    mut debugger: Debugger<'a, I, O, R>,
+    mut parse: P,
+    commands: B,
+    mut screen: W,
+) -> io::Result<Debugger<'a, I, O, R>>
+where
+    I: Clone + Display,
+    O: Clone + Display,
+    R: Debug,
+    P: FnMut(&str) -> Result<I, String>,
+    B: BufRead,
+    W: Write,
+{
+    writeln!(screen, "{}", HELP)?;
+    write!(screen, "> ")?;
+    screen.flush()?;
+    for line in commands.lines() {
+        let line = line?;
+        let (command, argument) = match line.trim().split_once(' ') {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line.trim(), ""),
+        };
+        match command {
+            "" => {}
+            "feed" => match parse(argument) {
+                Ok(input) => match debugger.feed(input) {
+                    Ok(()) => writeln!(screen, "{} outputs pending", debugger.pending.len())?,
+                    Err(_) => writeln!(screen, "already finished")?,
+                },
+                Err(error) => writeln!(screen, "invalid input: {}", error)?,
+            },
+            "outputs" => {
+                for output in debugger.take_outputs() {
+                    writeln!(screen, "{}", output)?;
+                }
+            }
+            "status" => match debugger.status() {
+                Status::Awaiting => writeln!(screen, "awaiting input")?,
+                Status::Finished(value) => writeln!(screen, "finished with {:?}", value)?,
+            },
+            "transcript" => write!(screen, "{}", debugger.transcript())?,
+            "quit" => break,
+            _ => writeln!(screen, "{}", HELP)?,
+        }
+        write!(screen, "> ")?;
+        screen.flush()?;
+    }
+    Ok(debugger)
+}
@@ -1,6 +1,8 @@
+use std::collections::VecDeque;
+
 use crate::{
-    bind, intercept_input, map, map_input, map_output, receive, recieve_until, result, right,
-    run_step, send, subroutine, suspend, tuple, Coroutine, StepResult,
+    bind, intercept_input, map, map_input, map_output, receive, receive_until, result, right,
+    run_step, send, slot::Slot, subroutine, suspend, tuple, Coroutine, Received, StepResult,
 };
 
 /// A selection for which coroutine to route to
@@ -176,6 +178,60 @@ where
     bind(rr, on_result)
 }
 
+/// The result of a race, along with the loser
+pub type RaceRoutine<'a, I, O, A, B> = Coroutine<'a, I, O, DispatchResult<'a, I, I, O, O, A, B>>;
+
+/// Sends inputs to both coroutines, finishing as soon as either does
+///
+/// The loser is returned in the result, so it can be finished
+/// or inspected. If both finish on the same step, first wins.
+/// See race if the loser isn't needed.
+pub fn race_with_loser<'a, I, O, A, B>(
+    first: Coroutine<'a, I, O, A>,
+    second: Coroutine<'a, I, O, B>,
+) -> RaceRoutine<'a, I, O, A, B>
+where
+    I: Clone,
+    A: Send,
+    B: Send,
+    O: Send,
+{
+    map_output(broadcast(first, second), |output| match output {
+        UnicastSelect::Left(o) => o,
+        UnicastSelect::Right(o) => o,
+    })
+}
+
+/// Sends inputs to both coroutines, returning the result of whichever finishes first
+///
+/// The loser is dropped. If both finish on the same step, first wins.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// // the first needs two inputs, the second only one
+/// let slow: Coroutine<i32, (), i32> = receive().and_then(|_: i32| receive());
+/// let fast: Coroutine<i32, (), i32> = map(receive(), |i: i32| i * 10);
+///
+/// let mut it = as_iterator(race(slow, fast), vec![1, 2].into_iter());
+/// assert!(it.next().is_none());
+/// assert!(matches!(it.finish(), (Ok(10), _)));
+/// ```
+pub fn race<'a, I, O, R>(
+    first: Coroutine<'a, I, O, R>,
+    second: Coroutine<'a, I, O, R>,
+) -> Coroutine<'a, I, O, R>
+where
+    I: Clone,
+    R: Send,
+    O: Send,
+{
+    map(race_with_loser(first, second), |winner| match winner {
+        DispatchResult::Left { value, .. } => value,
+        DispatchResult::Right { value, .. } => value,
+    })
+}
+
 /// A more specific version of select, where messages are exclusive
 ///
 /// This allows you to not have to deal with A or B being cloneable
@@ -184,6 +240,12 @@ pub enum UnicastSelect<A, B> {
     Right(B),
 }
 
+pub type UnicastRoutine<'a, IA, IB, OA, OB, A, B> = Coroutine<
+    'a,
+    UnicastSelect<IA, IB>,
+    UnicastSelect<OA, OB>,
+    DispatchResult<'a, IA, IB, OA, OB, A, B>,
+>;
 /// Run two co-routines, sharing inputs depending on selector.
 ///
 /// This can be thought of as running them almost in parralel.
@@ -193,12 +255,7 @@ pub enum UnicastSelect<A, B> {
 pub fn unicast<'a, IA, IB, OA, OB, A, B>(
     first: Coroutine<'a, IA, OA, A>,
     second: Coroutine<'a, IB, OB, B>,
-) -> Coroutine<
-    'a,
-    UnicastSelect<IA, IB>,
-    UnicastSelect<OA, OB>,
-    DispatchResult<'a, IA, IB, OA, OB, A, B>,
->
+) -> UnicastRoutine<'a, IA, IB, OA, OB, A, B>
 where
     OA: Send,
     OB: Send,
@@ -246,6 +303,33 @@ where
     map(both, extract)
 }
 
+// The value of an unbounded receive_until, which only stops with one
+pub(crate) fn until_value<R>(received: Received<R>) -> R {
+    received
+        .value
+        .expect("unbounded receives only stop with a value")
+}
+
+// Runs the coroutine on the inputs pick keeps, throwing the rest away
+pub(crate) fn only<'a, In, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+    pick: fn(In) -> Option<I>,
+) -> Coroutine<'a, In, O, R>
+where
+    In: 'a,
+    I: 'a,
+    O: Send,
+    R: Send,
+{
+    intercept_input(co, move |input| match pick(input) {
+        Some(input) => result(input),
+        None => map(
+            receive_until(None, move |input| result(pick(input))),
+            until_value,
+        ),
+    })
+}
+
 /// Unicast until both routines are completed
 ///
 /// Unlike broadcast, there is an issue with completing unicast routines
@@ -270,8 +354,9 @@ where
     };
 
     // These ultimately throw away inputs that aren't for them
-    let on_ib_input = move || recieve_until(is_ib);
-    let on_ia_input = move || recieve_until(is_ia);
+    // Without a bound, they only stop once they have a value
+    let on_ib_input = move || map(receive_until(None, is_ib), until_value);
+    let on_ia_input = move || map(receive_until(None, is_ia), until_value);
 
     // This will finish-of, the 'loser' coroutine. Thus getting the values tupled together
     let on_result = move |r| match r {
@@ -291,3 +376,173 @@ where
     });
     bind(ur, on_result)
 }
+
+/// Runs two coroutines in lockstep, pairing their inputs and outputs
+///
+/// Each input is a pair, with one half for each coroutine. Each coroutine
+/// is stepped until it has an output, and outputs are only sent once both
+/// have one, so neither can run ahead of the other. Inputs are only asked
+/// for once neither can go further without one. A coroutine holding an output
+/// keeps its halves of inputs until it asks for them. Unlike dispatch,
+/// this keeps the two in sync. Once one has finished, its half of each input
+/// is ignored, and outputs the other sends without a partner are dropped.
+/// Each input only runs the other on to its next output, so one that
+/// never waits for input can't stop the zip from waiting.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let double: Coroutine<i32, i32, ()> = receive().and_then(|i: i32| send(i * 2));
+/// let shout: Coroutine<&str, String, ()> = receive().and_then(|s: &str| send(s.to_uppercase()));
+///
+/// let zipped = zip(double, shout);
+/// let outputs = as_iterator(zipped, vec![(1, "hi")].into_iter()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![(2, "HI".to_string())]);
+/// ```
+pub fn zip<'a, IA, IB, OA, OB, RA, RB>(
+    first: Coroutine<'a, IA, OA, RA>,
+    second: Coroutine<'a, IB, OB, RB>,
+) -> Coroutine<'a, (IA, IB), (OA, OB), (RA, RB)>
+where
+    IA: Send + 'a,
+    IB: Send + 'a,
+    OA: Send + 'a,
+    OB: Send + 'a,
+    RA: Send + 'a,
+    RB: Send + 'a,
+{
+    zip_sides(ZipSide::new(first), ZipSide::new(second))
+}
+
+/// One of the zipped coroutines, with its output waiting for a partner
+struct ZipSide<'a, I, O, R> {
+    slot: Slot<'a, I, O, R>,
+    output: Option<O>,
+    inputs: VecDeque<I>,
+}
+
+impl<'a, I, O, R> ZipSide<'a, I, O, R> {
+    fn new(co: Coroutine<'a, I, O, R>) -> Self {
+        ZipSide {
+            slot: Slot::Running(co),
+            output: None,
+            inputs: VecDeque::new(),
+        }
+    }
+
+    /// Steps until there is an output, or it needs an input it doesn't have
+    fn advance(mut self) -> Self {
+        while self.output.is_none() {
+            match self.slot {
+                Slot::Running(_) => {
+                    let (slot, output) = self.slot.step();
+                    self.slot = slot;
+                    self.output = output;
+                }
+                Slot::Awaiting(_) => match self.inputs.pop_front() {
+                    Some(input) => self.slot = self.slot.resume(input).0,
+                    None => break,
+                },
+                Slot::Finished(_) => break,
+            }
+        }
+        self
+    }
+
+    fn offer(&mut self, input: I) {
+        if !self.slot.is_finished() {
+            self.inputs.push_back(input);
+        }
+    }
+}
+
+fn zip_sides<'a, IA, IB, OA, OB, RA, RB>(
+    mut first: ZipSide<'a, IA, OA, RA>,
+    mut second: ZipSide<'a, IB, OB, RB>,
+) -> Coroutine<'a, (IA, IB), (OA, OB), (RA, RB)>
+where
+    IA: Send + 'a,
+    IB: Send + 'a,
+    OA: Send + 'a,
+    OB: Send + 'a,
+    RA: Send + 'a,
+    RB: Send + 'a,
+{
+    loop {
+        let queued = (first.inputs.len(), second.inputs.len());
+        first = first.advance();
+        second = second.advance();
+        match (first.output.take(), second.output.take()) {
+            (Some(a), Some(b)) => return bind(send((a, b)), move |()| zip_sides(first, second)),
+            (a, b) => {
+                first.output = a;
+                second.output = b;
+            }
+        }
+        match (first.slot, second.slot) {
+            (Slot::Finished(a), Slot::Finished(b)) => return result((a, b)),
+            (first_slot, second_slot) => {
+                first.slot = first_slot;
+                second.slot = second_slot;
+            }
+        }
+        // An output without a partner to pair with is dropped. The other
+        // side only runs on while it uses up queued inputs, so one that
+        // sends without ever waiting can't stop the zip from suspending
+        let dropped = match (first.slot.is_finished(), second.slot.is_finished()) {
+            (true, false) => second.output.take().is_some(),
+            (false, true) => first.output.take().is_some(),
+            _ => false,
+        };
+        let fed = queued != (first.inputs.len(), second.inputs.len());
+        if !dropped || !fed {
+            break;
+        }
+    }
+    suspend(move |(a, b)| {
+        first.offer(a);
+        second.offer(b);
+        zip_sides(first, second)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{forever, iterator::as_iterator, Never};
+
+    type Co<I, O, R> = Coroutine<'static, I, O, R>;
+
+    #[test]
+    fn producers_are_zipped_in_lockstep() {
+        let ones: Co<(), i32, Never> = forever(|| send(1));
+        let twos: Co<(), i32, Never> = forever(|| send(2));
+
+        let outputs = as_iterator(zip(ones, twos), std::iter::empty())
+            .take(3)
+            .collect::<Vec<_>>();
+        assert_eq!(outputs, vec![(1, 2), (1, 2), (1, 2)]);
+    }
+
+    #[test]
+    fn a_side_holding_an_output_keeps_its_inputs() {
+        // sends before it reads, where the other reads before it sends
+        let eager: Co<i32, i32, i32> = send(0).and_then(|()| receive());
+        let lazy: Co<i32, i32, ()> = receive().and_then(send);
+
+        let mut it = as_iterator(zip(eager, lazy), vec![(7, 1)].into_iter());
+        assert_eq!(it.by_ref().collect::<Vec<_>>(), vec![(0, 1)]);
+        assert!(matches!(it.finish(), (Ok((7, ())), _)));
+    }
+
+    #[test]
+    fn a_finished_side_does_not_spin_on_a_producer() {
+        let done: Co<(), i32, ()> = result(());
+        let ones: Co<(), i32, Never> = forever(|| send(1));
+
+        let inputs = std::iter::repeat_n(((), ()), 3);
+        let mut it = as_iterator(zip(done, ones), inputs);
+        assert!(it.next().is_none());
+        assert!(matches!(it.finish(), (Err(_), _)));
+    }
+}
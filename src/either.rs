@@ -0,0 +1,79 @@
+//! This module contains functions that provide easier
+//! to use workflows for the co-routine if R is Either<A, B>
+//! This is handy for protocols that branch, where each branch finishes differently.
+use super::*;
+
+/// One of two values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+/// Process the Left value, or keep the Right value
+///
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// // the left branch needs another input, the right branch is done
+/// let branch: Coroutine<i32, (), Either<i32, &str>> =
+///     receive().and_then(|i: i32| result(if i > 0 { Either::Left(i) } else { Either::Right("stopped") }));
+/// let co = bind_left(branch, |a: i32| receive().and_then(move |b: i32| result(Either::Left(a + b))));
+///
+/// let mut it = as_iterator(co, vec![1, 2].into_iter());
+/// it.by_ref().for_each(drop);
+/// assert!(matches!(it.finish(), (Ok(Either::Left(3)), _)));
+/// ```
+pub fn bind_left<'a, I, O, A, B, C, F>(
+    co: Coroutine<'a, I, O, Either<A, B>>,
+    f: F,
+) -> Coroutine<'a, I, O, Either<C, B>>
+where
+    F: FnOnce(A) -> Coroutine<'a, I, O, Either<C, B>> + Send + 'a,
+{
+    bind(co, |r| match r {
+        Either::Left(a) => f(a),
+        Either::Right(b) => result(Either::Right(b)),
+    })
+}
+
+/// Process the Right value, or keep the Left value
+pub fn bind_right<'a, I, O, A, B, C, F>(
+    co: Coroutine<'a, I, O, Either<A, B>>,
+    f: F,
+) -> Coroutine<'a, I, O, Either<A, C>>
+where
+    F: FnOnce(B) -> Coroutine<'a, I, O, Either<A, C>> + Send + 'a,
+{
+    bind(co, |r| match r {
+        Either::Left(a) => result(Either::Left(a)),
+        Either::Right(b) => f(b),
+    })
+}
+
+/// Maps the Left value
+///
+/// Sugar over bind_left
+pub fn map_left<'a, I, O, A, B, C, F>(
+    co: Coroutine<'a, I, O, Either<A, B>>,
+    f: F,
+) -> Coroutine<'a, I, O, Either<C, B>>
+where
+    F: FnOnce(A) -> C + Send + 'a,
+{
+    bind_left(co, |a| result(Either::Left(f(a))))
+}
+
+/// Maps the Right value
+///
+/// Sugar over bind_right
+pub fn map_right<'a, I, O, A, B, C, F>(
+    co: Coroutine<'a, I, O, Either<A, B>>,
+    f: F,
+) -> Coroutine<'a, I, O, Either<A, C>>
+where
+    F: FnOnce(B) -> C + Send + 'a,
+{
+    bind_right(co, |b| result(Either::Right(f(b))))
+}
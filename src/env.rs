@@ -0,0 +1,143 @@
+//! Coroutines that can ask for a shared environment
+//!
+//! EnvCoroutine gives a coroutine access to a value, such as configuration,
+//! that is provided once when it is run with with_env. This saves passing the
+//! value through every function and closure that might need it.
+//! ```
+//! use bicoro::*;
+//! use bicoro::env::*;
+//! use bicoro::iterator::*;
+//!
+//! struct Config {
+//!     greeting: &'static str,
+//! }
+//!
+//! // greets each name it receives, using the configured greeting
+//! fn greet<'a>() -> EnvCoroutine<'a, &'static Config, &'static str, String, ()> {
+//!     lift(receive()).and_then(|name| {
+//!         ask().and_then(move |config: &Config| lift(send(format!("{} {}", config.greeting, name))))
+//!     })
+//! }
+//!
+//! static CONFIG: Config = Config { greeting: "hello" };
+//! let co = with_env(&CONFIG, greet());
+//! let outputs = as_iterator(co, vec!["alice"].into_iter()).collect::<Vec<_>>();
+//! assert_eq!(outputs, vec!["hello alice"]);
+//! ```
+use crate::{bind, map, result, Coroutine};
+
+type Reader<'a, E, I, O, R> = Box<dyn FnOnce(E) -> Coroutine<'a, I, O, R> + Send + 'a>;
+
+/// A coroutine that produces R, and can ask for an environment E
+///
+/// The environment is cloned for each part that asks for it,
+/// so it is usually a reference or an Arc.
+pub struct EnvCoroutine<'a, E, I, O, R> {
+    run: Reader<'a, E, I, O, R>,
+}
+
+impl<'a, E, I, O, R> EnvCoroutine<'a, E, I, O, R> {
+    /// Wraps a function from the environment to a coroutine
+    pub fn new<F>(f: F) -> Self
+    where
+        F: FnOnce(E) -> Coroutine<'a, I, O, R> + Send + 'a,
+    {
+        EnvCoroutine { run: Box::new(f) }
+    }
+
+    /// Continue with the value, in the same environment
+    pub fn and_then<F, B>(self, f: F) -> EnvCoroutine<'a, E, I, O, B>
+    where
+        F: FnOnce(R) -> EnvCoroutine<'a, E, I, O, B> + Send + 'a,
+        E: Clone + Send + 'a,
+        I: 'a,
+        O: 'a,
+        R: 'a,
+    {
+        EnvCoroutine::new(move |env: E| {
+            let first = (self.run)(env.clone());
+            bind(first, move |value| (f(value).run)(env))
+        })
+    }
+
+    /// Maps the value
+    pub fn map<F, B>(self, f: F) -> EnvCoroutine<'a, E, I, O, B>
+    where
+        F: FnOnce(R) -> B + Send + 'a,
+        E: 'a,
+        I: 'a,
+        O: 'a,
+        R: 'a,
+    {
+        EnvCoroutine::new(move |env| map((self.run)(env), f))
+    }
+}
+
+impl<'a, E, I, O, R> ::do_notation::Lift<R> for EnvCoroutine<'a, E, I, O, R>
+where
+    R: Send + 'a,
+{
+    /// Creates a coroutine that returns the value, without asking
+    ///
+    /// see [value](function@value)
+    fn lift(a: R) -> Self {
+        value(a)
+    }
+}
+
+/// Runs the coroutine, giving it the environment whenever it asks
+pub fn with_env<'a, E, I, O, R>(
+    env: E,
+    co: EnvCoroutine<'a, E, I, O, R>,
+) -> Coroutine<'a, I, O, R> {
+    (co.run)(env)
+}
+
+/// A coroutine that returns the value, without asking
+/// ```
+/// use bicoro::env::*;
+/// let co: EnvCoroutine<&str, (), (), i32> = value(1);
+/// ```
+pub fn value<'a, E, I, O, R>(value: R) -> EnvCoroutine<'a, E, I, O, R>
+where
+    R: Send + 'a,
+{
+    EnvCoroutine::new(move |_| result(value))
+}
+
+/// Lifts a coroutine that doesn't need the environment
+/// ```
+/// use bicoro::*;
+/// use bicoro::env::*;
+/// let co: EnvCoroutine<&str, i32, (), i32> = lift(receive());
+/// ```
+pub fn lift<'a, E, I, O, R>(co: Coroutine<'a, I, O, R>) -> EnvCoroutine<'a, E, I, O, R>
+where
+    O: Send + 'a,
+    R: Send + 'a,
+    I: 'a,
+{
+    EnvCoroutine::new(move |_| co)
+}
+
+/// Returns the environment
+///
+/// Also works with do-notation
+/// ```
+/// use bicoro::*;
+/// use bicoro::env::*;
+/// use bicoro::iterator::*;
+/// use ::do_notation::m;
+///
+/// let co: EnvCoroutine<i32, i32, i32, ()> = m! {
+///     input <- lift(receive());
+///     offset <- ask();
+///     lift(send(input + offset))
+/// };
+///
+/// let outputs = as_iterator(with_env(10, co), vec![1].into_iter()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![11]);
+/// ```
+pub fn ask<'a, E, I, O>() -> EnvCoroutine<'a, E, I, O, E> {
+    EnvCoroutine::new(result)
+}
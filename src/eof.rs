@@ -0,0 +1,89 @@
+//! Running coroutines where the end of input is an input too
+//!
+//! An executor that runs out of inputs just stops, so the coroutine
+//! can't tell "no more input ever" apart from "none yet". Here inputs
+//! are Options, and None says the input has ended, such as at the end
+//! of a file. Parsers can then finish with whatever they have.
+use crate::{bind, receive, result, run_step, send, suspend, Coroutine, StepResult};
+
+/// The input ended while the coroutine was still waiting for more
+pub struct Incomplete<'a, I, O, R> {
+    /// The coroutine, waiting for the input that never came
+    pub remaining: Coroutine<'a, I, O, R>,
+}
+
+pub type EofRoutine<'a, I, O, R> = Coroutine<'a, Option<I>, O, Result<R, Incomplete<'a, I, O, R>>>;
+
+/// Runs a coroutine that doesn't know about the end of input
+///
+/// If the input ends while it is waiting, it fails with the coroutine
+/// so the caller can see what it was doing.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let pair: Coroutine<i32, (), (i32, i32)> = receive().and_then(|a| receive().and_then(move |b| result((a, b))));
+///
+/// let mut it = as_iterator(with_eof(pair), eof_terminated(vec![1]));
+/// it.by_ref().for_each(drop);
+/// assert!(matches!(it.finish(), (Ok(Err(Incomplete { .. })), _)));
+/// ```
+pub fn with_eof<'a, I, O, R>(co: Coroutine<'a, I, O, R>) -> EofRoutine<'a, I, O, R>
+where
+    I: 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    match run_step(co) {
+        StepResult::Done(value) => result(Ok(value)),
+        StepResult::Yield { output, next } => bind(send(output), move |()| with_eof(*next)),
+        StepResult::Next(next) => bind(receive(), move |input| match input {
+            Some(input) => with_eof(next(input)),
+            None => result(Err(Incomplete {
+                remaining: suspend(next),
+            })),
+        }),
+    }
+}
+
+/// Receives the next input, or None if the input has ended
+pub fn receive_or_eof<'a, I, O>() -> Coroutine<'a, Option<I>, O, Option<I>> {
+    receive()
+}
+
+/// Receives every input until the input ends
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let co: Coroutine<Option<i32>, (), Vec<i32>> = receive_until_eof();
+/// let mut it = as_iterator(co, eof_terminated(vec![1, 2]));
+/// it.by_ref().for_each(drop);
+/// assert!(matches!(it.finish(), (Ok(inputs), _) if inputs == vec![1, 2]));
+/// ```
+pub fn receive_until_eof<'a, I, O>() -> Coroutine<'a, Option<I>, O, Vec<I>>
+where
+    I: Send + 'a,
+{
+    fn collecting<'a, I, O>(mut inputs: Vec<I>) -> Coroutine<'a, Option<I>, O, Vec<I>>
+    where
+        I: Send + 'a,
+    {
+        bind(receive_or_eof(), move |input| match input {
+            Some(input) => {
+                inputs.push(input);
+                collecting(inputs)
+            }
+            None => result(inputs),
+        })
+    }
+    collecting(Vec::new())
+}
+
+/// The inputs as Somes, followed by a None to say they have ended
+pub fn eof_terminated<It>(inputs: It) -> impl Iterator<Item = Option<It::Item>>
+where
+    It: IntoIterator,
+{
+    inputs.into_iter().map(Some).chain(std::iter::once(None))
+}
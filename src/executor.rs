@@ -3,9 +3,9 @@
 //! It's not necessary to use this, as run_step is all you need if rolling your own
 //! but it's a good reference, and is fairly generally useable
 
-use std::fmt::Debug;
+use std::{collections::VecDeque, convert::Infallible, fmt::Debug};
 
-use crate::*;
+use crate::{slot::Slot, *};
 
 pub enum IteratorExecutorResult<'a, It, Input, Output, Result> {
     /// The coroutine has finished
@@ -106,6 +106,1264 @@ where
     }
 }
 
+/// The coroutine used up its steps before sending an output or finishing
+pub struct OutOfFuel<'a, It, I, O, R> {
+    /// The coroutine, which can be run again with more fuel
+    pub co: Coroutine<'a, I, O, R>,
+    /// The inputs that haven't been used yet
+    pub remaining: It,
+}
+
+/// What run_with_fuel returns, an error if it ran out of steps
+pub type FuelResult<'a, It, I, O, R> =
+    Result<IteratorExecutorResult<'a, It, I, O, R>, OutOfFuel<'a, It, I, O, R>>;
+
+/// Like run_until_output, but gives up after max_steps steps
+///
+/// This protects the host from a coroutine that keeps stepping without
+/// ever sending an output, such as one that ignores every input.
+/// Fuel is only counted between steps, so it can't stop routed coroutines
+/// that message each other forever within one step; routed_with_budget
+/// guards against that. Running out of fuel can also be used to share time between coroutines.
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+///
+/// // keeps reading inputs, without ever sending anything
+/// fn ignore(count: i32) -> Coroutine<'static, i32, i32, ()> {
+///     receive().and_then(move |_: i32| ignore(count + 1))
+/// }
+///
+/// let exec = run_with_fuel(ignore(0), 0.., 10);
+/// assert!(matches!(exec, Err(OutOfFuel { .. })));
+/// ```
+pub fn run_with_fuel<'a, It, I, O, R>(
+    mut co: Coroutine<'a, I, O, R>,
+    mut inputs: It,
+    max_steps: usize,
+) -> FuelResult<'a, It, I, O, R>
+where
+    It: Iterator<Item = I>,
+{
+    for _ in 0..max_steps {
+        match run_step(co) {
+            StepResult::Done(result) => {
+                return Ok(IteratorExecutorResult::Completed {
+                    result,
+                    remaining: inputs,
+                })
+            }
+            StepResult::Yield { output, next } => {
+                return Ok(IteratorExecutorResult::Output {
+                    output,
+                    remaining: inputs,
+                    co: *next,
+                })
+            }
+            StepResult::Next(next) => match inputs.next() {
+                Some(input) => co = next(input),
+                None => return Ok(IteratorExecutorResult::Exhausted { co: next }),
+            },
+        }
+    }
+    Err(OutOfFuel {
+        co,
+        remaining: inputs,
+    })
+}
+
+/// How running a coroutine ended, whichever executor ran it
+///
+/// Executors stop for different reasons, but they all either finished,
+/// paused with something that can be continued, or failed. Converting their
+/// results into this lets the code matching on them stay the same if the
+/// executor is swapped for another.
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+/// use bicoro::iterator::*;
+/// use std::convert::Infallible;
+///
+/// let co: Coroutine<i32, i32, i32> = receive().and_then(|a: i32| receive().and_then(move |b: i32| result(a + b)));
+///
+/// // the iterator runs out of inputs part way through
+/// let mut it = as_iterator(co, vec![1].into_iter());
+/// it.by_ref().for_each(drop);
+/// let outcome: RunOutcome<i32, Coroutine<i32, i32, i32>, Infallible> = it.finish().0.into();
+/// let paused = match outcome {
+///     RunOutcome::Paused(paused) => paused,
+///     _ => panic!("should have paused"),
+/// };
+///
+/// // the same matching works for the lower level executor
+/// let outcome: RunOutcome<_, _, Infallible> = run_until_output(paused, vec![2].into_iter()).into();
+/// assert_eq!(outcome.completed(), Some(3));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunOutcome<R, P, E> {
+    /// The coroutine finished with a result
+    Completed(R),
+    /// The executor stopped early, with what is needed to continue
+    Paused(P),
+    /// The executor failed
+    Failed(E),
+}
+
+impl<R, P, E> RunOutcome<R, P, E> {
+    /// Whether the coroutine finished
+    pub fn is_completed(&self) -> bool {
+        matches!(self, RunOutcome::Completed(_))
+    }
+
+    /// The result, if the coroutine finished
+    pub fn completed(self) -> Option<R> {
+        match self {
+            RunOutcome::Completed(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Changes what is kept when paused
+    pub fn map_paused<Q, F>(self, f: F) -> RunOutcome<R, Q, E>
+    where
+        F: FnOnce(P) -> Q,
+    {
+        match self {
+            RunOutcome::Completed(value) => RunOutcome::Completed(value),
+            RunOutcome::Paused(paused) => RunOutcome::Paused(f(paused)),
+            RunOutcome::Failed(error) => RunOutcome::Failed(error),
+        }
+    }
+}
+
+/// Executors that return the rest of the coroutine if they stop early,
+/// such as iterator::CoroutineIterator::finish and Driver::into_result
+impl<R, P, E> From<Result<R, P>> for RunOutcome<R, P, E> {
+    fn from(value: Result<R, P>) -> Self {
+        match value {
+            Ok(value) => RunOutcome::Completed(value),
+            Err(paused) => RunOutcome::Paused(paused),
+        }
+    }
+}
+
+/// An output is treated as a pause, and is sent first when the coroutine is continued.
+/// The remaining inputs are dropped, so only convert once they are not needed.
+impl<'a, It, I, O, R, E> From<IteratorExecutorResult<'a, It, I, O, R>>
+    for RunOutcome<R, Coroutine<'a, I, O, R>, E>
+where
+    O: Send,
+    R: Send,
+{
+    fn from(value: IteratorExecutorResult<'a, It, I, O, R>) -> Self {
+        match value {
+            IteratorExecutorResult::Completed { result, .. } => RunOutcome::Completed(result),
+            IteratorExecutorResult::Output { output, co, .. } => {
+                RunOutcome::Paused(right(send(output), co))
+            }
+            IteratorExecutorResult::Exhausted { co } => RunOutcome::Paused(suspend(co)),
+        }
+    }
+}
+
+/// Runs a coroutine that can push back inputs, until it outputs
+///
+/// This runs it with [with_pushback](function@crate::with_pushback), so
+/// leftovers are given to the following receives, and any the coroutine
+/// didn't use are returned alongside its result. They are never put back
+/// onto the remaining inputs.
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+///
+/// // reads an input, but gives it back
+/// let co: Coroutine<i32, Pushback<i32, ()>, ()> = receive().and_then(push_back);
+///
+/// let exec = run_until_output_with_pushback(co, vec![1, 2].into_iter());
+///
+/// match exec {
+///     IteratorExecutorResult::Completed { result: ((), leftovers), mut remaining } => {
+///         assert_eq!(leftovers, vec![1]);
+///         assert_eq!(remaining.next(), Some(2));
+///     }
+///     _ => panic!("should have completed"),
+/// }
+/// ```
+pub fn run_until_output_with_pushback<'a, Iter, Input, Output, Result>(
+    routine: Coroutine<'a, Input, Pushback<Input, Output>, Result>,
+    events: Iter,
+) -> IteratorExecutorResult<'a, Iter, Input, Output, (Result, Vec<Input>)>
+where
+    Iter: Iterator<Item = Input>,
+    Input: Send + 'a,
+    Output: Send,
+    Result: Send,
+{
+    run_until_output(with_pushback(routine), events)
+}
+
+/// The outputs sent before a coroutine waited, and its result or the waiting coroutine
+pub type UntilInput<'a, I, O, R> = (Vec<O>, Result<R, Coroutine<'a, I, O, R>>);
+
+/// Runs the coroutine until it waits for input or finishes, collecting the outputs
+///
+/// Handy for request and response protocols, where everything the
+/// coroutine has to say is sent before fetching the next input.
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+///
+/// let co: Coroutine<i32, &str, i32> = send("hello").and_then(|()| send("name?")).and_then(|()| receive());
+///
+/// let (outputs, waiting) = run_until_input(co);
+/// assert_eq!(outputs, vec!["hello", "name?"]);
+/// assert!(waiting.is_err());
+/// ```
+pub fn run_until_input<'a, I, O, R>(co: Coroutine<'a, I, O, R>) -> UntilInput<'a, I, O, R> {
+    let drained = run_to_end(co, std::iter::empty());
+    let outcome = match drained.outcome {
+        RunOutcome::Completed(value) => Ok(value),
+        RunOutcome::Paused(co) => Err(co),
+        RunOutcome::Failed(never) => match never {},
+    };
+    (drained.outputs, outcome)
+}
+
+/// Runs the coroutine to completion, with callbacks for its inputs and outputs
+///
+/// on_input is only called when the coroutine is waiting for an input,
+/// so it can block, such as reading a line from stdin.
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+///
+/// let co: Coroutine<i32, i32, i32> = receive().and_then(|i: i32| send(i * 2).and_then(move |()| result(i)));
+///
+/// let mut outputs = Vec::new();
+/// let value = run(co, || 21, |output| outputs.push(output));
+/// assert_eq!((value, outputs), (21, vec![42]));
+/// ```
+pub fn run<'a, I, O, R, FI, FO>(
+    co: Coroutine<'a, I, O, R>,
+    mut on_input: FI,
+    mut on_output: FO,
+) -> R
+where
+    FI: FnMut() -> I,
+    FO: FnMut(O),
+{
+    let never_fails = try_run(
+        co,
+        || Ok::<_, Infallible>(on_input()),
+        |output| {
+            on_output(output);
+            Ok(())
+        },
+    );
+    match never_fails {
+        Ok(value) => value,
+        Err(never) => match never {},
+    }
+}
+
+/// Like run, but the callbacks can fail, stopping the coroutine
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+///
+/// let co: Coroutine<i32, (), i32> = receive();
+/// let value = try_run(co, || "nope".parse::<i32>(), |()| Ok(()));
+/// assert!(value.is_err());
+/// ```
+pub fn try_run<'a, I, O, R, E, FI, FO>(
+    co: Coroutine<'a, I, O, R>,
+    mut on_input: FI,
+    mut on_output: FO,
+) -> Result<R, E>
+where
+    FI: FnMut() -> Result<I, E>,
+    FO: FnMut(O) -> Result<(), E>,
+{
+    let mut co = co;
+    loop {
+        match run_step(co) {
+            StepResult::Done(value) => return Ok(value),
+            StepResult::Yield { output, next } => {
+                on_output(output)?;
+                co = *next;
+            }
+            StepResult::Next(next) => co = next(on_input()?),
+        }
+    }
+}
+
+/// Like run, but the callbacks are async, so it can run in any async runtime
+///
+/// The coroutine still knows nothing about IO, only the callbacks do.
+/// Only compiled with the async feature.
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+/// use std::{future::Future, pin::pin, task::{Context, Poll, Waker}};
+///
+/// let co: Coroutine<i32, i32, i32> = receive().and_then(|i: i32| send(i * 2).and_then(move |()| result(i)));
+///
+/// let mut outputs = Vec::new();
+/// let running = run_async(co, || async { 21 }, |o| {
+///     outputs.push(o);
+///     async {}
+/// });
+///
+/// // nothing here ever waits, so a single poll finishes it
+/// let mut context = Context::from_waker(Waker::noop());
+/// assert_eq!(pin!(running).poll(&mut context), Poll::Ready(21));
+/// assert_eq!(outputs, vec![42]);
+/// ```
+#[cfg(feature = "async")]
+pub async fn run_async<'a, I, O, R, FI, FutI, FO, FutO>(
+    co: Coroutine<'a, I, O, R>,
+    mut get_input: FI,
+    mut put_output: FO,
+) -> R
+where
+    FI: FnMut() -> FutI,
+    FutI: std::future::Future<Output = I>,
+    FO: FnMut(O) -> FutO,
+    FutO: std::future::Future<Output = ()>,
+{
+    let mut co = co;
+    loop {
+        match run_step(co) {
+            StepResult::Done(value) => return value,
+            StepResult::Yield { output, next } => {
+                put_output(output).await;
+                co = *next;
+            }
+            StepResult::Next(next) => co = next(get_input().await),
+        }
+    }
+}
+
+/// What a coroutine spawned on tokio finishes with
+///
+/// The coroutine is handed back if the input senders were all dropped
+/// while it was waiting for input
+#[cfg(feature = "tokio")]
+pub type TokioTask<I, O, R> = tokio::task::JoinHandle<Result<R, Coroutine<'static, I, O, R>>>;
+
+/// Spawns the coroutine as a tokio task, talking to it over channels
+///
+/// Both channels hold buffer messages, which must be at least one.
+/// A slow reader of outputs holds up the coroutine, and a slow coroutine
+/// holds up senders of inputs.
+/// If the output receiver is dropped, outputs are thrown away.
+/// Must be called from within a tokio runtime. Only compiled with the tokio feature.
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+///
+/// let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// runtime.block_on(async {
+///     let doubler: Coroutine<i32, i32, ()> = receive().and_then(|i: i32| send(i * 2));
+///     let (inputs, mut outputs, task) = spawn_tokio(doubler, 1);
+///
+///     inputs.send(21).await.unwrap();
+///     assert_eq!(outputs.recv().await, Some(42));
+///     assert!(matches!(task.await, Ok(Ok(()))));
+/// });
+/// ```
+///
+/// # Panics
+/// If buffer is zero
+#[cfg(feature = "tokio")]
+pub fn spawn_tokio<I, O, R>(
+    co: Coroutine<'static, I, O, R>,
+    buffer: usize,
+) -> (
+    tokio::sync::mpsc::Sender<I>,
+    tokio::sync::mpsc::Receiver<O>,
+    TokioTask<I, O, R>,
+)
+where
+    I: Send + 'static,
+    O: Send + 'static,
+    R: Send + 'static,
+{
+    assert!(
+        buffer > 0,
+        "tokio channels need room for at least one message"
+    );
+    let (inputs, mut input_receiver) = tokio::sync::mpsc::channel(buffer);
+    let (output_sender, outputs) = tokio::sync::mpsc::channel(buffer);
+    let task = tokio::spawn(async move {
+        let mut co = co;
+        loop {
+            match run_step(co) {
+                StepResult::Done(value) => return Ok(value),
+                StepResult::Yield { output, next } => {
+                    // Nobody is listening if the receiver was dropped
+                    let _ = output_sender.send(output).await;
+                    co = *next;
+                }
+                StepResult::Next(next) => match input_receiver.recv().await {
+                    Some(input) => co = next(input),
+                    None => return Err(suspend(next)),
+                },
+            }
+        }
+    });
+    (inputs, outputs, task)
+}
+
+/// How a coroutine talking over IO ended
+///
+/// It is paused if the reader ran out while it was waiting for input,
+/// and fails if reading or writing failed.
+pub type IoOutcome<'a, I, O, R> = RunOutcome<R, Coroutine<'a, I, O, R>, std::io::Error>;
+
+/// How many bytes are read at a time, at most
+#[cfg(feature = "futures")]
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Runs a byte oriented coroutine, reading its inputs and writing its outputs
+///
+/// Whatever each read returns is sent as an input, so the coroutine must
+/// cope with messages split across reads, or several in one. Outputs are
+/// written in full, waiting for the writer if it is busy, and flushed
+/// before waiting for more input. Something that is both, like a socket,
+/// can be split into halves with futures' AsyncReadExt::split.
+/// Only compiled with the futures feature.
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+/// use futures::executor::block_on;
+///
+/// // shouts back whatever it reads, until it reads nothing
+/// fn shout() -> Coroutine<'static, Vec<u8>, Vec<u8>, ()> {
+///     receive().and_then(|bytes: Vec<u8>| send(bytes.to_ascii_uppercase()))
+///         .and_then(|()| shout())
+/// }
+///
+/// let mut written = Vec::new();
+/// let outcome = block_on(run_async_io(shout(), &b"hello"[..], &mut written));
+/// assert_eq!(written, b"HELLO");
+/// assert!(matches!(outcome, RunOutcome::Paused(_)));
+/// ```
+#[cfg(feature = "futures")]
+pub async fn run_async_io<'a, R, Rd, W>(
+    co: Coroutine<'a, Vec<u8>, Vec<u8>, R>,
+    mut reader: Rd,
+    mut writer: W,
+) -> IoOutcome<'a, Vec<u8>, Vec<u8>, R>
+where
+    Rd: futures::AsyncRead + Unpin,
+    W: futures::AsyncWrite + Unpin,
+{
+    use futures::{AsyncReadExt, AsyncWriteExt};
+
+    let mut buffer = vec![0; READ_CHUNK];
+    let mut co = co;
+    loop {
+        match run_step(co) {
+            StepResult::Done(value) => {
+                return match writer.flush().await {
+                    Ok(()) => RunOutcome::Completed(value),
+                    Err(error) => RunOutcome::Failed(error),
+                }
+            }
+            StepResult::Yield { output, next } => {
+                if let Err(error) = writer.write_all(&output).await {
+                    return RunOutcome::Failed(error);
+                }
+                co = *next;
+            }
+            StepResult::Next(next) => {
+                if let Err(error) = writer.flush().await {
+                    return RunOutcome::Failed(error);
+                }
+                co = match reader.read(&mut buffer).await {
+                    Ok(0) => return RunOutcome::Paused(suspend(next)),
+                    Ok(read) => next(buffer[..read].to_vec()),
+                    Err(error) if error.kind() == std::io::ErrorKind::Interrupted => suspend(next),
+                    Err(error) => return RunOutcome::Failed(error),
+                };
+            }
+        }
+    }
+}
+
+/// Runs a line oriented coroutine, reading lines and writing its outputs
+///
+/// Each line read is sent as an input, without its line ending. Outputs
+/// are written as they are, and flushed before waiting for the next line,
+/// so prompts show up before they are answered. Lines must be utf8,
+/// even for coroutines reading bytes.
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+///
+/// // greets whoever it is told about, until it reads nothing
+/// fn greet() -> Coroutine<'static, String, String, ()> {
+///     receive().and_then(|name: String| send(format!("hello {}\n", name)))
+///         .and_then(|()| greet())
+/// }
+///
+/// let mut written = Vec::new();
+/// let outcome = run_io(greet(), "alice\r\nbob\n".as_bytes(), &mut written);
+/// assert_eq!(written, b"hello alice\nhello bob\n");
+/// assert!(matches!(outcome, RunOutcome::Paused(_)));
+/// ```
+pub fn run_io<'a, I, O, R, Rd, W>(
+    co: Coroutine<'a, I, O, R>,
+    mut reader: Rd,
+    mut writer: W,
+) -> IoOutcome<'a, I, O, R>
+where
+    I: From<String>,
+    O: AsRef<[u8]>,
+    Rd: std::io::BufRead,
+    W: std::io::Write,
+{
+    let mut co = co;
+    loop {
+        match run_step(co) {
+            StepResult::Done(value) => {
+                return match writer.flush() {
+                    Ok(()) => RunOutcome::Completed(value),
+                    Err(error) => RunOutcome::Failed(error),
+                }
+            }
+            StepResult::Yield { output, next } => {
+                if let Err(error) = writer.write_all(output.as_ref()) {
+                    return RunOutcome::Failed(error);
+                }
+                co = *next;
+            }
+            StepResult::Next(next) => {
+                if let Err(error) = writer.flush() {
+                    return RunOutcome::Failed(error);
+                }
+                let mut line = String::new();
+                co = match reader.read_line(&mut line) {
+                    Ok(0) => return RunOutcome::Paused(suspend(next)),
+                    Ok(_) => {
+                        let trimmed = line.trim_end_matches(['\r', '\n']).len();
+                        line.truncate(trimmed);
+                        next(I::from(line))
+                    }
+                    Err(error) => return RunOutcome::Failed(error),
+                };
+            }
+        }
+    }
+}
+
+/// Everything a coroutine did once its inputs ran out
+pub struct Drained<'a, I, O, R> {
+    /// Every output, in order
+    pub outputs: Vec<O>,
+    /// The result, or the coroutine if it is still waiting for input
+    pub outcome: RunOutcome<R, Coroutine<'a, I, O, R>, Infallible>,
+}
+
+/// Runs the coroutine over all of the inputs, and as far as it can go after
+///
+/// Unlike run_until_output, this doesn't stop at each output, or when the
+/// inputs are exhausted. Outputs sent after the last input are collected too.
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+///
+/// let co: Coroutine<i32, i32, ()> = receive().and_then(|i: i32| send(i).and_then(move |()| send(i + 1)));
+///
+/// let drained = run_to_end(co, vec![1].into_iter());
+/// assert_eq!(drained.outputs, vec![1, 2]);
+/// assert!(drained.outcome.is_completed());
+/// ```
+pub fn run_to_end<'a, It, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+    mut inputs: It,
+) -> Drained<'a, I, O, R>
+where
+    It: Iterator<Item = I>,
+{
+    let mut outputs = Vec::new();
+    let mut co = co;
+    loop {
+        match run_step(co) {
+            StepResult::Done(value) => {
+                return Drained {
+                    outputs,
+                    outcome: RunOutcome::Completed(value),
+                }
+            }
+            StepResult::Yield { output, next } => {
+                outputs.push(output);
+                co = *next;
+            }
+            StepResult::Next(next) => match inputs.next() {
+                Some(input) => co = next(input),
+                None => {
+                    return Drained {
+                        outputs,
+                        outcome: RunOutcome::Paused(suspend(next)),
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// Inputs given to a coroutine that has already finished
+///
+/// The input is handed back, so it isn't silently lost, along with any
+/// outputs the coroutine made on its way to finishing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlreadyFinished<I, O> {
+    /// The input that could not be delivered
+    pub input: I,
+    /// Outputs made before the coroutine was found to have finished
+    pub outputs: Vec<O>,
+}
+
+impl<I, O> std::fmt::Display for AlreadyFinished<I, O> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the coroutine has already finished")
+    }
+}
+
+impl<I: Debug, O: Debug> std::error::Error for AlreadyFinished<I, O> {}
+
+/// Why an input could not be queued
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OfferError<I, O> {
+    /// The queue was full, and the policy was to drop the newest input
+    Full(I),
+    /// The coroutine finished, so the queue will never drain
+    Finished(AlreadyFinished<I, O>),
+}
+
+impl<I, O> std::fmt::Display for OfferError<I, O> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OfferError::Full(_) => write!(f, "the input queue is full"),
+            OfferError::Finished(_) => write!(f, "the coroutine has already finished"),
+        }
+    }
+}
+
+impl<I: Debug, O: Debug> std::error::Error for OfferError<I, O> {}
+
+/// Drives a coroutine with inputs pushed in by the host
+///
+/// The iterator executors pull inputs, but often inputs arrive one at a time,
+/// such as from a channel or a socket. Feeding a finished coroutine returns
+/// AlreadyFinished with the input, or panics if configured to, as it is
+/// usually a bug in the host.
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+///
+/// let co: Coroutine<i32, i32, ()> = receive().and_then(|i: i32| send(i * 2));
+/// let mut driver = Driver::new(co);
+///
+/// assert_eq!(driver.feed(21), Ok(vec![42]));
+/// assert!(driver.is_finished());
+/// let finished = AlreadyFinished { input: 1, outputs: vec![] };
+/// assert_eq!(driver.feed(1), Err(finished));
+/// ```
+pub struct Driver<'a, I, O, R> {
+    // Only None while it is being stepped
+    slot: Option<Slot<'a, I, O, R>>,
+    panic_on_finished: bool,
+    // Inputs offered but not yet fed, oldest first
+    queue: VecDeque<(I, Option<Reservation>)>,
+    capacity: Option<usize>,
+    overflow: OverflowPolicy,
+    budget: Option<Budget>,
+    dropped: usize,
+    // When each output not yet acknowledged was made, oldest first
+    #[cfg(feature = "metrics")]
+    outstanding: std::collections::VecDeque<std::time::Instant>,
+    #[cfg(feature = "metrics")]
+    latencies: crate::metrics::LatencyHistogram,
+}
+
+impl<'a, I, O, R> Driver<'a, I, O, R> {
+    /// Wraps the coroutine, which doesn't run until polled or fed
+    pub fn new(co: Coroutine<'a, I, O, R>) -> Self {
+        Driver {
+            slot: Some(Slot::Running(co)),
+            panic_on_finished: false,
+            queue: VecDeque::new(),
+            capacity: None,
+            overflow: OverflowPolicy::Block,
+            budget: None,
+            dropped: 0,
+            #[cfg(feature = "metrics")]
+            outstanding: Default::default(),
+            #[cfg(feature = "metrics")]
+            latencies: Default::default(),
+        }
+    }
+
+    /// Panic, instead of returning an error, when fed after finishing
+    pub fn panic_on_finished(self) -> Self {
+        Driver {
+            panic_on_finished: true,
+            ..self
+        }
+    }
+
+    /// Holds at most capacity offered inputs, using policy when full
+    ///
+    /// Without this, the queue used by offer grows without limit.
+    /// Blocking runs the coroutine on the oldest queued input to make room,
+    /// and dropping the newest hands the input back as an error.
+    /// ```
+    /// use bicoro::*;
+    /// use bicoro::executor::*;
+    ///
+    /// let echo: Coroutine<i32, i32, Never> = forever(|| receive().and_then(send));
+    /// let mut driver = Driver::new(echo).bounded(2, OverflowPolicy::DropOldest);
+    ///
+    /// for i in 1..=3 {
+    ///     driver.offer(i).unwrap();
+    /// }
+    /// assert_eq!(driver.dropped(), 1);
+    /// assert_eq!(driver.run_queued(), vec![2, 3]);
+    /// ```
+    pub fn bounded(self, capacity: usize, policy: OverflowPolicy) -> Self {
+        assert!(capacity > 0, "a bounded driver needs room for an input");
+        Driver {
+            capacity: Some(capacity),
+            overflow: policy,
+            ..self
+        }
+    }
+
+    /// Queued inputs also count against a shared budget, a unit each
+    ///
+    /// When the budget is used up, the queue is treated as full and the
+    /// policy applies. A blocking driver with nothing of its own queued
+    /// goes over budget instead, as waiting would never free anything up.
+    /// ```
+    /// use bicoro::*;
+    /// use bicoro::executor::*;
+    ///
+    /// let echo = || -> Coroutine<i32, i32, Never> { forever(|| receive().and_then(send)) };
+    /// let budget = Budget::new(1);
+    /// let mut first = Driver::new(echo()).with_budget(budget.clone());
+    /// let mut second = Driver::new(echo())
+    ///     .bounded(10, OverflowPolicy::DropNewest)
+    ///     .with_budget(budget);
+    ///
+    /// first.offer(1).unwrap();
+    /// // the first driver holds the only unit
+    /// assert_eq!(second.offer(2), Err(OfferError::Full(2)));
+    /// ```
+    pub fn with_budget(self, budget: Budget) -> Self {
+        Driver {
+            budget: Some(budget),
+            ..self
+        }
+    }
+
+    /// Queues the input, to be fed later by run_queued
+    ///
+    /// Returns any outputs made while making room for it, which only
+    /// happens with the blocking policy.
+    pub fn offer(&mut self, input: I) -> Result<Vec<O>, OfferError<I, O>> {
+        let mut outputs = Vec::new();
+        if self.is_finished() {
+            let outputs = Vec::new();
+            return Err(OfferError::Finished(AlreadyFinished { input, outputs }));
+        }
+        let full = matches!(self.capacity, Some(capacity) if self.queue.len() >= capacity);
+        let reserved = match &self.budget {
+            _ if full => None,
+            Some(budget) => budget.try_reserve(1).map(Some),
+            None => Some(None),
+        };
+        if let Some(reservation) = reserved {
+            self.queue.push_back((input, reservation));
+            return Ok(outputs);
+        }
+        // Room is made by taking the oldest input, and its share of the budget
+        let reservation = match (self.overflow, self.queue.pop_front()) {
+            (OverflowPolicy::DropNewest, oldest) => {
+                if let Some(oldest) = oldest {
+                    self.queue.push_front(oldest);
+                }
+                return Err(OfferError::Full(input));
+            }
+            (OverflowPolicy::DropOldest, None) => return Err(OfferError::Full(input)),
+            (OverflowPolicy::DropOldest, Some((_, reservation))) => {
+                self.dropped += 1;
+                reservation
+            }
+            (OverflowPolicy::Block, None) => self.budget.as_ref().map(|budget| budget.reserve(1)),
+            (OverflowPolicy::Block, Some((oldest, reservation))) => match self.feed(oldest) {
+                Ok(made) => {
+                    outputs = made;
+                    reservation
+                }
+                Err(AlreadyFinished {
+                    input: oldest,
+                    outputs,
+                }) => {
+                    self.queue.push_front((oldest, reservation));
+                    return Err(OfferError::Finished(AlreadyFinished { input, outputs }));
+                }
+            },
+        };
+        self.queue.push_back((input, reservation));
+        Ok(outputs)
+    }
+
+    /// Feeds the queued inputs, returning the outputs
+    ///
+    /// Stops early if the coroutine finishes, leaving the rest queued
+    pub fn run_queued(&mut self) -> Vec<O> {
+        let mut outputs = Vec::new();
+        while let Some((input, reservation)) = self.queue.pop_front() {
+            match self.feed(input) {
+                Ok(made) => outputs.extend(made),
+                Err(AlreadyFinished {
+                    input,
+                    outputs: made,
+                }) => {
+                    outputs.extend(made);
+                    self.queue.push_front((input, reservation));
+                    break;
+                }
+            }
+        }
+        outputs
+    }
+
+    /// How many offered inputs are waiting to be fed
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// How many offered inputs were dropped to make room
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Runs until the coroutine waits for input or finishes, returning the outputs
+    pub fn poll(&mut self) -> Vec<O> {
+        let mut outputs = Vec::new();
+        self.update(|slot| slot.drive(|output| outputs.push(output)));
+        #[cfg(feature = "metrics")]
+        {
+            let now = std::time::Instant::now();
+            self.outstanding.extend(outputs.iter().map(|_| now));
+        }
+        outputs
+    }
+
+    /// Marks the oldest count outputs as consumed downstream
+    ///
+    /// The time from each output being made until it was acknowledged
+    /// is recorded in the latencies. This shows whether outputs are slow to
+    /// be made, or slow to be taken by the host.
+    /// ```
+    /// use bicoro::*;
+    /// use bicoro::executor::*;
+    ///
+    /// let co: Coroutine<i32, i32, ()> = receive().and_then(send);
+    /// let mut driver = Driver::new(co);
+    ///
+    /// driver.feed(1).unwrap();
+    /// assert_eq!(driver.outstanding(), 1);
+    /// driver.acknowledge(1);
+    /// assert_eq!(driver.latencies().count(), 1);
+    /// ```
+    #[cfg(feature = "metrics")]
+    pub fn acknowledge(&mut self, count: usize) {
+        let now = std::time::Instant::now();
+        let count = count.min(self.outstanding.len());
+        for made in self.outstanding.drain(..count) {
+            self.latencies.record(now - made);
+        }
+    }
+
+    /// How many outputs have not been acknowledged yet
+    #[cfg(feature = "metrics")]
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.len()
+    }
+
+    /// How long acknowledged outputs took to be consumed
+    #[cfg(feature = "metrics")]
+    pub fn latencies(&self) -> &crate::metrics::LatencyHistogram {
+        &self.latencies
+    }
+
+    /// Gives the input to the coroutine, returning the outputs it made
+    ///
+    /// Any outputs from before it was waiting are included, and are
+    /// returned in the error if the coroutine finished instead of waiting.
+    pub fn feed(&mut self, input: I) -> Result<Vec<O>, AlreadyFinished<I, O>> {
+        let mut outputs = self.poll();
+        if self.is_finished() {
+            if self.panic_on_finished {
+                panic!("fed an input to a coroutine that has already finished");
+            }
+            return Err(AlreadyFinished { input, outputs });
+        }
+        self.update(|slot| slot.resume(input).0);
+        outputs.extend(self.poll());
+        Ok(outputs)
+    }
+
+    /// Whether the coroutine has finished
+    pub fn is_finished(&self) -> bool {
+        matches!(&self.slot, Some(slot) if slot.is_finished())
+    }
+
+    /// The result, if the coroutine has finished
+    pub fn result(&self) -> Option<&R> {
+        match &self.slot {
+            Some(Slot::Finished(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The result, or the coroutine so it can be continued
+    pub fn into_result(self) -> std::result::Result<R, Coroutine<'a, I, O, R>> {
+        self.slot
+            .expect("driver is only empty while stepping")
+            .into_result()
+    }
+
+    fn update(&mut self, f: impl FnOnce(Slot<'a, I, O, R>) -> Slot<'a, I, O, R>) {
+        let slot = self
+            .slot
+            .take()
+            .expect("driver is only empty while stepping");
+        self.slot = Some(f(slot));
+    }
+}
+
+/// A driver whose coroutine can be replaced while it is running
+///
+/// The host keeps feeding the same handle, so the connection handling
+/// around it is untouched. Inputs fed while paused are queued, and given to
+/// the replacement once it is swapped in.
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+///
+/// let double: Coroutine<i32, i32, Never> = forever(|| receive().and_then(|i: i32| send(i * 2)));
+/// let mut service = Swappable::new(double);
+/// assert_eq!(service.feed(1), Ok(vec![2]));
+///
+/// service.pause();
+/// assert_eq!(service.feed(2), Ok(vec![]));
+///
+/// let triple = forever(|| receive().and_then(|i: i32| send(i * 3)));
+/// assert_eq!(service.swap(triple), vec![6]);
+/// ```
+pub struct Swappable<'a, I, O, R> {
+    driver: Driver<'a, I, O, R>,
+    paused: bool,
+    // Inputs fed while paused, oldest first
+    queue: VecDeque<I>,
+}
+
+impl<'a, I, O, R> Swappable<'a, I, O, R> {
+    /// Wraps the coroutine, which doesn't run until fed
+    pub fn new(co: Coroutine<'a, I, O, R>) -> Self {
+        Swappable {
+            driver: Driver::new(co),
+            paused: false,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Gives the input to the coroutine, or queues it while paused
+    pub fn feed(&mut self, input: I) -> Result<Vec<O>, AlreadyFinished<I, O>> {
+        if self.paused {
+            self.queue.push_back(input);
+            return Ok(Vec::new());
+        }
+        self.driver.feed(input)
+    }
+
+    /// Queues inputs until the next swap, such as while the replacement is prepared
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Whether inputs are being queued for a swap
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// How many inputs are waiting for the replacement
+    ///
+    /// Inputs are left here if the replacement finishes before using them
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Replaces the coroutine, then gives it the queued inputs
+    pub fn swap(&mut self, co: Coroutine<'a, I, O, R>) -> Vec<O> {
+        self.swap_with(|_| co)
+    }
+
+    /// Replaces the coroutine with one made from the old one
+    ///
+    /// The old coroutine is run until it waits for input first, so migrate
+    /// is given either its result, or the coroutine waiting for input, to
+    /// carry its state over. The returned outputs are the ones the old coroutine
+    /// made before the swap, then the ones the replacement made from the queue.
+    pub fn swap_with<F>(&mut self, migrate: F) -> Vec<O>
+    where
+        F: FnOnce(Result<R, Coroutine<'a, I, O, R>>) -> Coroutine<'a, I, O, R>,
+    {
+        let mut outputs = self.driver.poll();
+        let old = self
+            .driver
+            .slot
+            .take()
+            .expect("driver is only empty while stepping")
+            .into_result();
+        self.driver.slot = Some(Slot::Running(migrate(old)));
+        self.paused = false;
+
+        while let Some(input) = self.queue.pop_front() {
+            match self.driver.feed(input) {
+                Ok(made) => outputs.extend(made),
+                Err(AlreadyFinished {
+                    input,
+                    outputs: made,
+                }) => {
+                    outputs.extend(made);
+                    self.queue.push_front(input);
+                    break;
+                }
+            }
+        }
+        outputs.extend(self.driver.poll());
+        outputs
+    }
+
+    /// Whether the current coroutine has finished
+    pub fn is_finished(&self) -> bool {
+        self.driver.is_finished()
+    }
+
+    /// The result of the current coroutine, or it so it can be continued
+    pub fn into_result(self) -> std::result::Result<R, Coroutine<'a, I, O, R>> {
+        self.driver.into_result()
+    }
+}
+
+/// Why running over JSON lines failed
+#[cfg(feature = "serde_json")]
+#[derive(Debug)]
+pub enum JsonLinesError {
+    /// Reading or writing failed
+    Io(std::io::Error),
+    /// A line was not a valid input, or an output could not be written as JSON
+    Json {
+        /// The line the input was on, or 0 for an output
+        line: usize,
+        error: serde_json::Error,
+    },
+}
+
+#[cfg(feature = "serde_json")]
+impl std::fmt::Display for JsonLinesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonLinesError::Io(error) => write!(f, "io error: {}", error),
+            JsonLinesError::Json { line: 0, error } => write!(f, "invalid output: {}", error),
+            JsonLinesError::Json { line, error } => {
+                write!(f, "invalid input on line {}: {}", line, error)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl std::error::Error for JsonLinesError {}
+
+#[cfg(feature = "serde_json")]
+impl From<std::io::Error> for JsonLinesError {
+    fn from(error: std::io::Error) -> Self {
+        JsonLinesError::Io(error)
+    }
+}
+
+/// Runs the coroutine as a filter, reading inputs and writing outputs as JSON lines
+///
+/// Each line of the reader is one input, and each output is written as one line.
+/// Blank lines are skipped. If the reader ends first, the coroutine is paused,
+/// so it can be continued with more input. Only compiled with the serde_json feature.
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+///
+/// let doubler: Coroutine<i32, i32, Never> = forever(|| receive().and_then(|i: i32| send(i * 2)));
+///
+/// let mut output = Vec::new();
+/// let outcome = run_json_lines(doubler, "1\n2\n".as_bytes(), &mut output);
+/// assert!(matches!(outcome, RunOutcome::Paused(_)));
+/// assert_eq!(String::from_utf8(output).unwrap(), "2\n4\n");
+/// ```
+#[cfg(feature = "serde_json")]
+pub fn run_json_lines<'a, I, O, R, B, W>(
+    co: Coroutine<'a, I, O, R>,
+    mut reader: B,
+    mut writer: W,
+) -> RunOutcome<R, Coroutine<'a, I, O, R>, JsonLinesError>
+where
+    I: serde::de::DeserializeOwned,
+    O: serde::Serialize,
+    B: std::io::BufRead,
+    W: std::io::Write,
+{
+    let mut co = co;
+    let mut line = String::new();
+    let mut line_number = 0;
+    let outcome = loop {
+        match run_step(co) {
+            StepResult::Done(value) => break RunOutcome::Completed(value),
+            StepResult::Yield { output, next } => {
+                let written = serde_json::to_writer(&mut writer, &output)
+                    .map_err(|error| JsonLinesError::Json { line: 0, error })
+                    .and_then(|()| Ok(writer.write_all(b"\n")?));
+                if let Err(error) = written {
+                    return RunOutcome::Failed(error);
+                }
+                co = *next;
+            }
+            StepResult::Next(next) => {
+                let input = loop {
+                    line.clear();
+                    line_number += 1;
+                    match reader.read_line(&mut line) {
+                        Ok(0) => break None,
+                        Ok(_) if line.trim().is_empty() => continue,
+                        Ok(_) => break Some(serde_json::from_str(&line)),
+                        Err(error) => return RunOutcome::Failed(error.into()),
+                    }
+                };
+                match input {
+                    None => break RunOutcome::Paused(suspend(next)),
+                    Some(Ok(input)) => co = next(input),
+                    Some(Err(error)) => {
+                        let line = line_number;
+                        return RunOutcome::Failed(JsonLinesError::Json { line, error });
+                    }
+                }
+            }
+        }
+    };
+    match writer.flush() {
+        Ok(()) => outcome,
+        Err(error) => RunOutcome::Failed(error.into()),
+    }
+}
+
+/// What to do with a row that can't be read as an input
+#[cfg(feature = "csv")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowErrorPolicy {
+    /// Skip the row, and carry on with the next
+    ///
+    /// Only rows that can't be read are skipped, reading failing still stops
+    Skip,
+    /// Stop, failing with the error
+    Abort,
+}
+
+/// Whether the error is in the row itself, so the next row can still be read
+#[cfg(feature = "csv")]
+fn is_row_error(error: &csv::Error) -> bool {
+    matches!(
+        error.kind(),
+        csv::ErrorKind::Utf8 { .. }
+            | csv::ErrorKind::UnequalLengths { .. }
+            | csv::ErrorKind::Deserialize { .. }
+    )
+}
+
+/// Runs the coroutine over CSV, reading rows as inputs and writing outputs as rows
+///
+/// Headers are handled by the reader and writer, so configure them
+/// with csv::ReaderBuilder and csv::WriterBuilder. If the reader ends first,
+/// the coroutine is paused, so it can be continued with more rows.
+/// Only compiled with the csv feature.
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+///
+/// // sums the two columns of each row
+/// let sum: Coroutine<(i32, i32), i32, Never> = forever(|| receive().and_then(|(a, b): (i32, i32)| send(a + b)));
+///
+/// let reader = csv::Reader::from_reader("a,b\n1,2\nx,y\n3,4\n".as_bytes());
+/// let mut writer = csv::Writer::from_writer(Vec::new());
+/// let outcome = run_csv(sum, reader, &mut writer, RowErrorPolicy::Skip);
+///
+/// assert!(matches!(outcome, RunOutcome::Paused(_)));
+/// let written = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+/// assert_eq!(written, "3\n7\n");
+/// ```
+#[cfg(feature = "csv")]
+pub fn run_csv<'a, I, O, R, Rd, W>(
+    co: Coroutine<'a, I, O, R>,
+    mut reader: csv::Reader<Rd>,
+    writer: &mut csv::Writer<W>,
+    policy: RowErrorPolicy,
+) -> RunOutcome<R, Coroutine<'a, I, O, R>, csv::Error>
+where
+    I: serde::de::DeserializeOwned,
+    O: serde::Serialize,
+    Rd: std::io::Read,
+    W: std::io::Write,
+{
+    let mut co = co;
+    let mut rows = reader.deserialize();
+    let outcome = loop {
+        match run_step(co) {
+            StepResult::Done(value) => break RunOutcome::Completed(value),
+            StepResult::Yield { output, next } => {
+                if let Err(error) = writer.serialize(output) {
+                    return RunOutcome::Failed(error);
+                }
+                co = *next;
+            }
+            StepResult::Next(next) => {
+                let input = loop {
+                    match rows.next() {
+                        Some(Err(error))
+                            if policy == RowErrorPolicy::Skip && is_row_error(&error) =>
+                        {
+                            continue
+                        }
+                        input => break input,
+                    }
+                };
+                match input {
+                    None => break RunOutcome::Paused(suspend(next)),
+                    Some(Ok(input)) => co = next(input),
+                    Some(Err(error)) => return RunOutcome::Failed(error),
+                }
+            }
+        }
+    };
+    match writer.flush() {
+        Ok(()) => outcome,
+        Err(error) => RunOutcome::Failed(error.into()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -159,4 +1417,157 @@ mod tests {
             IteratorExecutorResult::Output { output: 1, .. }
         ));
     }
+
+    #[test]
+    fn leftovers_are_received_again() {
+        let test: Co<i32, Pushback<i32, i32>, ()> = receive()
+            .and_then(push_back)
+            .and_then(|()| receive())
+            .and_then(|i| send(Pushback::Output(i)));
+
+        let exec = run_until_output_with_pushback(test, vec![1, 2].into_iter());
+
+        match exec {
+            IteratorExecutorResult::Output {
+                output, remaining, ..
+            } => {
+                assert_eq!(output, 1);
+                assert_eq!(remaining.collect::<Vec<_>>(), vec![2]);
+            }
+            _ => panic!("should have sent the pushed back input"),
+        }
+    }
+
+    #[test]
+    fn outputs_before_input_are_returned_with_it() {
+        let test: Co<i32, i32, ()> = send(1).and_then(|()| receive()).and_then(send);
+        let mut driver = Driver::new(test);
+
+        assert_eq!(driver.feed(2), Ok(vec![1, 2]));
+        assert!(matches!(driver.into_result(), Ok(())));
+    }
+
+    #[test]
+    #[should_panic]
+    fn can_panic_when_fed_after_finishing() {
+        let test: Co<i32, (), ()> = result(());
+        let mut driver = Driver::new(test).panic_on_finished();
+
+        let _ = driver.feed(1);
+    }
+
+    #[test]
+    fn full_queues_follow_the_policy() {
+        let echo = || -> Co<i32, i32, ()> { receive().and_then(send) };
+
+        let mut driver = Driver::new(echo()).bounded(1, OverflowPolicy::DropNewest);
+        assert_eq!(driver.offer(1), Ok(vec![]));
+        assert_eq!(driver.offer(2), Err(OfferError::Full(2)));
+
+        // blocking feeds the oldest input, which finishes the echo
+        let mut driver = Driver::new(echo()).bounded(1, OverflowPolicy::Block);
+        assert_eq!(driver.offer(1), Ok(vec![]));
+        assert_eq!(driver.offer(2), Ok(vec![1]));
+        assert!(driver.run_queued().is_empty());
+        assert_eq!(driver.queued(), 1);
+        let finished = AlreadyFinished {
+            input: 3,
+            outputs: vec![],
+        };
+        assert_eq!(driver.offer(3), Err(OfferError::Finished(finished)));
+    }
+
+    #[test]
+    fn outputs_made_while_finishing_are_handed_back() {
+        let test: Co<i32, i32, ()> = send(1);
+        let mut driver = Driver::new(test);
+
+        let finished = AlreadyFinished {
+            input: 5,
+            outputs: vec![1],
+        };
+        assert_eq!(driver.feed(5), Err(finished));
+        assert!(driver.poll().is_empty());
+
+        let test: Co<i32, i32, ()> = send(1);
+        let mut driver = Driver::new(test).bounded(1, OverflowPolicy::Block);
+        assert_eq!(driver.offer(2), Ok(vec![]));
+        let finished = AlreadyFinished {
+            input: 3,
+            outputs: vec![1],
+        };
+        assert_eq!(driver.offer(3), Err(OfferError::Finished(finished)));
+    }
+
+    #[test]
+    fn blocked_drivers_hand_on_their_share_of_the_budget() {
+        let echo = || -> Co<i32, i32, Never> { forever(|| receive().and_then(send)) };
+        let budget = Budget::new(1);
+        let mut driver = Driver::new(echo()).with_budget(budget.clone());
+
+        assert_eq!(driver.offer(1), Ok(vec![]));
+        assert_eq!(driver.offer(2), Ok(vec![1]));
+        assert_eq!(budget.used(), 1);
+        assert_eq!(driver.run_queued(), vec![2]);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn unbounded_queues_keep_every_input() {
+        let echo: Co<i32, i32, Never> = forever(|| receive().and_then(send));
+        let mut driver = Driver::new(echo);
+        for input in 1..=5 {
+            assert_eq!(driver.offer(input), Ok(vec![]));
+        }
+        assert_eq!(driver.queued(), 5);
+        assert_eq!(driver.dropped(), 0);
+        assert_eq!(driver.run_queued(), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    #[cfg(feature = "serde_json")]
+    fn invalid_json_lines_report_the_line() {
+        let test: Co<i32, i32, ()> = receive().and_then(|_: i32| receive()).and_then(send);
+        let mut output = Vec::new();
+
+        let outcome = run_json_lines(test, "1\n\nnope\n".as_bytes(), &mut output);
+        assert!(matches!(
+            outcome,
+            RunOutcome::Failed(JsonLinesError::Json { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "csv")]
+    fn bad_rows_abort_when_asked() {
+        let test: Co<(i32, i32), i32, ()> = receive().and_then(|(a, b)| send(a + b));
+        let reader = csv::Reader::from_reader("a,b\nx,y\n1,2\n".as_bytes());
+        let mut writer = csv::Writer::from_writer(Vec::new());
+
+        let outcome = run_csv(test, reader, &mut writer, RowErrorPolicy::Abort);
+        assert!(matches!(outcome, RunOutcome::Failed(_)));
+        assert!(writer.into_inner().unwrap().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "csv")]
+    fn read_failures_are_never_skipped() {
+        struct Broken;
+        impl std::io::Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disconnected"))
+            }
+        }
+        let test: Co<(i32, i32), i32, ()> = receive().and_then(|(a, b)| send(a + b));
+        let reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(Broken);
+        let mut writer = csv::Writer::from_writer(Vec::new());
+
+        let outcome = run_csv(test, reader, &mut writer, RowErrorPolicy::Skip);
+        assert!(matches!(
+            outcome,
+            RunOutcome::Failed(error) if matches!(error.kind(), csv::ErrorKind::Io(_))
+        ));
+    }
 }
@@ -0,0 +1,1540 @@
+//! Coroutines that may fail
+//!
+//! ResultCoroutine wraps a coroutine whose result is a Result.
+//! Chaining works on the Ok value, and any Err short-circuits the rest,
+//! much like the ? operator.
+//! ```
+//! use bicoro::*;
+//! use bicoro::failable::*;
+//! use bicoro::iterator::*;
+//!
+//! // reads two inputs, failing if the second is zero
+//! let co: ResultCoroutine<i32, (), i32, &str> = lift(receive()).and_then(|a: i32| {
+//!     lift(receive()).and_then(move |b: i32| match a.checked_div(b) {
+//!         Some(value) => ok(value),
+//!         None => err("divide by zero"),
+//!     })
+//! });
+//!
+//! let mut it = as_iterator(co.to_coroutine(), vec![1, 0].into_iter());
+//! it.by_ref().for_each(drop);
+//! let (value, _) = it.finish();
+//! assert!(matches!(value, Ok(Err("divide by zero"))));
+//! ```
+use std::{collections::VecDeque, ops::ControlFlow};
+
+use crate::{
+    bind, broadcast, dispatch,
+    dispatch::only,
+    executor::{IteratorExecutorResult, RunOutcome},
+    inject, iterate,
+    machine::{Machine, Transition},
+    map, map_output, race_with_loser, receive, result, run_step, send, sequence, suspend, unicast,
+    Coroutine, DispatchResult, Never, RoutedResult, Select, StepResult, UnicastSelect,
+};
+
+/// A coroutine that produces either R or fails with E
+pub struct ResultCoroutine<'a, I, O, R, E> {
+    co: Coroutine<'a, I, O, Result<R, E>>,
+}
+
+impl<'a, I, O, R, E> From<Coroutine<'a, I, O, Result<R, E>>> for ResultCoroutine<'a, I, O, R, E> {
+    fn from(co: Coroutine<'a, I, O, Result<R, E>>) -> Self {
+        ResultCoroutine { co }
+    }
+}
+
+impl<'a, I, O, R, E> From<ResultCoroutine<'a, I, O, R, E>> for Coroutine<'a, I, O, Result<R, E>> {
+    fn from(co: ResultCoroutine<'a, I, O, R, E>) -> Self {
+        co.co
+    }
+}
+
+impl<'a, I, O, R, E> ResultCoroutine<'a, I, O, R, E> {
+    /// Wraps a coroutine that already returns a result
+    pub fn new(co: Coroutine<'a, I, O, Result<R, E>>) -> Self {
+        ResultCoroutine { co }
+    }
+
+    /// Unwraps into the plain coroutine
+    pub fn to_coroutine(self) -> Coroutine<'a, I, O, Result<R, E>> {
+        self.co
+    }
+
+    /// Continue with the ok value, or short-circuit with the error
+    ///
+    /// see [bind_ok](function@crate::bind_ok)
+    pub fn and_then<F, B>(self, f: F) -> ResultCoroutine<'a, I, O, B, E>
+    where
+        F: FnOnce(R) -> ResultCoroutine<'a, I, O, B, E> + Send + 'a,
+    {
+        let co = crate::bind_ok(self.co, move |r| f(r).co);
+        ResultCoroutine { co }
+    }
+
+    /// Continue with the error value, or keep the ok value
+    ///
+    /// see [bind_err](function@crate::bind_err)
+    pub fn or_else<F, E2>(self, f: F) -> ResultCoroutine<'a, I, O, R, E2>
+    where
+        F: FnOnce(E) -> ResultCoroutine<'a, I, O, R, E2> + Send + 'a,
+    {
+        let co = crate::bind_err(self.co, move |e| f(e).co);
+        ResultCoroutine { co }
+    }
+
+    /// Maps the ok value
+    pub fn map<F, B>(self, f: F) -> ResultCoroutine<'a, I, O, B, E>
+    where
+        F: FnOnce(R) -> B + Send + 'a,
+    {
+        let co = map(self.co, move |r| r.map(f));
+        ResultCoroutine { co }
+    }
+
+    /// Maps the error value
+    ///
+    /// see [map_err](function@crate::map_err)
+    pub fn map_err<F, E2>(self, f: F) -> ResultCoroutine<'a, I, O, R, E2>
+    where
+        F: FnOnce(E) -> E2 + Send + 'a,
+    {
+        let co = crate::map_err(self.co, f);
+        ResultCoroutine { co }
+    }
+
+    /// Transforms the inputs with a coroutine
+    ///
+    /// see [intercept_input](function@crate::intercept_input)
+    pub fn intercept_input<I2, F>(self, f: F) -> ResultCoroutine<'a, I2, O, R, E>
+    where
+        F: FnMut(I2) -> Coroutine<'a, I2, O, I> + Send + 'a,
+        O: Send,
+        R: Send,
+        E: Send,
+    {
+        let co = crate::intercept_input(self.co, f);
+        ResultCoroutine { co }
+    }
+
+    /// Maps the inputs
+    ///
+    /// see [map_input](function@crate::map_input)
+    pub fn map_input<I2, F>(self, f: F) -> ResultCoroutine<'a, I2, O, R, E>
+    where
+        F: FnMut(I2) -> I + Send + 'a,
+        O: Send,
+        R: Send,
+        E: Send,
+    {
+        let co = crate::map_input(self.co, f);
+        ResultCoroutine { co }
+    }
+
+    /// Transforms the outputs with a coroutine
+    ///
+    /// see [intercept_output](function@crate::intercept_output)
+    pub fn intercept_output<O2, F>(self, f: F) -> ResultCoroutine<'a, I, O2, R, E>
+    where
+        F: Fn(O) -> Coroutine<'a, I, O2, ()> + Send + 'a,
+        O: Send,
+        R: Send,
+        E: Send,
+    {
+        let co = crate::intercept_output(self.co, f);
+        ResultCoroutine { co }
+    }
+
+    /// Maps the outputs
+    ///
+    /// see [map_output](function@crate::map_output)
+    /// ```
+    /// use bicoro::*;
+    /// use bicoro::failable::*;
+    /// use bicoro::iterator::*;
+    ///
+    /// // a protocol of numbers, adapted to one of strings
+    /// let double: ResultCoroutine<i32, i32, (), &str> = lift(receive()).and_then(|i: i32| lift(send(i * 2)));
+    /// let co = double
+    ///     .map_input(|s: &str| s.parse::<i32>().unwrap())
+    ///     .map_output(|i: i32| i.to_string());
+    ///
+    /// let outputs = as_iterator(co.to_coroutine(), vec!["21"].into_iter()).collect::<Vec<_>>();
+    /// assert_eq!(outputs, vec!["42"]);
+    /// ```
+    pub fn map_output<O2, F>(self, f: F) -> ResultCoroutine<'a, I, O2, R, E>
+    where
+        F: Fn(O) -> O2 + Send + 'a,
+    {
+        let co = crate::map_output(self.co, f);
+        ResultCoroutine { co }
+    }
+}
+
+impl<'a, I, O, R, E> ::do_notation::Lift<R> for ResultCoroutine<'a, I, O, R, E> {
+    /// Creates a successful coroutine from a value
+    ///
+    /// see [ok](function@ok)
+    fn lift(a: R) -> Self {
+        ok(a)
+    }
+}
+
+impl<'a, I, O, R, E> Machine for ResultCoroutine<'a, I, O, R, E> {
+    type Input = I;
+    type Output = O;
+    type Result = Result<R, E>;
+
+    fn step(self) -> Transition<Self> {
+        match self.co.step() {
+            Transition::Done(value) => Transition::Done(value),
+            Transition::Yield(output, co) => Transition::Yield(output, ResultCoroutine { co }),
+            Transition::Await(co) => Transition::Await(ResultCoroutine { co }),
+        }
+    }
+
+    fn receive(self, input: I) -> Self {
+        let co = self.co.receive(input);
+        ResultCoroutine { co }
+    }
+}
+
+/// A coroutine that has succeeded with the value
+/// ```
+/// use bicoro::failable::*;
+/// let co: ResultCoroutine<(), (), i32, ()> = ok(1);
+/// ```
+pub fn ok<'a, I, O, R, E>(value: R) -> ResultCoroutine<'a, I, O, R, E> {
+    let co = result(Ok(value));
+    ResultCoroutine { co }
+}
+
+/// A coroutine that has failed with the error
+/// ```
+/// use bicoro::failable::*;
+/// let co: ResultCoroutine<(), (), (), &str> = err("failed");
+/// ```
+pub fn err<'a, I, O, R, E>(error: E) -> ResultCoroutine<'a, I, O, R, E> {
+    let co = result(Err(error));
+    ResultCoroutine { co }
+}
+
+/// Lifts a coroutine that cannot fail into a failable one
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+/// let co: ResultCoroutine<i32, (), i32, ()> = lift(receive());
+/// ```
+pub fn lift<'a, I, O, R, E>(co: Coroutine<'a, I, O, R>) -> ResultCoroutine<'a, I, O, R, E> {
+    let co = map(co, Ok);
+    ResultCoroutine { co }
+}
+
+/// Handles a failure by carrying on with the coroutine made by handler
+///
+/// The fallback can still send and receive, and may fail with a new error.
+/// This is the free function form of [ResultCoroutine::or_else].
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+/// use bicoro::executor::RunOutcome;
+///
+/// let parse: ResultCoroutine<&str, &str, i32, String> =
+///     lift(receive()).and_then(|s: &str| match s.parse() {
+///         Ok(value) => ok(value),
+///         Err(_) => err(s.to_string()),
+///     });
+/// // ask again, once
+/// let co: ResultCoroutine<&str, &str, i32, ()> = recover(parse, |_| {
+///     lift(send("try again")).and_then(|()| lift(receive())).and_then(|s: &str| s.parse().map_or(err(()), ok))
+/// });
+///
+/// let mut it = as_result_iterator(co, vec!["x", "2"].into_iter());
+/// assert_eq!(it.by_ref().collect::<Vec<_>>(), vec!["try again"]);
+/// assert!(matches!(it.finish().0, RunOutcome::Completed(2)));
+/// ```
+pub fn recover<'a, I, O, R, E, E2, F>(
+    co: ResultCoroutine<'a, I, O, R, E>,
+    handler: F,
+) -> ResultCoroutine<'a, I, O, R, E2>
+where
+    F: FnOnce(E) -> ResultCoroutine<'a, I, O, R, E2> + Send + 'a,
+{
+    co.or_else(handler)
+}
+
+/// Runs cleanup once the coroutine finishes, whether it succeeded or failed
+///
+/// The result is kept, so the cleanup can only send and receive.
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+/// use bicoro::executor::RunOutcome;
+///
+/// let co: ResultCoroutine<(), &str, (), &str> = lift(send("working")).and_then(|()| err("broken"));
+/// let co = finally(co, send("closed"));
+///
+/// let mut it = as_result_iterator(co, std::iter::empty());
+/// assert_eq!(it.by_ref().collect::<Vec<_>>(), vec!["working", "closed"]);
+/// assert!(matches!(it.finish().0, RunOutcome::Failed("broken")));
+/// ```
+pub fn finally<'a, I, O, R, E>(
+    co: ResultCoroutine<'a, I, O, R, E>,
+    cleanup: Coroutine<'a, I, O, ()>,
+) -> ResultCoroutine<'a, I, O, R, E>
+where
+    O: Send,
+    R: Send + 'a,
+    E: Send + 'a,
+{
+    let co = bind(co.co, move |value| map(cleanup, move |()| value));
+    ResultCoroutine { co }
+}
+
+/// Acquires a resource, uses it, then always releases it
+///
+/// Release runs once using finishes, even if it failed. If acquiring
+/// fails there is nothing to release, so neither of the others run.
+/// Using only borrows the resource, so release can be given it afterwards.
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+/// use bicoro::executor::RunOutcome;
+///
+/// // opens a session, which must be closed even if the request fails
+/// let open: ResultCoroutine<(), String, u32, &str> = lift(send("open".to_string())).and_then(|()| ok(7));
+/// let request = |session: &u32| lift(send(format!("request {session}"))).and_then(|()| err("timed out"));
+/// let close = |session: u32| send(format!("close {session}"));
+///
+/// let co: ResultCoroutine<(), String, (), &str> = bracket(open, request, close);
+/// let mut it = as_result_iterator(co, std::iter::empty());
+/// let outputs = it.by_ref().collect::<Vec<_>>();
+/// assert_eq!(outputs, vec!["open", "request 7", "close 7"]);
+/// assert!(matches!(it.finish().0, RunOutcome::Failed("timed out")));
+/// ```
+pub fn bracket<'a, I, O, A, R, E, FU, FR>(
+    acquire: ResultCoroutine<'a, I, O, A, E>,
+    using: FU,
+    release: FR,
+) -> ResultCoroutine<'a, I, O, R, E>
+where
+    FU: FnOnce(&A) -> ResultCoroutine<'a, I, O, R, E> + Send + 'a,
+    FR: FnOnce(A) -> Coroutine<'a, I, O, ()> + Send + 'a,
+    A: Send + 'a,
+    O: Send,
+    R: Send + 'a,
+    E: Send + 'a,
+{
+    acquire.and_then(move |resource: A| {
+        let used = using(&resource).co;
+        let co = bind(used, move |value| map(release(resource), move |()| value));
+        ResultCoroutine { co }
+    })
+}
+
+/// Runs the failable coroutine made by f for each item, stopping at the first error
+///
+/// The results are collected in order. Items after a failure are never started.
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+/// use bicoro::iterator::*;
+///
+/// // reads a divisor for each number, failing on zero
+/// let divide = |n: i32| lift(receive()).and_then(move |d: i32| match n.checked_div(d) {
+///     Some(value) => ok(value),
+///     None => err(n),
+/// });
+/// let co: ResultCoroutine<i32, (), Vec<i32>, i32> = try_traverse(vec![10, 20, 30], divide);
+///
+/// let mut it = as_iterator(co.to_coroutine(), vec![2, 0, 5].into_iter());
+/// assert!(it.next().is_none());
+/// let (value, inputs) = it.finish();
+/// assert!(matches!(value, Ok(Err(20))));
+/// assert_eq!(inputs.map(|it| it.collect::<Vec<_>>()), Some(vec![5]));
+/// ```
+pub fn try_traverse<'a, I, O, T, R, E, F>(
+    items: impl IntoIterator<Item = T>,
+    f: F,
+) -> ResultCoroutine<'a, I, O, Vec<R>, E>
+where
+    F: Fn(T) -> ResultCoroutine<'a, I, O, R, E> + Send + 'a,
+    T: Send + 'a,
+    O: Send,
+    R: Send + 'a,
+    E: Send,
+{
+    let items: Vec<T> = items.into_iter().collect();
+    let results = Vec::with_capacity(items.len());
+    let co = iterate(
+        (items.into_iter(), results),
+        move |(mut rest, mut results)| match rest.next() {
+            Some(item) => map(f(item).to_coroutine(), move |value| match value {
+                Ok(value) => {
+                    results.push(value);
+                    ControlFlow::Continue((rest, results))
+                }
+                Err(error) => ControlFlow::Break(Err(error)),
+            }),
+            None => result(ControlFlow::Break(Ok(results))),
+        },
+    );
+    ResultCoroutine { co }
+}
+
+/// Runs both one after another, keeping the errors from both
+///
+/// Unlike and_then, the second runs even if the first failed,
+/// so every problem can be reported at once.
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+/// use bicoro::iterator::*;
+///
+/// let name: ResultCoroutine<(), (), &str, &str> = err("missing name");
+/// let port: ResultCoroutine<(), (), u16, &str> = err("invalid port");
+///
+/// let mut it = as_iterator(tuple_validated(name, port).to_coroutine(), std::iter::empty());
+/// it.by_ref().for_each(drop);
+/// assert!(matches!(it.finish(), (Ok(Err(errors)), _) if errors == vec!["missing name", "invalid port"]));
+/// ```
+pub fn tuple_validated<'a, I, O, A, B, E>(
+    first: ResultCoroutine<'a, I, O, A, E>,
+    second: ResultCoroutine<'a, I, O, B, E>,
+) -> ResultCoroutine<'a, I, O, (A, B), Vec<E>>
+where
+    O: Send,
+    A: Send + 'a,
+    B: Send,
+    E: Send + 'a,
+{
+    let co = bind(first.co, move |a| {
+        map(second.co, move |b| match (a, b) {
+            (Ok(a), Ok(b)) => Ok((a, b)),
+            (Err(a), Ok(_)) => Err(vec![a]),
+            (Ok(_), Err(b)) => Err(vec![b]),
+            (Err(a), Err(b)) => Err(vec![a, b]),
+        })
+    });
+    ResultCoroutine { co }
+}
+
+/// Runs each one after another, keeping every error
+///
+/// Succeeds with all the values if none failed, otherwise
+/// fails with all the errors, in order.
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+/// use bicoro::iterator::*;
+///
+/// // checks each input is positive
+/// let check = || lift(receive()).and_then(|i: i32| if i > 0 { ok(i) } else { err(i) });
+/// let checks: Vec<ResultCoroutine<i32, (), i32, i32>> = vec![check(), check(), check()];
+///
+/// let mut it = as_iterator(validate_all(checks).to_coroutine(), vec![-1, 2, -3].into_iter());
+/// it.by_ref().for_each(drop);
+/// assert!(matches!(it.finish(), (Ok(Err(errors)), _) if errors == vec![-1, -3]));
+/// ```
+pub fn validate_all<'a, I, O, R, E>(
+    cos: Vec<ResultCoroutine<'a, I, O, R, E>>,
+) -> ResultCoroutine<'a, I, O, Vec<R>, Vec<E>>
+where
+    O: Send,
+    R: Send + 'a,
+    E: Send + 'a,
+{
+    let cos = cos.into_iter().map(ResultCoroutine::to_coroutine).collect();
+    let co = map(sequence(cos), |results| {
+        let mut values = Vec::new();
+        let mut errors = Vec::new();
+        for result in results {
+            match result {
+                Ok(value) => values.push(value),
+                Err(error) => errors.push(error),
+            }
+        }
+        if errors.is_empty() {
+            Ok(values)
+        } else {
+            Err(errors)
+        }
+    });
+    ResultCoroutine { co }
+}
+
+/// What to do with the other coroutine, once one has failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Survivor {
+    /// Drop it, failing straight away
+    Cancel,
+    /// Keep running it until it finishes, then fail
+    Finish,
+}
+
+/// The failable version of dispatch, finishing when both have finished
+///
+/// Fails with the first error, and the survivor is cancelled or finished
+/// depending on the policy. Outputs from both are merged.
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+/// use bicoro::iterator::*;
+///
+/// let first: ResultCoroutine<i32, i32, i32, &str> = lift(receive());
+/// let second: ResultCoroutine<i32, i32, i32, &str> = lift(receive()).and_then(|_| err("second failed"));
+///
+/// let selector = |i: i32| -> Select<i32, i32, i32> { if i > 0 { Select::Left(i) } else { Select::Right(i) } };
+/// let co = try_dispatch(first, second, Survivor::Cancel).map_input(selector);
+///
+/// let mut it = as_iterator(co.to_coroutine(), vec![-1, 1].into_iter());
+/// it.by_ref().for_each(drop);
+/// let (value, remaining) = it.finish();
+/// assert!(matches!(value, Ok(Err("second failed"))));
+/// assert_eq!(remaining.unwrap().collect::<Vec<_>>(), vec![1]);
+/// ```
+pub fn try_dispatch<'a, IA, IB, IAB, O, A, B, E>(
+    first: ResultCoroutine<'a, IA, O, A, E>,
+    second: ResultCoroutine<'a, IB, O, B, E>,
+    survivor: Survivor,
+) -> ResultCoroutine<'a, Select<IA, IB, IAB>, O, (A, B), E>
+where
+    IA: 'a,
+    IB: 'a,
+    IAB: Into<IA> + Into<IB> + Clone + 'a,
+    O: Send,
+    A: Send + 'a,
+    B: Send + 'a,
+    E: Send + 'a,
+{
+    let left: fn(Select<IA, IB, IAB>) -> Option<IA> = |input| match input {
+        Select::Left(input) => Some(input),
+        Select::Right(_) => None,
+        Select::Both(input) => Some(input.into()),
+    };
+    let right: fn(Select<IA, IB, IAB>) -> Option<IB> = |input| match input {
+        Select::Left(_) => None,
+        Select::Right(input) => Some(input),
+        Select::Both(input) => Some(input.into()),
+    };
+    let both = dispatch(first.co, second.co);
+    settle(
+        both,
+        survivor,
+        move |a| only(a, left),
+        move |b| only(b, right),
+    )
+}
+
+/// The failable version of broadcast_until_finished
+///
+/// see [try_dispatch] for how failures are handled
+pub fn try_broadcast<'a, I, O, A, B, E>(
+    first: ResultCoroutine<'a, I, O, A, E>,
+    second: ResultCoroutine<'a, I, O, B, E>,
+    survivor: Survivor,
+) -> ResultCoroutine<'a, I, O, (A, B), E>
+where
+    I: Clone,
+    O: Send,
+    A: Send + 'a,
+    B: Send + 'a,
+    E: Send + 'a,
+{
+    let both = broadcast(first.co, second.co);
+    settle(both, survivor, |a| a, |b| b)
+}
+
+/// The failable version of unicast_until_finished
+///
+/// see [try_dispatch] for how failures are handled
+pub fn try_unicast<'a, IA, IB, O, A, B, E>(
+    first: ResultCoroutine<'a, IA, O, A, E>,
+    second: ResultCoroutine<'a, IB, O, B, E>,
+    survivor: Survivor,
+) -> ResultCoroutine<'a, UnicastSelect<IA, IB>, O, (A, B), E>
+where
+    IA: 'a,
+    IB: 'a,
+    O: Send,
+    A: Send + 'a,
+    B: Send + 'a,
+    E: Send + 'a,
+{
+    let left: fn(UnicastSelect<IA, IB>) -> Option<IA> = |input| match input {
+        UnicastSelect::Left(input) => Some(input),
+        UnicastSelect::Right(_) => None,
+    };
+    let right: fn(UnicastSelect<IA, IB>) -> Option<IB> = |input| match input {
+        UnicastSelect::Left(_) => None,
+        UnicastSelect::Right(input) => Some(input),
+    };
+    let both = unicast(first.co, second.co);
+    settle(
+        both,
+        survivor,
+        move |a| only(a, left),
+        move |b| only(b, right),
+    )
+}
+
+/// The side of a failable routed pair that succeeded first, and the other side
+pub enum Routed<'a, IA, IB, O, RA, RB, E> {
+    Left {
+        value: RA,
+        remain: ResultCoroutine<'a, IB, UnicastSelect<IA, O>, RB, E>,
+    },
+    Right {
+        value: RB,
+        remain: ResultCoroutine<'a, IA, UnicastSelect<IB, O>, RA, E>,
+    },
+}
+
+/// The side of a failable routed pair that failed, and the surviving side
+pub enum RoutedError<'a, IA, IB, O, RA, RB, E> {
+    Left {
+        error: E,
+        remain: ResultCoroutine<'a, IB, UnicastSelect<IA, O>, RB, E>,
+    },
+    Right {
+        error: E,
+        remain: ResultCoroutine<'a, IA, UnicastSelect<IB, O>, RA, E>,
+    },
+}
+
+pub type RoutedResultRoutine<'a, IA, IB, O, RA, RB, E> = ResultCoroutine<
+    'a,
+    UnicastSelect<IA, IB>,
+    O,
+    Routed<'a, IA, IB, O, RA, RB, E>,
+    RoutedError<'a, IA, IB, O, RA, RB, E>,
+>;
+
+/// The failable version of routed
+///
+/// The pair can send messages to each other, and stops as soon as either
+/// finishes. If that side failed, the error says which it was, along with
+/// the survivor so it can be carried on or cleaned up.
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::{self, *};
+///
+/// // the first asks the second to check its input
+/// let first: ResultCoroutine<i32, UnicastSelect<i32, ()>, (), &str> =
+///     lift(receive().and_then(|i: i32| send(UnicastSelect::Left(i))))
+///         .and_then(|()| lift(receive()))
+///         .and_then(|_: i32| ok(()));
+/// let second: ResultCoroutine<i32, UnicastSelect<i32, ()>, (), &str> =
+///     lift(receive()).and_then(|i: i32| if i > 0 { ok(()) } else { err("not positive") });
+///
+/// let co = failable::routed(first, second);
+/// let exec = run_result_until_output(co, vec![UnicastSelect::Left(-1)].into_iter());
+/// assert!(matches!(
+///     exec,
+///     ResultExecutorResult::Err { error: RoutedError::Right { error: "not positive", .. }, .. }
+/// ));
+/// ```
+pub fn routed<'a, IA, IB, O, RA, RB, E>(
+    first: ResultCoroutine<'a, IA, UnicastSelect<IB, O>, RA, E>,
+    second: ResultCoroutine<'a, IB, UnicastSelect<IA, O>, RB, E>,
+) -> RoutedResultRoutine<'a, IA, IB, O, RA, RB, E>
+where
+    IA: Send,
+    IB: Send,
+    O: Send,
+    RA: Send,
+    RB: Send,
+    E: Send,
+{
+    let co = map(
+        crate::routed(first.co, second.co),
+        |finished| match finished {
+            RoutedResult::Left {
+                value: Ok(value),
+                remain,
+            } => Ok(Routed::Left {
+                value,
+                remain: remain.into(),
+            }),
+            RoutedResult::Left {
+                value: Err(error),
+                remain,
+            } => Err(RoutedError::Left {
+                error,
+                remain: remain.into(),
+            }),
+            RoutedResult::Right {
+                value: Ok(value),
+                remain,
+            } => Ok(Routed::Right {
+                value,
+                remain: remain.into(),
+            }),
+            RoutedResult::Right {
+                value: Err(error),
+                remain,
+            } => Err(RoutedError::Right {
+                error,
+                remain: remain.into(),
+            }),
+        },
+    );
+    ResultCoroutine { co }
+}
+
+// Two failable coroutines dispatched together, with their outputs side by side
+type Dispatched<'a, In, IA, IB, O, A, B, E> = Coroutine<
+    'a,
+    In,
+    UnicastSelect<O, O>,
+    DispatchResult<'a, IA, IB, O, O, Result<A, E>, Result<B, E>>,
+>;
+
+// Waits for the survivor of a dispatch, depending on whether the first to finish failed
+fn settle<'a, In, IA, IB, O, A, B, E, FA, FB>(
+    both: Dispatched<'a, In, IA, IB, O, A, B, E>,
+    survivor: Survivor,
+    lift_a: FA,
+    lift_b: FB,
+) -> ResultCoroutine<'a, In, O, (A, B), E>
+where
+    FA: FnOnce(Coroutine<'a, IA, O, Result<A, E>>) -> Coroutine<'a, In, O, Result<A, E>>
+        + Send
+        + 'a,
+    FB: FnOnce(Coroutine<'a, IB, O, Result<B, E>>) -> Coroutine<'a, In, O, Result<B, E>>
+        + Send
+        + 'a,
+    O: Send,
+    A: Send + 'a,
+    B: Send + 'a,
+    E: Send + 'a,
+{
+    let merged = map_output(both, |output| match output {
+        UnicastSelect::Left(output) => output,
+        UnicastSelect::Right(output) => output,
+    });
+    let co = bind(merged, move |finished| match finished {
+        DispatchResult::Left {
+            value: Ok(a),
+            remaining,
+        } => map(lift_b(remaining), move |b| b.map(|b| (a, b))),
+        DispatchResult::Right {
+            value: Ok(b),
+            remaining,
+        } => map(lift_a(remaining), move |a| a.map(|a| (a, b))),
+        DispatchResult::Left {
+            value: Err(error),
+            remaining,
+        } => match survivor {
+            Survivor::Cancel => result(Err(error)),
+            Survivor::Finish => map(lift_b(remaining), move |_| Err(error)),
+        },
+        DispatchResult::Right {
+            value: Err(error),
+            remaining,
+        } => match survivor {
+            Survivor::Cancel => result(Err(error)),
+            Survivor::Finish => map(lift_a(remaining), move |_| Err(error)),
+        },
+    });
+    ResultCoroutine { co }
+}
+
+/// Runs both on the same inputs, returning the first to succeed
+///
+/// Only fails if both fail, with both errors. The other
+/// is dropped as soon as one succeeds.
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+/// use bicoro::iterator::*;
+///
+/// // the first gives up straight away, but the second waits for a positive number
+/// let quick: ResultCoroutine<i32, (), i32, &str> = err("not ready");
+/// let positive = receive_until(None, |i: i32| result((i > 0).then(|| i)));
+/// let patient: ResultCoroutine<i32, (), i32, &str> = lift(positive).map(|received| received.value.unwrap());
+///
+/// let mut it = as_iterator(select_ok(quick, patient).to_coroutine(), vec![-1, 5].into_iter());
+/// it.by_ref().for_each(drop);
+/// assert!(matches!(it.finish(), (Ok(Ok(5)), _)));
+/// ```
+pub fn select_ok<'a, I, O, R, E1, E2>(
+    first: ResultCoroutine<'a, I, O, R, E1>,
+    second: ResultCoroutine<'a, I, O, R, E2>,
+) -> ResultCoroutine<'a, I, O, R, (E1, E2)>
+where
+    I: Clone,
+    O: Send,
+    R: Send,
+    E1: Send,
+    E2: Send,
+{
+    let race = race_with_loser(first.co, second.co);
+    let co = bind(race, |winner| match winner {
+        DispatchResult::Left {
+            value: Err(first),
+            remaining,
+        } => map(remaining, |second| second.map_err(|second| (first, second))),
+        DispatchResult::Right {
+            value: Err(second),
+            remaining,
+        } => map(remaining, |first| first.map_err(|first| (first, second))),
+        DispatchResult::Left {
+            value: Ok(value), ..
+        } => result(Ok(value)),
+        DispatchResult::Right {
+            value: Ok(value), ..
+        } => result(Ok(value)),
+    });
+    ResultCoroutine { co }
+}
+
+/// How a supervisor should react when its child fails
+pub enum RestartPolicy<I> {
+    /// Always restart the child, never giving up
+    ///
+    /// A child that fails without reading an input is only restarted
+    /// once the next input arrives, which is given to the new child.
+    /// This stops a child that always fails from being restarted in a loop.
+    Always,
+    /// Restart the child at most this many times, then fail with the last error
+    MaxRetries(usize),
+    /// Like max retries, but wait for ticks between restarts
+    ///
+    /// Inputs are read until is_tick has matched the number of ticks.
+    /// Inputs that arrive while waiting, that are not ticks, are dropped.
+    Backoff {
+        max_retries: usize,
+        ticks: usize,
+        is_tick: fn(&I) -> bool,
+    },
+}
+
+impl<I> Clone for RestartPolicy<I> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<I> Copy for RestartPolicy<I> {}
+
+/// Runs the child, and restarts it from the factory whenever it fails
+///
+/// This lets a long-running service survive errors, with the
+/// policy controlling how often it can fail before giving up.
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+/// use bicoro::iterator::*;
+///
+/// // fails on any negative number
+/// let child = || -> ResultCoroutine<i32, (), i32, i32> {
+///     lift(receive()).and_then(|i: i32| if i < 0 { err(i) } else { ok(i) })
+/// };
+///
+/// let co = supervise(child, RestartPolicy::MaxRetries(2));
+/// let mut it = as_iterator(co.to_coroutine(), vec![-1, -2, 3].into_iter());
+/// it.by_ref().for_each(drop);
+/// let (value, _) = it.finish();
+/// assert!(matches!(value, Ok(Ok(3))));
+/// ```
+pub fn supervise<'a, I, O, R, E, F>(
+    factory: F,
+    policy: RestartPolicy<I>,
+) -> ResultCoroutine<'a, I, O, R, E>
+where
+    F: Fn() -> ResultCoroutine<'a, I, O, R, E> + Send + 'a,
+    I: Send + 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+    E: Send + 'a,
+{
+    // What a supervisor carries between steps of the child
+    struct Supervisor<I, F> {
+        factory: F,
+        policy: RestartPolicy<I>,
+        restarts: usize,
+        // Whether the running child has read an input
+        read: bool,
+        // An input for the child, that arrived before it was started
+        pending: Option<I>,
+    }
+
+    fn supervising<'a, I, O, R, E, F>(
+        mut child: Coroutine<'a, I, O, Result<R, E>>,
+        mut supervisor: Supervisor<I, F>,
+    ) -> Coroutine<'a, I, O, Result<R, E>>
+    where
+        F: Fn() -> ResultCoroutine<'a, I, O, R, E> + Send + 'a,
+        I: Send + 'a,
+        O: Send + 'a,
+        R: Send + 'a,
+        E: Send + 'a,
+    {
+        // Restarts are stepped in a loop, so a child that fails
+        // straight away doesn't grow the stack
+        loop {
+            child = match run_step(child) {
+                StepResult::Done(Ok(value)) => return result(Ok(value)),
+                StepResult::Done(Err(error)) => {
+                    let restarts = supervisor.restarts;
+                    match supervisor.policy {
+                        RestartPolicy::Always if !supervisor.read => {
+                            return suspend(move |input| {
+                                supervisor.pending = Some(input);
+                                let child = (supervisor.factory)().co;
+                                supervising(child, supervisor)
+                            })
+                        }
+                        RestartPolicy::Always => {}
+                        RestartPolicy::MaxRetries(max) if restarts >= max => {
+                            return result(Err(error))
+                        }
+                        RestartPolicy::Backoff { max_retries, .. } if restarts >= max_retries => {
+                            return result(Err(error))
+                        }
+                        RestartPolicy::MaxRetries(_) => supervisor.restarts += 1,
+                        RestartPolicy::Backoff { ticks, is_tick, .. } => {
+                            supervisor.restarts += 1;
+                            supervisor.read = false;
+                            let wait = wait_for_ticks(ticks, is_tick);
+                            return bind(wait, move |()| {
+                                let child = (supervisor.factory)().co;
+                                supervising(child, supervisor)
+                            });
+                        }
+                    }
+                    supervisor.read = false;
+                    (supervisor.factory)().co
+                }
+                StepResult::Yield { output, next } => {
+                    return bind(send(output), move |()| supervising(*next, supervisor))
+                }
+                StepResult::Next(next) => {
+                    supervisor.read = true;
+                    match supervisor.pending.take() {
+                        Some(input) => next(input),
+                        None => return suspend(move |input| supervising(next(input), supervisor)),
+                    }
+                }
+            };
+        }
+    }
+
+    let supervisor = Supervisor {
+        factory,
+        policy,
+        restarts: 0,
+        read: false,
+        pending: None,
+    };
+    let child = (supervisor.factory)().co;
+    let co = supervising(child, supervisor);
+    ResultCoroutine { co }
+}
+
+/// The outputs of a circuit breaker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BreakerOutput<I, O, R, E> {
+    /// The running child sent an output
+    Output(O),
+    /// A child succeeded
+    Succeeded(R),
+    /// A child failed
+    Failed(E),
+    /// The breaker is open, so the input was turned away
+    Rejected(I),
+}
+
+// What a circuit breaker needs to start children, and when to trip
+struct Breaker<I, F> {
+    factory: F,
+    threshold: usize,
+    cooldown: usize,
+    is_tick: fn(&I) -> bool,
+}
+
+/// Runs children from the factory, one after another, turning inputs away after repeated failures
+///
+/// Once threshold children fail in a row the breaker opens, and every input
+/// is rejected until cooldown ticks have been received. Then one child is
+/// tried, if it fails the breaker opens again straight away. Ticks are
+/// ordinary inputs matched by is_tick, and are never given to the children.
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+/// use bicoro::iterator::*;
+///
+/// // fails on any negative number
+/// let child = || -> ResultCoroutine<i32, (), i32, i32> {
+///     lift(receive()).and_then(|i: i32| if i < 0 { err(i) } else { ok(i) })
+/// };
+///
+/// let co = circuit_breaker(child, 2, 1, |i| *i == 0);
+/// let outputs = as_iterator(co, vec![-1, -2, 3, 0, 4].into_iter()).collect::<Vec<_>>();
+/// assert_eq!(
+///     outputs,
+///     vec![
+///         BreakerOutput::Failed(-1),
+///         BreakerOutput::Failed(-2),
+///         BreakerOutput::Rejected(3),
+///         BreakerOutput::Succeeded(4),
+///     ]
+/// );
+/// ```
+pub fn circuit_breaker<'a, I, O, R, E, F>(
+    factory: F,
+    threshold: usize,
+    cooldown_ticks: usize,
+    is_tick: fn(&I) -> bool,
+) -> Coroutine<'a, I, BreakerOutput<I, O, R, E>, Never>
+where
+    F: Fn() -> ResultCoroutine<'a, I, O, R, E> + Send + 'a,
+    I: Send + 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+    E: Send + 'a,
+{
+    fn closed<'a, I, O, R, E, F>(
+        co: Coroutine<'a, I, O, Result<R, E>>,
+        breaker: Breaker<I, F>,
+        failures: usize,
+    ) -> Coroutine<'a, I, BreakerOutput<I, O, R, E>, Never>
+    where
+        F: Fn() -> ResultCoroutine<'a, I, O, R, E> + Send + 'a,
+        I: Send + 'a,
+        O: Send + 'a,
+        R: Send + 'a,
+        E: Send + 'a,
+    {
+        match run_step(co) {
+            StepResult::Done(Ok(value)) => bind(send(BreakerOutput::Succeeded(value)), |()| {
+                let child = (breaker.factory)().co;
+                closed(child, breaker, 0)
+            }),
+            StepResult::Done(Err(error)) => bind(send(BreakerOutput::Failed(error)), move |()| {
+                if failures + 1 >= breaker.threshold {
+                    let cooldown = breaker.cooldown;
+                    open(breaker, cooldown)
+                } else {
+                    let child = (breaker.factory)().co;
+                    closed(child, breaker, failures + 1)
+                }
+            }),
+            StepResult::Yield { output, next } => {
+                bind(send(BreakerOutput::Output(output)), move |()| {
+                    closed(*next, breaker, failures)
+                })
+            }
+            StepResult::Next(next) => bind(receive(), move |input| {
+                if (breaker.is_tick)(&input) {
+                    closed(suspend(next), breaker, failures)
+                } else {
+                    closed(next(input), breaker, failures)
+                }
+            }),
+        }
+    }
+
+    fn open<'a, I, O, R, E, F>(
+        breaker: Breaker<I, F>,
+        ticks: usize,
+    ) -> Coroutine<'a, I, BreakerOutput<I, O, R, E>, Never>
+    where
+        F: Fn() -> ResultCoroutine<'a, I, O, R, E> + Send + 'a,
+        I: Send + 'a,
+        O: Send + 'a,
+        R: Send + 'a,
+        E: Send + 'a,
+    {
+        if ticks == 0 {
+            // half open, a single failure trips it again
+            let child = (breaker.factory)().co;
+            let failures = breaker.threshold - 1;
+            return closed(child, breaker, failures);
+        }
+        bind(receive(), move |input| {
+            if (breaker.is_tick)(&input) {
+                open(breaker, ticks - 1)
+            } else {
+                bind(send(BreakerOutput::Rejected(input)), move |()| {
+                    open(breaker, ticks)
+                })
+            }
+        })
+    }
+
+    let child = factory().co;
+    let breaker = Breaker {
+        factory,
+        threshold: threshold.max(1),
+        cooldown: cooldown_ticks,
+        is_tick,
+    };
+    closed(child, breaker, 0)
+}
+
+// Consumes inputs until the number of ticks has been seen
+fn wait_for_ticks<'a, I, O>(ticks: usize, is_tick: fn(&I) -> bool) -> Coroutine<'a, I, O, ()>
+where
+    I: 'a,
+{
+    if ticks == 0 {
+        return result(());
+    }
+    bind(receive(), move |input: I| {
+        let remaining = if is_tick(&input) { ticks - 1 } else { ticks };
+        wait_for_ticks(remaining, is_tick)
+    })
+}
+
+/// Creates an alternative for fallback_chain
+pub type Factory<'a, I, O, R, E> = Box<dyn FnOnce() -> ResultCoroutine<'a, I, O, R, E> + Send + 'a>;
+
+/// Tries each alternative in turn, until one succeeds
+///
+/// Inputs are recorded, so when an alternative fails, the next one
+/// is started and given every input received so far. This means
+/// alternatives can pick up where the last one failed. Outputs already
+/// sent by a failed alternative can't be taken back, so the next alternative
+/// will usually send its own versions. Fails with every error, once
+/// there are no alternatives left.
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+/// use bicoro::iterator::*;
+///
+/// // only accepts even numbers
+/// let primary: Factory<i32, (), &str, &str> = Box::new(|| {
+///     lift(receive()).and_then(|i: i32| if i % 2 == 0 { ok("primary") } else { err("odd") })
+/// });
+/// let secondary: Factory<i32, (), &str, &str> =
+///     Box::new(|| lift(receive()).and_then(|_: i32| ok("secondary")));
+///
+/// let co = fallback_chain(vec![primary, secondary]);
+/// let mut it = as_iterator(co.to_coroutine(), vec![1].into_iter());
+/// it.by_ref().for_each(drop);
+/// let (value, _) = it.finish();
+/// assert!(matches!(value, Ok(Ok("secondary"))));
+/// ```
+pub fn fallback_chain<'a, I, O, R, E>(
+    alternatives: Vec<Factory<'a, I, O, R, E>>,
+) -> ResultCoroutine<'a, I, O, R, Vec<E>>
+where
+    I: Clone + Send + 'a,
+    O: Send,
+    R: Send,
+    E: Send,
+{
+    fn next_alternative<'a, I, O, R, E>(
+        mut alternatives: VecDeque<Factory<'a, I, O, R, E>>,
+        inputs: Vec<I>,
+        errors: Vec<E>,
+    ) -> Coroutine<'a, I, O, Result<R, Vec<E>>>
+    where
+        I: Clone + Send + 'a,
+        O: Send,
+        R: Send,
+        E: Send,
+    {
+        match alternatives.pop_front() {
+            None => result(Err(errors)),
+            Some(factory) => {
+                let replay = inputs
+                    .iter()
+                    .cloned()
+                    .fold(factory().co, |co, input| inject(input, co));
+                recording(replay, alternatives, inputs, errors)
+            }
+        }
+    }
+
+    fn recording<'a, I, O, R, E>(
+        co: Coroutine<'a, I, O, Result<R, E>>,
+        alternatives: VecDeque<Factory<'a, I, O, R, E>>,
+        mut inputs: Vec<I>,
+        mut errors: Vec<E>,
+    ) -> Coroutine<'a, I, O, Result<R, Vec<E>>>
+    where
+        I: Clone + Send + 'a,
+        O: Send,
+        R: Send,
+        E: Send,
+    {
+        match run_step(co) {
+            StepResult::Done(Ok(value)) => result(Ok(value)),
+            StepResult::Done(Err(error)) => {
+                errors.push(error);
+                next_alternative(alternatives, inputs, errors)
+            }
+            StepResult::Yield { output, next } => bind(send(output), move |()| {
+                recording(*next, alternatives, inputs, errors)
+            }),
+            StepResult::Next(next) => bind(receive(), move |input: I| {
+                inputs.push(input.clone());
+                recording(next(input), alternatives, inputs, errors)
+            }),
+        }
+    }
+
+    let co = next_alternative(alternatives.into(), Vec::new(), Vec::new());
+    ResultCoroutine { co }
+}
+
+/// How retry runs its attempts
+pub struct RetryPolicy<I> {
+    max_attempts: usize,
+    delay: usize,
+    is_tick: fn(&I) -> bool,
+}
+
+impl<I> Clone for RetryPolicy<I> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<I> Copy for RetryPolicy<I> {}
+
+impl<I> RetryPolicy<I> {
+    /// Tries at most this many times, counting the first attempt
+    pub fn attempts(max_attempts: usize) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            delay: 0,
+            is_tick: |_| false,
+        }
+    }
+
+    /// Waits for ticks between attempts
+    ///
+    /// Inputs are read until is_tick has matched the number of ticks.
+    /// Inputs that arrive while waiting, that are not ticks, are dropped.
+    pub fn with_delay(self, ticks: usize, is_tick: fn(&I) -> bool) -> Self {
+        RetryPolicy {
+            delay: ticks,
+            is_tick,
+            ..self
+        }
+    }
+}
+
+/// Runs a fresh coroutine from the factory until one succeeds
+///
+/// Inputs a failed attempt read are gone, so the next attempt starts
+/// from the next unread input. Use [fallback_chain] to give each attempt
+/// the inputs read so far instead. Fails with the last error, once the
+/// policy's attempts are used up.
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+/// use bicoro::iterator::*;
+///
+/// #[derive(Clone, Copy, PartialEq)]
+/// enum Input {
+///     Reply(i32),
+///     Tick,
+/// }
+///
+/// // fails on a negative reply
+/// let request = || -> ResultCoroutine<Input, (), i32, i32> {
+///     lift(receive()).and_then(|input| match input {
+///         Input::Reply(reply) if reply < 0 => err(reply),
+///         Input::Reply(reply) => ok(reply),
+///         Input::Tick => err(0),
+///     })
+/// };
+///
+/// // the reply that arrives during the delay is dropped
+/// let policy = RetryPolicy::attempts(2).with_delay(1, |input| *input == Input::Tick);
+/// let co = retry(request, policy);
+/// let inputs = vec![Input::Reply(-1), Input::Reply(2), Input::Tick, Input::Reply(3)];
+/// let mut it = as_iterator(co.to_coroutine(), inputs.into_iter());
+/// it.by_ref().for_each(drop);
+/// assert!(matches!(it.finish(), (Ok(Ok(3)), _)));
+/// ```
+pub fn retry<'a, I, O, R, E, F>(
+    factory: F,
+    policy: RetryPolicy<I>,
+) -> ResultCoroutine<'a, I, O, R, E>
+where
+    F: Fn() -> ResultCoroutine<'a, I, O, R, E> + Send + 'a,
+    I: 'a,
+{
+    fn attempt<'a, I, O, R, E, F>(
+        factory: F,
+        policy: RetryPolicy<I>,
+        attempts: usize,
+    ) -> Coroutine<'a, I, O, Result<R, E>>
+    where
+        F: Fn() -> ResultCoroutine<'a, I, O, R, E> + Send + 'a,
+        I: 'a,
+    {
+        bind(factory().co, move |value| match value {
+            Ok(value) => result(Ok(value)),
+            Err(error) if attempts + 1 >= policy.max_attempts => result(Err(error)),
+            Err(_) => {
+                let wait = wait_for_ticks(policy.delay, policy.is_tick);
+                bind(wait, move |()| attempt(factory, policy, attempts + 1))
+            }
+        })
+    }
+    let co = attempt(factory, policy, 0);
+    ResultCoroutine { co }
+}
+
+/// Where run_result_until_output stopped
+pub enum ResultExecutorResult<'a, It, I, O, R, E> {
+    /// The coroutine succeeded
+    Ok { value: R, remaining: It },
+    /// The coroutine failed
+    Err { error: E, remaining: It },
+    /// The coroutine sent an output, and can be continued
+    Output {
+        output: O,
+        co: ResultCoroutine<'a, I, O, R, E>,
+        remaining: It,
+    },
+    /// We ran out of inputs, returns the coroutine to continue when more
+    /// inputs are available
+    Exhausted { co: ResultCoroutine<'a, I, O, R, E> },
+}
+
+/// Runs the failable coroutine until it sends an output, finishes, or runs out of inputs
+///
+/// The same as executor::run_until_output, but with success and failure separated.
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+///
+/// let co: ResultCoroutine<i32, (), i32, &str> = lift(receive()).and_then(|i: i32| if i > 0 { ok(i) } else { err("not positive") });
+///
+/// let exec = run_result_until_output(co, vec![-1].into_iter());
+/// assert!(matches!(exec, ResultExecutorResult::Err { error: "not positive", .. }));
+/// ```
+pub fn run_result_until_output<'a, It, I, O, R, E>(
+    co: ResultCoroutine<'a, I, O, R, E>,
+    inputs: It,
+) -> ResultExecutorResult<'a, It, I, O, R, E>
+where
+    It: Iterator<Item = I>,
+{
+    match crate::executor::run_until_output(co.co, inputs) {
+        IteratorExecutorResult::Completed {
+            result: Ok(value),
+            remaining,
+        } => ResultExecutorResult::Ok { value, remaining },
+        IteratorExecutorResult::Completed {
+            result: Err(error),
+            remaining,
+        } => ResultExecutorResult::Err { error, remaining },
+        IteratorExecutorResult::Output {
+            output,
+            co,
+            remaining,
+        } => ResultExecutorResult::Output {
+            output,
+            co: ResultCoroutine { co },
+            remaining,
+        },
+        IteratorExecutorResult::Exhausted { co } => ResultExecutorResult::Exhausted {
+            co: ResultCoroutine { co: suspend(co) },
+        },
+    }
+}
+
+/// How a failable coroutine ended, or the rest of it if it was paused
+pub type ResultOutcome<'a, I, O, R, E> = RunOutcome<R, ResultCoroutine<'a, I, O, R, E>, E>;
+
+/// Iterates over the outputs of a failable coroutine
+///
+/// see [as_result_iterator]
+pub struct ResultIterator<'a, It, I, O, R, E>
+where
+    It: Iterator<Item = I>,
+{
+    inner: crate::iterator::CoroutineIterator<'a, It, I, O, Result<R, E>>,
+}
+
+impl<'a, It, I, O, R, E> Iterator for ResultIterator<'a, It, I, O, R, E>
+where
+    It: Iterator<Item = I>,
+{
+    type Item = O;
+
+    fn next(&mut self) -> Option<O> {
+        self.inner.next()
+    }
+}
+
+impl<'a, It, I, O, R, E> ResultIterator<'a, It, I, O, R, E>
+where
+    It: Iterator<Item = I>,
+{
+    /// The outcome, and the inputs that were not used
+    ///
+    /// Call once the iterator has returned None
+    pub fn finish(self) -> (ResultOutcome<'a, I, O, R, E>, Option<It>) {
+        let (value, inputs) = self.inner.finish();
+        let outcome = match value {
+            Ok(Ok(value)) => RunOutcome::Completed(value),
+            Ok(Err(error)) => RunOutcome::Failed(error),
+            Err(co) => RunOutcome::Paused(ResultCoroutine { co }),
+        };
+        (outcome, inputs)
+    }
+}
+
+/// Runs the failable coroutine as an iterator of its outputs
+///
+/// Like iterator::as_iterator, but finish separates the error
+/// from the result, rather than nesting results. It has its own name,
+/// so both modules can be glob imported together.
+/// ```
+/// use bicoro::*;
+/// use bicoro::executor::*;
+/// use bicoro::failable::*;
+///
+/// let co: ResultCoroutine<i32, i32, (), &str> = lift(receive()).and_then(|i: i32| match i {
+///     0 => err("zero"),
+///     i => lift(send(i)),
+/// });
+///
+/// let mut it = as_result_iterator(co, vec![0].into_iter());
+/// assert_eq!(it.next(), None);
+/// assert!(matches!(it.finish(), (RunOutcome::Failed("zero"), _)));
+/// ```
+pub fn as_result_iterator<'a, It, I, O, R, E>(
+    co: ResultCoroutine<'a, I, O, R, E>,
+    inputs: It,
+) -> ResultIterator<'a, It, I, O, R, E>
+where
+    It: Iterator<Item = I>,
+{
+    let inner = crate::iterator::as_iterator(co.co, inputs);
+    ResultIterator { inner }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iterator::as_iterator;
+
+    #[derive(Clone)]
+    enum Input {
+        Tick,
+        Value(i32),
+    }
+
+    fn failing() -> ResultCoroutine<'static, Input, (), i32, i32> {
+        lift(receive()).and_then(|input| match input {
+            Input::Value(i) if i >= 0 => ok(i),
+            Input::Value(i) => err(i),
+            Input::Tick => err(0),
+        })
+    }
+
+    #[test]
+    fn release_is_made_once_using_finishes() {
+        use std::sync::{Arc, Mutex};
+
+        // not Clone, like a file or session handle
+        struct Session(u32);
+
+        let built = Arc::new(Mutex::new(Vec::new()));
+        let (on_use, on_release) = (built.clone(), built.clone());
+        let open: ResultCoroutine<i32, u32, Session, ()> = ok(Session(7));
+        let using = move |session: &Session| {
+            on_use.lock().unwrap().push("using");
+            let id = session.0;
+            lift(receive()).and_then(move |i: i32| lift(send(id + i as u32)))
+        };
+        let release = move |session: Session| {
+            on_release.lock().unwrap().push("release");
+            send(session.0)
+        };
+
+        let co = bracket(open, using, release);
+        let mut it = as_result_iterator(co, vec![1].into_iter());
+        assert_eq!(*built.lock().unwrap(), vec!["using"]);
+        assert_eq!(it.by_ref().collect::<Vec<_>>(), vec![8, 7]);
+        assert_eq!(*built.lock().unwrap(), vec!["using", "release"]);
+    }
+
+    #[test]
+    fn fallback_chain_collects_every_error() {
+        let alternative = |limit: i32| -> Factory<'static, Input, (), i32, i32> {
+            Box::new(move || {
+                lift(receive()).and_then(move |input| match input {
+                    Input::Value(i) if i < limit => ok(i),
+                    _ => err(limit),
+                })
+            })
+        };
+        let co = fallback_chain(vec![alternative(1), alternative(2)]);
+        let mut it = as_iterator(co.to_coroutine(), vec![Input::Value(5)].into_iter());
+        it.by_ref().for_each(drop);
+        let (value, _) = it.finish();
+        assert!(matches!(value, Ok(Err(errors)) if errors == vec![1, 2]));
+    }
+
+    #[test]
+    fn children_failing_without_input_wait_to_be_restarted() {
+        let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counted = calls.clone();
+        let broken = move || -> ResultCoroutine<'static, i32, (), (), i32> {
+            counted.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            err(0)
+        };
+        let co = supervise(broken, RestartPolicy::Always);
+        let mut it = as_iterator(co.to_coroutine(), vec![1, 2].into_iter());
+        it.by_ref().for_each(drop);
+        assert!(it.finish().0.is_err());
+        // started once, then once for each input
+        assert_eq!(calls.load(std::sync::atomic::Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let co = supervise(failing, RestartPolicy::MaxRetries(1));
+        let inputs = vec![Input::Value(-1), Input::Value(-2), Input::Value(3)];
+        let mut it = as_iterator(co.to_coroutine(), inputs.into_iter());
+        it.by_ref().for_each(drop);
+        let (value, _) = it.finish();
+        assert!(matches!(value, Ok(Err(-2))));
+    }
+
+    #[test]
+    fn backoff_waits_for_ticks() {
+        let policy = RestartPolicy::Backoff {
+            max_retries: 1,
+            ticks: 2,
+            is_tick: |input| matches!(input, Input::Tick),
+        };
+        let co = supervise(failing, policy);
+        // the value received while backing off is dropped
+        let inputs = vec![
+            Input::Value(-1),
+            Input::Tick,
+            Input::Value(-2),
+            Input::Tick,
+            Input::Value(5),
+        ];
+        let mut it = as_iterator(co.to_coroutine(), inputs.into_iter());
+        it.by_ref().for_each(drop);
+        let (value, _) = it.finish();
+        assert!(matches!(value, Ok(Ok(5))));
+    }
+
+    #[test]
+    fn finished_survivors_still_send() {
+        let fails: ResultCoroutine<'static, i32, i32, (), i32> = err(-1);
+        let survivor = lift(receive()).and_then(|i: i32| lift(send(i)));
+        let co = try_broadcast(fails, survivor, Survivor::Finish);
+
+        let mut it = as_iterator(co.to_coroutine(), vec![7].into_iter());
+        assert_eq!(it.next(), Some(7));
+        assert_eq!(it.next(), None);
+        assert!(matches!(it.finish(), (Ok(Err(-1)), _)));
+    }
+}
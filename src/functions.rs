@@ -3,6 +3,7 @@
 //! This can be implemented outside the crate, but are here for convenience.
 
 use super::*;
+use std::{collections::VecDeque, ops::ControlFlow};
 
 /// Suspend this coroutine until an input arrives
 ///
@@ -105,21 +106,44 @@ where
 /// into a, as this is similar to running 'co'
 /// in the context of the output
 ///
-/// This is a specialization of subroutine
+/// The transform only needs to be FnMut, so it can keep state,
+/// or hold resources that can't be cloned.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use std::sync::mpsc::channel;
+///
+/// // receivers can't be cloned, so neither can this transform
+/// let (sender, offsets) = channel();
+/// sender.send(10).unwrap();
+/// let offset = move |input: i32| result(input + offsets.try_recv().unwrap_or(0));
+/// let echo: Coroutine<i32, i32, ()> = receive().and_then(send);
+///
+/// let outputs = as_iterator(intercept_input(echo, offset), vec![4].into_iter()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![14]);
+/// ```
 ///
 /// TLDR; change Input with the inform transform function
 pub fn intercept_input<'a, Input, InputNested, Output, Transform, Result>(
     co: Coroutine<'a, InputNested, Output, Result>,
-    transform: Transform,
+    mut transform: Transform,
 ) -> Coroutine<'a, Input, Output, Result>
 where
-    Transform: Fn(Input) -> Coroutine<'a, Input, Output, InputNested> + Send + Clone + 'a,
+    Transform: FnMut(Input) -> Coroutine<'a, Input, Output, InputNested> + Send + 'a,
     Result: Send,
     Output: Send,
 {
-    let on_input =
-        move || -> Coroutine<Input, Output, InputNested> { bind(receive(), transform.clone()) };
-    subroutine(on_input, send, co)
+    // The transform is owned by whichever step is waiting, so it never needs cloning
+    match run_step(co) {
+        StepResult::Done(value) => result(value),
+        StepResult::Yield { output, next } => {
+            bind(send(output), move |()| intercept_input(*next, transform))
+        }
+        StepResult::Next(next) => suspend(move |input| {
+            let nested = transform(input);
+            bind(nested, move |input| intercept_input(next(input), transform))
+        }),
+    }
 }
 
 /// Takes a coroutine that wants a, and 'lifts' or maps it into B
@@ -128,16 +152,53 @@ where
 /// but is the closest you can get.
 pub fn map_input<'a, InputA, InputB, Output, Result, MapFn>(
     co: Coroutine<'a, InputB, Output, Result>,
-    map_fn: MapFn,
+    mut map_fn: MapFn,
 ) -> Coroutine<'a, InputA, Output, Result>
 where
-    MapFn: Fn(InputA) -> InputB + Send + Clone + 'a,
+    MapFn: FnMut(InputA) -> InputB + Send + 'a,
     Result: Send,
     Output: Send,
 {
     intercept_input(co, move |a| result(map_fn(a)))
 }
 
+/// Transforms each input with some state, before the coroutine gets it
+///
+/// Like map_input, but f also gets the state left by the last input,
+/// so it can add running totals or sequence numbers.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let echo: Coroutine<(usize, &str), (usize, &str), ()> = receive().and_then(send).and_then(|()| receive().and_then(send));
+/// let numbered = scan_inputs(echo, 0, |count, input| (count + 1, (count, input)));
+///
+/// let outputs = as_iterator(numbered, vec!["a", "b"].into_iter()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![(0, "a"), (1, "b")]);
+/// ```
+pub fn scan_inputs<'a, I, I2, O, R, S, F>(
+    co: Coroutine<'a, I2, O, R>,
+    initial: S,
+    f: F,
+) -> Coroutine<'a, I, O, R>
+where
+    F: Fn(S, I) -> (S, I2) + Send + 'a,
+    S: Send + 'a,
+    O: Send,
+    R: Send,
+{
+    match run_step(co) {
+        StepResult::Done(value) => result(value),
+        StepResult::Yield { output, next } => {
+            bind(send(output), move |()| scan_inputs(*next, initial, f))
+        }
+        StepResult::Next(next) => suspend(move |input| {
+            let (state, input) = f(initial, input);
+            scan_inputs(next(input), state, f)
+        }),
+    }
+}
+
 /// Transforms the output of coroutine A into B
 ///
 /// This requires a coroutine that can map B outputs
@@ -159,26 +220,11 @@ where
     subroutine(receive, transform, co)
 }
 
-/// Takes a coutine outputting A, and makes it output B
-///
-/// This is a specialization of intercept_output, when we
-/// don't need to have any affects
-pub fn map_output<'a, Input, OutputA, OutputB, Result, MapFn>(
-    co: Coroutine<'a, Input, OutputA, Result>,
-    map_fn: MapFn,
-) -> Coroutine<'a, Input, OutputB, Result>
-where
-    MapFn: Fn(OutputA) -> OutputB + Send + 'a,
-    OutputA: Send,
-    Result: Send,
-{
-    intercept_output(co, move |o| send(map_fn(o)))
-}
-
 /// Runs recieve until f returns some
 ///
 /// This is ran inside it's own coroutine, so
 /// you can call send inside it.
+#[deprecated(since = "0.20.0", note = "use receive_until, which also counts inputs")]
 pub fn recieve_until<'a, Input, Output, Result, F>(f: F) -> Coroutine<'a, Input, Output, Result>
 where
     F: Fn(Input) -> Coroutine<'a, Input, Output, Option<Result>> + Send + Clone + 'a,
@@ -190,6 +236,162 @@ where
     })
 }
 
+/// What receive_until finished with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Received<R> {
+    /// The value f returned, or None if the bound was reached first
+    pub value: Option<R>,
+    /// How many inputs were received, including the one that gave the value
+    pub consumed: usize,
+}
+
+/// Receives inputs until f returns some, or bound inputs have been received
+///
+/// f is ran inside it's own coroutine, so you can call send inside it.
+/// With no bound, this keeps receiving until f returns some.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// // waits for a positive number, but gives up after three tries
+/// let positive: Coroutine<i32, (), Received<i32>> =
+///     receive_until(Some(3), |i: i32| result((i > 0).then_some(i)));
+///
+/// let mut it = as_iterator(positive, vec![-1, 0, 5].into_iter());
+/// assert!(it.next().is_none());
+/// assert!(matches!(it.finish(), (Ok(Received { value: Some(5), consumed: 3 }), _)));
+/// ```
+pub fn receive_until<'a, I, O, R, F>(bound: Option<usize>, f: F) -> Coroutine<'a, I, O, Received<R>>
+where
+    F: Fn(I) -> Coroutine<'a, I, O, Option<R>> + Send + Clone + 'a,
+{
+    fn go<'a, I, O, R, F>(
+        bound: Option<usize>,
+        f: F,
+        consumed: usize,
+    ) -> Coroutine<'a, I, O, Received<R>>
+    where
+        F: Fn(I) -> Coroutine<'a, I, O, Option<R>> + Send + Clone + 'a,
+    {
+        if bound.is_some_and(|bound| consumed >= bound) {
+            let received = Received {
+                value: None,
+                consumed,
+            };
+            return result(received);
+        }
+        let input = bind(receive(), f.clone());
+        bind(input, move |value| match value {
+            Some(value) => result(Received {
+                value: Some(value),
+                consumed: consumed + 1,
+            }),
+            None => go(bound, f, consumed + 1),
+        })
+    }
+    go(bound, f, 0)
+}
+
+/// Folds inputs into the state, until step breaks with a result
+///
+/// Each input is folded as it arrives, so inputs are never buffered.
+/// Folding doesn't grow the stack, so any number of inputs can be folded.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use std::ops::ControlFlow;
+///
+/// // sums inputs until a zero is received
+/// let sum: Coroutine<i32, (), i32> = fold_inputs(0, |total, i| match i {
+///     0 => ControlFlow::Break(total),
+///     i => ControlFlow::Continue(total + i),
+/// });
+///
+/// let inputs = std::iter::repeat(1).take(100_000).chain(std::iter::once(0));
+/// let mut it = as_iterator(sum, inputs);
+/// assert!(it.next().is_none());
+/// assert!(matches!(it.finish(), (Ok(100_000), _)));
+/// ```
+pub fn fold_inputs<'a, I, O, S, R, F>(initial: S, mut step: F) -> Coroutine<'a, I, O, R>
+where
+    F: FnMut(S, I) -> ControlFlow<R, S> + Send + 'a,
+    S: Send + 'a,
+{
+    bind(receive(), move |input| match step(initial, input) {
+        ControlFlow::Break(value) => result(value),
+        ControlFlow::Continue(state) => fold_inputs(state, step),
+    })
+}
+
+/// Receives exactly n inputs, returning them in order
+///
+/// Space for the inputs grows as they arrive, so n can be read off the wire
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// // reads a frame of three bytes
+/// let frame: Coroutine<u8, (), Vec<u8>> = receive_n(3);
+///
+/// let mut it = as_iterator(frame, vec![1, 2, 3, 4].into_iter());
+/// assert!(it.next().is_none());
+/// assert!(matches!(it.finish(), (Ok(frame), _) if frame == vec![1, 2, 3]));
+/// ```
+pub fn receive_n<'a, I, O>(n: usize) -> Coroutine<'a, I, O, Vec<I>>
+where
+    I: Send + 'a,
+    O: Send,
+{
+    // n can come from untrusted input, so only a little is reserved up front
+    iterate(Vec::with_capacity(n.min(1024)), move |mut inputs| {
+        if inputs.len() >= n {
+            return result(ControlFlow::Break(inputs));
+        }
+        map(receive(), move |input| {
+            inputs.push(input);
+            ControlFlow::Continue(inputs)
+        })
+    })
+}
+
+/// Receives inputs while pred holds, returning them and the first input that failed it
+///
+/// Unlike receive_until, every input is kept
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// // reads digits until something else arrives
+/// let digits: Coroutine<char, (), (Vec<char>, char)> = receive_while(|c: &char| c.is_ascii_digit());
+///
+/// let mut it = as_iterator(digits, "12;".chars());
+/// assert!(it.next().is_none());
+/// assert!(matches!(it.finish(), (Ok((digits, ';')), _) if digits == vec!['1', '2']));
+/// ```
+pub fn receive_while<'a, I, O, P>(pred: P) -> Coroutine<'a, I, O, (Vec<I>, I)>
+where
+    P: Fn(&I) -> bool + Send + 'a,
+    I: Send + 'a,
+    O: Send,
+{
+    fn collect<'a, I, O, P>(mut inputs: Vec<I>, pred: P) -> Coroutine<'a, I, O, (Vec<I>, I)>
+    where
+        P: Fn(&I) -> bool + Send + 'a,
+        I: Send + 'a,
+        O: Send,
+    {
+        bind(receive(), move |input| {
+            if pred(&input) {
+                inputs.push(input);
+                collect(inputs, pred)
+            } else {
+                result((inputs, input))
+            }
+        })
+    }
+    collect(Vec::new(), pred)
+}
+
 /// Use to either consume this input or re-emit as an output
 ///
 /// This is useful when we want to intercept or transform
@@ -257,10 +459,10 @@ pub fn right<'a, I, O, A, B>(
 ) -> Coroutine<'a, I, O, B>
 where
     O: Send,
-    A: Send,
     B: Send,
 {
-    map(tuple(left, right), |(_, b)| b)
+    // Only right is kept while left runs, so A never needs to be Send
+    bind(left, move |_| right)
 }
 
 /// Runs a routine before the second routine
@@ -287,6 +489,92 @@ pub fn void<I, O, A>(co: Coroutine<I, O, A>) -> Coroutine<I, O, ()> {
     map(co, |_| ())
 }
 
+/// Runs each coroutine one after another, collecting their results
+///
+/// The coroutines share the same inputs and outputs, so
+/// this is tuple for any number of coroutines
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let reads: Vec<Coroutine<i32, (), i32>> = vec![receive(), receive(), receive()];
+///
+/// let mut it = as_iterator(sequence(reads), vec![1, 2, 3].into_iter());
+/// assert!(it.next().is_none());
+/// assert!(matches!(it.finish(), (Ok(results), _) if results == vec![1, 2, 3]));
+/// ```
+pub fn sequence<'a, I, O, R>(cos: Vec<Coroutine<'a, I, O, R>>) -> Coroutine<'a, I, O, Vec<R>>
+where
+    O: Send,
+    R: Send + 'a,
+{
+    let results = Vec::with_capacity(cos.len());
+    iterate(
+        (cos.into_iter(), results),
+        |(mut rest, mut results)| match rest.next() {
+            Some(co) => map(co, move |value| {
+                results.push(value);
+                ControlFlow::Continue((rest, results))
+            }),
+            None => result(ControlFlow::Break(results)),
+        },
+    )
+}
+
+/// Runs each coroutine one after another, ignoring their results
+///
+/// Like sequence, but for when only the effects matter
+pub fn sequence_<'a, I, O, R>(cos: Vec<Coroutine<'a, I, O, R>>) -> Coroutine<'a, I, O, ()>
+where
+    O: Send,
+    R: Send + 'a,
+{
+    iterate(cos.into_iter(), |mut rest| match rest.next() {
+        Some(co) => map(co, move |_| ControlFlow::Continue(rest)),
+        None => result(ControlFlow::Break(())),
+    })
+}
+
+/// Runs the coroutine made by f for each item, one after another
+///
+/// Like sequence, but the coroutines are only made when they are
+/// needed, so large batches don't all need building up front
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// // asks a question for each name, collecting the answers
+/// let ask = |name: &'static str| send(name).and_then(|()| receive());
+/// let co: Coroutine<i32, &str, Vec<i32>> = traverse(vec!["alice", "bob"], ask);
+///
+/// let mut it = as_iterator(co, vec![30, 40].into_iter());
+/// assert_eq!(it.by_ref().collect::<Vec<_>>(), vec!["alice", "bob"]);
+/// assert!(matches!(it.finish(), (Ok(ages), _) if ages == vec![30, 40]));
+/// ```
+pub fn traverse<'a, I, O, T, R, F>(
+    items: impl IntoIterator<Item = T>,
+    f: F,
+) -> Coroutine<'a, I, O, Vec<R>>
+where
+    F: Fn(T) -> Coroutine<'a, I, O, R> + Send + 'a,
+    T: Send + 'a,
+    O: Send,
+    R: Send + 'a,
+{
+    let items: Vec<T> = items.into_iter().collect();
+    let results = Vec::with_capacity(items.len());
+    iterate(
+        (items.into_iter(), results),
+        move |(mut rest, mut results)| match rest.next() {
+            Some(item) => map(f(item), move |value| {
+                results.push(value);
+                ControlFlow::Continue((rest, results))
+            }),
+            None => result(ControlFlow::Break(results)),
+        },
+    )
+}
+
 /// Use this input for the next input
 ///
 /// Allows us to provide a single input to the coroutine
@@ -309,14 +597,13 @@ where
     }
 }
 
+pub type TryObserveRoutine<'a, I, O, R> = Coroutine<'a, I, O, (Option<O>, Coroutine<'a, I, O, R>)>;
 /// Get the next output of the coroutine
 ///
 /// This captures the next output, allowing it to be inspected instead of
 /// emitted. Returns the observed value and the remaining coroutine.
 /// If the coroutine has ended, the observed value is none.
-pub fn try_observe<I, O, R>(
-    co: Coroutine<I, O, R>,
-) -> Coroutine<I, O, (Option<O>, Coroutine<I, O, R>)> {
+pub fn try_observe<I, O, R>(co: Coroutine<I, O, R>) -> TryObserveRoutine<I, O, R> {
     match run_step(co) {
         StepResult::Done(r) => result((None, result(r))),
         StepResult::Yield { output, next } => result((Some(output), *next)),
@@ -329,3 +616,496 @@ pub fn try_observe<I, O, R>(
         }
     }
 }
+
+/// Output of a coroutine that can return inputs it didn't use
+///
+/// See with_pushback for running these
+pub enum Pushback<I, O> {
+    /// A normal output
+    Output(O),
+    /// An input that should be given back on the next receive
+    Leftover(I),
+}
+
+/// Gives an input back, so the next receive will get it again
+///
+/// This is useful for parsers, that may need to look at an input
+/// to decide it isn't theirs.
+/// ```
+/// use bicoro::*;
+/// let co: Coroutine<i32, Pushback<i32, ()>, ()> = push_back(1);
+/// ```
+pub fn push_back<'a, I, O>(input: I) -> Coroutine<'a, I, Pushback<I, O>, ()> {
+    send(Pushback::Leftover(input))
+}
+
+/// Sends a normal output from a coroutine that can push back inputs
+/// ```
+/// use bicoro::*;
+/// let co: Coroutine<(), Pushback<(), i32>, ()> = send_output(1);
+/// ```
+pub fn send_output<'a, I, O>(output: O) -> Coroutine<'a, I, Pushback<I, O>, ()> {
+    send(Pushback::Output(output))
+}
+
+/// Runs a coroutine that can push back inputs
+///
+/// Leftovers are given back on the following receives, the most
+/// recently pushed back first. Any leftovers not received by the time the
+/// coroutine finishes are returned with the result, in the order they
+/// would have been received.
+/// The executor and iterator runners for pushback are built on this, so
+/// they hand back leftovers the same way.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// // peeks at an input, then reads it properly
+/// let co: Coroutine<i32, Pushback<i32, i32>, i32> = receive()
+///     .and_then(|peeked: i32| push_back(peeked))
+///     .and_then(|()| receive())
+///     .and_then(|i: i32| send_output(i).and_then(move |()| push_back(i + 1)))
+///     .and_then(|()| result(0));
+///
+/// let mut it = as_iterator(with_pushback(co), vec![1].into_iter());
+/// assert_eq!(it.by_ref().collect::<Vec<_>>(), vec![1]);
+/// let (value, _) = it.finish();
+/// assert!(matches!(value, Ok((0, leftovers)) if leftovers == vec![2]));
+/// ```
+pub fn with_pushback<'a, I, O, R>(
+    co: Coroutine<'a, I, Pushback<I, O>, R>,
+) -> Coroutine<'a, I, O, (R, Vec<I>)>
+where
+    I: Send + 'a,
+    O: Send,
+    R: Send,
+{
+    fn go<'a, I, O, R>(
+        co: Coroutine<'a, I, Pushback<I, O>, R>,
+        mut leftovers: Vec<I>,
+    ) -> Coroutine<'a, I, O, (R, Vec<I>)>
+    where
+        I: Send + 'a,
+        O: Send,
+        R: Send,
+    {
+        match run_step(co) {
+            StepResult::Done(value) => {
+                leftovers.reverse();
+                result((value, leftovers))
+            }
+            StepResult::Yield { output, next } => match output {
+                Pushback::Output(output) => bind(send(output), move |()| go(*next, leftovers)),
+                Pushback::Leftover(input) => {
+                    leftovers.push(input);
+                    go(*next, leftovers)
+                }
+            },
+            StepResult::Next(next) => match leftovers.pop() {
+                Some(input) => go(next(input), leftovers),
+                None => bind(receive(), move |input| go(next(input), leftovers)),
+            },
+        }
+    }
+    go(co, Vec::new())
+}
+
+/// The result of the coroutine, or the rest of it if it was stopped early
+pub type TakeRoutine<'a, I, O, R> = Coroutine<'a, I, O, Result<R, Coroutine<'a, I, O, R>>>;
+
+/// Runs the coroutine until it has sent n outputs
+///
+/// Stops straight after the nth output, returning the rest of the coroutine
+/// so it can be resumed later. If it finishes first, the result is returned.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let counter: Coroutine<(), i32, ()> = send(1).and_then(|()| send(2)).and_then(|()| send(3));
+///
+/// // read a page of two
+/// let mut it = as_iterator(take_outputs(2, counter), std::iter::empty());
+/// assert_eq!(it.by_ref().collect::<Vec<_>>(), vec![1, 2]);
+/// let rest = match it.finish() {
+///     (Ok(Err(rest)), _) => rest,
+///     _ => panic!("should have stopped early"),
+/// };
+///
+/// // and then the rest
+/// let outputs = as_iterator(rest, std::iter::empty()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![3]);
+/// ```
+pub fn take_outputs<'a, I, O, R>(n: usize, co: Coroutine<'a, I, O, R>) -> TakeRoutine<'a, I, O, R>
+where
+    O: Send,
+    R: Send,
+{
+    if n == 0 {
+        return result(Err(co));
+    }
+    match run_step(co) {
+        StepResult::Done(value) => result(Ok(value)),
+        StepResult::Yield { output, next } => {
+            bind(send(output), move |()| take_outputs(n - 1, *next))
+        }
+        StepResult::Next(next) => suspend(move |input| take_outputs(n, next(input))),
+    }
+}
+
+/// Groups outputs into batches of size
+///
+/// Outputs are held until there are size of them, and then sent together.
+/// When the coroutine finishes, whatever is left is sent as a smaller batch.
+/// Useful for writing to something that is cheaper to write to in bulk.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let rows: Coroutine<(), i32, ()> = send(1).and_then(|()| send(2)).and_then(|()| send(3));
+///
+/// let outputs = as_iterator(batch_output(2, rows), std::iter::empty()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![vec![1, 2], vec![3]]);
+/// ```
+///
+/// # Panics
+/// If size is zero
+pub fn batch_output<'a, I, O, R>(
+    size: usize,
+    co: Coroutine<'a, I, O, R>,
+) -> Coroutine<'a, I, Vec<O>, R>
+where
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    assert!(size > 0, "batches must hold at least one output");
+    batching(size, co, None, Batch::default())
+}
+
+/// Batch output, where held outputs also count against a shared budget
+///
+/// Each held output uses a single unit of the budget. When the budget is
+/// used up, the batch is sent straight away, even if it isn't full yet.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let rows: Coroutine<(), i32, ()> = send(1).and_then(|()| send(2)).and_then(|()| send(3));
+///
+/// // batches could hold 10, but the budget only has room for one
+/// let co = batch_output_budgeted(10, rows, Budget::new(1));
+/// let outputs = as_iterator(co, std::iter::empty()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![vec![1, 2], vec![3]]);
+/// ```
+///
+/// # Panics
+/// If size is zero
+pub fn batch_output_budgeted<'a, I, O, R>(
+    size: usize,
+    co: Coroutine<'a, I, O, R>,
+    budget: Budget,
+) -> Coroutine<'a, I, Vec<O>, R>
+where
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    assert!(size > 0, "batches must hold at least one output");
+    batching(size, co, Some(budget), Batch::default())
+}
+
+/// Outputs held by batch output, and their share of the budget
+struct Batch<O> {
+    outputs: Vec<O>,
+    reservations: Vec<Reservation>,
+}
+
+impl<O> Default for Batch<O> {
+    fn default() -> Self {
+        Batch {
+            outputs: Vec::new(),
+            reservations: Vec::new(),
+        }
+    }
+}
+
+fn batching<'a, I, O, R>(
+    size: usize,
+    mut co: Coroutine<'a, I, O, R>,
+    budget: Option<Budget>,
+    mut batch: Batch<O>,
+) -> Coroutine<'a, I, Vec<O>, R>
+where
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    loop {
+        match run_step(co) {
+            StepResult::Done(value) if batch.outputs.is_empty() => return result(value),
+            StepResult::Done(value) => return right(send(batch.outputs), result(value)),
+            StepResult::Yield { output, next } => {
+                batch.outputs.push(output);
+                let reserved = match &budget {
+                    Some(budget) => budget.try_reserve(1).map(|reservation| {
+                        batch.reservations.push(reservation);
+                    }),
+                    None => Some(()),
+                };
+                if batch.outputs.len() == size || reserved.is_none() {
+                    let outputs = batch.outputs;
+                    let next = move |()| batching(size, *next, budget, Batch::default());
+                    return bind(send(outputs), next);
+                }
+                co = *next;
+            }
+            StepResult::Next(next) => {
+                return suspend(move |input| batching(size, next(input), budget, batch))
+            }
+        }
+    }
+}
+
+/// Sends each item of every collection the coroutine sends, one at a time
+///
+/// The opposite of [batch_output], for when a coroutine produces
+/// several outputs at once but whoever is listening wants them one by one.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let pages: Coroutine<(), Vec<i32>, ()> = send(vec![1, 2]).and_then(|()| send(vec![3]));
+///
+/// let outputs = as_iterator(flatten_output(pages), std::iter::empty()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![1, 2, 3]);
+/// ```
+pub fn flatten_output<'a, I, O, R>(co: Coroutine<'a, I, O, R>) -> Coroutine<'a, I, O::Item, R>
+where
+    O: IntoIterator + Send,
+    O::Item: Send + 'a,
+    R: Send + 'a,
+{
+    match run_step(co) {
+        StepResult::Done(value) => result(value),
+        StepResult::Yield { output, next } => {
+            let sent = output
+                .into_iter()
+                .fold(result(()), |co, item| right(co, send(item)));
+            bind(sent, move |()| flatten_output(*next))
+        }
+        StepResult::Next(next) => suspend(move |input| flatten_output(next(input))),
+    }
+}
+
+/// Answers some outputs straight away, without the host seeing them
+///
+/// When responder has an input for an output, the output is not sent,
+/// and the input is given to the coroutine the next time it receives,
+/// before any input from outside. Other outputs are sent as normal.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Frame { Ping, Data(i32) }
+///
+/// // pings are acked automatically, so the host only sees data
+/// let co: Coroutine<&str, Frame, ()> = send(Frame::Ping)
+///     .and_then(|()| receive())
+///     .and_then(|ack: &str| send(Frame::Data(ack.len() as i32)));
+/// let co = auto_respond(co, |frame: &Frame| match frame {
+///     Frame::Ping => Some("ack"),
+///     _ => None,
+/// });
+///
+/// let outputs = as_iterator(co, std::iter::empty()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![Frame::Data(3)]);
+/// ```
+pub fn auto_respond<'a, I, O, R, F>(
+    co: Coroutine<'a, I, O, R>,
+    responder: F,
+) -> Coroutine<'a, I, O, R>
+where
+    F: Fn(&O) -> Option<I> + Send + 'a,
+    I: Send + 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    fn go<'a, I, O, R, F>(
+        mut co: Coroutine<'a, I, O, R>,
+        responder: F,
+        mut responses: VecDeque<I>,
+    ) -> Coroutine<'a, I, O, R>
+    where
+        F: Fn(&O) -> Option<I> + Send + 'a,
+        I: Send + 'a,
+        O: Send + 'a,
+        R: Send + 'a,
+    {
+        loop {
+            match run_step(co) {
+                StepResult::Done(value) => return result(value),
+                StepResult::Yield { output, next } => match responder(&output) {
+                    Some(response) => {
+                        responses.push_back(response);
+                        co = *next;
+                    }
+                    None => return bind(send(output), move |()| go(*next, responder, responses)),
+                },
+                StepResult::Next(next) => match responses.pop_front() {
+                    Some(response) => co = next(response),
+                    None => return suspend(move |input| go(next(input), responder, responses)),
+                },
+            }
+        }
+    }
+    go(co, responder, VecDeque::new())
+}
+
+/// A type with no values, for coroutines that never finish
+///
+/// Should be !, but that is not stable yet
+pub enum Never {}
+
+/// Runs the coroutine made by factory, over and over again
+///
+/// This never finishes, which is shown by the Never result.
+/// Each run is started in a loop, rather than by recursion, so running
+/// forever won't grow the stack. A factory that neither sends nor receives
+/// will spin forever, like any other infinite loop.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// // echos every input
+/// let echo: Coroutine<i32, i32, Never> = forever(|| receive().and_then(send));
+///
+/// let outputs = as_iterator(echo, vec![1, 2, 3].into_iter()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![1, 2, 3]);
+/// ```
+pub fn forever<'a, I, O, F>(factory: F) -> Coroutine<'a, I, O, Never>
+where
+    F: Fn() -> Coroutine<'a, I, O, ()> + Send + 'a,
+    O: Send,
+{
+    fn run<'a, I, O, F>(mut co: Coroutine<'a, I, O, ()>, factory: F) -> Coroutine<'a, I, O, Never>
+    where
+        F: Fn() -> Coroutine<'a, I, O, ()> + Send + 'a,
+        O: Send,
+    {
+        loop {
+            match run_step(co) {
+                StepResult::Done(()) => co = factory(),
+                StepResult::Yield { output, next } => {
+                    return bind(send(output), move |()| run(*next, factory))
+                }
+                StepResult::Next(next) => return suspend(move |input| run(next(input), factory)),
+            }
+        }
+    }
+    let co = factory();
+    run(co, factory)
+}
+
+/// Runs step with the state, until it breaks with a result
+///
+/// Each run of step continues with a new state, or breaks out of the loop
+/// with the result. Like forever, the steps are run in a loop rather
+/// than by recursion, so long running machines won't grow the stack.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use std::ops::ControlFlow;
+///
+/// // sums inputs until it sees a zero
+/// let sum: Coroutine<i32, (), i32> = iterate(0, |total| {
+///     receive().and_then(move |input| match input {
+///         0 => result(ControlFlow::Break(total)),
+///         n => result(ControlFlow::Continue(total + n)),
+///     })
+/// });
+///
+/// let mut it = as_iterator(sum, vec![1, 2, 3, 0].into_iter());
+/// assert!(it.next().is_none());
+/// assert!(matches!(it.finish(), (Ok(6), _)));
+/// ```
+pub fn iterate<'a, I, O, S, R, F>(initial: S, step: F) -> Coroutine<'a, I, O, R>
+where
+    F: Fn(S) -> Coroutine<'a, I, O, ControlFlow<R, S>> + Send + 'a,
+    S: Send + 'a,
+    O: Send,
+    R: Send,
+{
+    fn run<'a, I, O, S, R, F>(
+        mut co: Coroutine<'a, I, O, ControlFlow<R, S>>,
+        step: F,
+    ) -> Coroutine<'a, I, O, R>
+    where
+        F: Fn(S) -> Coroutine<'a, I, O, ControlFlow<R, S>> + Send + 'a,
+        S: Send + 'a,
+        O: Send,
+        R: Send,
+    {
+        loop {
+            match run_step(co) {
+                StepResult::Done(ControlFlow::Continue(state)) => co = step(state),
+                StepResult::Done(ControlFlow::Break(value)) => return result(value),
+                StepResult::Yield { output, next } => {
+                    return bind(send(output), move |()| run(*next, step))
+                }
+                StepResult::Next(next) => return suspend(move |input| run(next(input), step)),
+            }
+        }
+    }
+    let co = step(initial);
+    run(co, step)
+}
+
+/// Runs the coroutine made by factory until its result passes pred
+///
+/// Outputs from every run are sent on, and the first result
+/// that passes is returned. Useful for retrying or polling until
+/// an answer is good enough.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// // asks for a number until it gets an even one
+/// let ask: Coroutine<i32, &str, i32> = repeat_until(|| send("number?").and_then(|()| receive()), |n| n % 2 == 0);
+///
+/// let mut it = as_iterator(ask, vec![1, 3, 4].into_iter());
+/// let outputs = it.by_ref().collect::<Vec<_>>();
+/// assert_eq!(outputs, vec!["number?", "number?", "number?"]);
+/// assert!(matches!(it.finish(), (Ok(4), _)));
+/// ```
+pub fn repeat_until<'a, I, O, R, F, P>(factory: F, pred: P) -> Coroutine<'a, I, O, R>
+where
+    F: Fn() -> Coroutine<'a, I, O, R> + Send + 'a,
+    P: Fn(&R) -> bool + Send + 'a,
+    O: Send,
+    R: Send,
+{
+    fn run<'a, I, O, R, F, P>(
+        mut co: Coroutine<'a, I, O, R>,
+        factory: F,
+        pred: P,
+    ) -> Coroutine<'a, I, O, R>
+    where
+        F: Fn() -> Coroutine<'a, I, O, R> + Send + 'a,
+        P: Fn(&R) -> bool + Send + 'a,
+        O: Send,
+        R: Send,
+    {
+        loop {
+            match run_step(co) {
+                StepResult::Done(value) if pred(&value) => return result(value),
+                StepResult::Done(_) => co = factory(),
+                StepResult::Yield { output, next } => {
+                    return bind(send(output), move |()| run(*next, factory, pred))
+                }
+                StepResult::Next(next) => {
+                    return suspend(move |input| run(next(input), factory, pred))
+                }
+            }
+        }
+    }
+    let co = factory();
+    run(co, factory, pred)
+}
@@ -1,8 +1,10 @@
 //! Convert the coroutine to an iterator
 //!
 //! One of the issues here is that we
+use std::{collections::VecDeque, convert::Infallible};
+
 use crate::{
-    executor::{run_until_output, IteratorExecutorResult},
+    executor::{run_until_output, IteratorExecutorResult, RunOutcome},
     *,
 };
 
@@ -15,6 +17,26 @@ where
     co: Option<Coroutine<'a, I, O, R>>,
     result: Option<R>,
     inputs: Option<It>,
+    stats: RunStats,
+}
+
+/// How much a coroutine did while being iterated
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RunStats {
+    /// Inputs given to the coroutine
+    pub inputs: usize,
+    /// Outputs the coroutine sent
+    pub outputs: usize,
+}
+
+/// Everything left once a coroutine iterator is finished
+pub struct FinishedRun<'a, It, I, O, R> {
+    /// The result, or the coroutine if it was waiting for more inputs
+    pub outcome: RunOutcome<R, Coroutine<'a, I, O, R>, Infallible>,
+    /// How much the coroutine did
+    pub stats: RunStats,
+    /// The inputs that were not used
+    pub remaining: Option<It>,
 }
 
 impl<'a, It, I, O, R> Iterator for CoroutineIterator<'a, It, I, O, R>
@@ -24,36 +46,31 @@ where
     type Item = O;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut co = None;
-        let mut it = None;
-        std::mem::swap(&mut self.co, &mut co);
-        std::mem::swap(&mut self.inputs, &mut it);
-
-        match (co, it) {
-            (Some(co), Some(it)) => match run_until_output(co, it) {
-                IteratorExecutorResult::Completed { result, remaining } => {
-                    std::mem::swap(&mut self.inputs, &mut Some(remaining));
-                    self.result = Some(result);
-                    None
-                }
-                IteratorExecutorResult::Output {
-                    output,
-                    co,
-                    remaining,
-                } => {
-                    std::mem::swap(&mut self.inputs, &mut Some(remaining));
-                    std::mem::swap(&mut self.co, &mut Some(co));
-                    Some(output)
-                }
-                IteratorExecutorResult::Exhausted { co } => {
-                    let co = suspend(co);
-                    std::mem::swap(&mut self.co, &mut Some(co));
-                    None
-                }
-            },
-            (mut co, mut it) => {
-                std::mem::swap(&mut self.co, &mut co);
-                std::mem::swap(&mut self.inputs, &mut it);
+        let co = self.co.take()?;
+        let inputs = match self.inputs.as_mut() {
+            Some(inputs) => inputs,
+            None => {
+                self.co = Some(co);
+                return None;
+            }
+        };
+
+        // The inputs are borrowed, so they are kept even once exhausted,
+        // in case more inputs are added to them later
+        let consumed = &mut self.stats.inputs;
+        let inputs = inputs.by_ref().inspect(|_| *consumed += 1);
+        match run_until_output(co, inputs) {
+            IteratorExecutorResult::Completed { result, .. } => {
+                self.result = Some(result);
+                None
+            }
+            IteratorExecutorResult::Output { output, co, .. } => {
+                self.co = Some(co);
+                self.stats.outputs += 1;
+                Some(output)
+            }
+            IteratorExecutorResult::Exhausted { co } => {
+                self.co = Some(suspend(co));
                 None
             }
         }
@@ -72,6 +89,37 @@ where
             _ => panic!("Invalid state. This is a bug"),
         }
     }
+
+    /// How much the coroutine has done so far
+    pub fn stats(&self) -> RunStats {
+        self.stats
+    }
+
+    /// Finishes, also returning how much the coroutine did
+    ///
+    /// Like finish, this should be called once the iterator returns None
+    /// ```
+    /// use bicoro::*;
+    /// use bicoro::executor::*;
+    /// use bicoro::iterator::*;
+    ///
+    /// let co: Coroutine<i32, i32, i32> = receive().and_then(|i: i32| send(i).and_then(move |()| result(i)));
+    ///
+    /// let mut it = as_iterator(co, vec![1, 2].into_iter());
+    /// it.by_ref().for_each(drop);
+    /// let run = it.finish_run();
+    /// assert_eq!(run.stats, RunStats { inputs: 1, outputs: 1 });
+    /// assert_eq!(run.outcome.completed(), Some(1));
+    /// ```
+    pub fn finish_run(self) -> FinishedRun<'a, It, I, O, R> {
+        let stats = self.stats;
+        let (outcome, remaining) = self.finish();
+        FinishedRun {
+            outcome: outcome.into(),
+            stats,
+            remaining,
+        }
+    }
 }
 
 pub fn as_iterator<I, O, R, It>(
@@ -85,5 +133,113 @@ where
         co: Some(co),
         result: None,
         inputs: Some(inputs),
+        stats: RunStats::default(),
+    }
+}
+
+/// Creates an iterator for a coroutine that can push back inputs
+///
+/// See [with_pushback](function@crate::with_pushback), leftovers
+/// the coroutine didn't use are returned alongside its result on finish.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let co: Coroutine<i32, Pushback<i32, i32>, ()> =
+///     receive().and_then(|i: i32| send_output(i).and_then(move |()| push_back(i)));
+///
+/// let mut it = as_iterator_with_pushback(co, vec![1, 2].into_iter());
+/// assert_eq!(it.by_ref().collect::<Vec<_>>(), vec![1]);
+/// let (value, remaining) = it.finish();
+/// assert!(matches!(value, Ok(((), leftovers)) if leftovers == vec![1]));
+/// assert_eq!(remaining.unwrap().collect::<Vec<_>>(), vec![2]);
+/// ```
+pub fn as_iterator_with_pushback<'a, I, O, R, It>(
+    co: Coroutine<'a, I, Pushback<I, O>, R>,
+    inputs: It,
+) -> CoroutineIterator<'a, It, I, O, (R, Vec<I>)>
+where
+    It: Iterator<Item = I>,
+    I: Send + 'a,
+    O: Send,
+    R: Send,
+{
+    as_iterator(with_pushback(co), inputs)
+}
+
+/// Inputs from a queue of sources, one after another
+///
+/// More sources can be appended once the earlier ones are used up,
+/// such as the next segment of a log file.
+pub struct Sources<'s, I> {
+    sources: VecDeque<Box<dyn Iterator<Item = I> + 's>>,
+}
+
+impl<'s, I> Default for Sources<'s, I> {
+    fn default() -> Self {
+        Sources {
+            sources: VecDeque::new(),
+        }
+    }
+}
+
+impl<'s, I> Sources<'s, I> {
+    /// No sources yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a source, to be read after the others
+    pub fn append<It>(&mut self, source: It)
+    where
+        It: IntoIterator<Item = I>,
+        It::IntoIter: 's,
+    {
+        self.sources.push_back(Box::new(source.into_iter()));
+    }
+}
+
+impl<'s, I> Iterator for Sources<'s, I> {
+    type Item = I;
+
+    fn next(&mut self) -> Option<I> {
+        while let Some(source) = self.sources.front_mut() {
+            match source.next() {
+                Some(input) => return Some(input),
+                None => {
+                    self.sources.pop_front();
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<'a, 's, I, O, R> CoroutineIterator<'a, Sources<'s, I>, I, O, R> {
+    /// Adds more inputs, which are used once the earlier sources run out
+    ///
+    /// The iterator can be continued after it ran out of inputs
+    /// ```
+    /// use bicoro::*;
+    /// use bicoro::iterator::*;
+    ///
+    /// let echo: Coroutine<i32, i32, Never> = forever(|| receive().and_then(send));
+    /// let mut it = as_iterator(echo, Sources::new());
+    ///
+    /// it.append_source(vec![1, 2]);
+    /// assert_eq!(it.by_ref().collect::<Vec<_>>(), vec![1, 2]);
+    ///
+    /// // the next segment arrives later
+    /// it.append_source(vec![3]);
+    /// assert_eq!(it.by_ref().collect::<Vec<_>>(), vec![3]);
+    /// ```
+    pub fn append_source<It>(&mut self, source: It)
+    where
+        It: IntoIterator<Item = I>,
+        It::IntoIter: 's,
+    {
+        if let Some(inputs) = self.inputs.as_mut() {
+            inputs.append(source);
+        }
     }
 }
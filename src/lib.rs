@@ -1,24 +1,70 @@
 #![doc = include_str!("../README.md")]
 
+mod budget;
+mod cancel;
 mod chain;
 mod compat;
+mod context;
 mod cooperate;
 mod coroutine;
+mod deadline;
 mod dispatch;
+mod either;
+mod eof;
 mod functions;
+mod logged;
+mod mailbox;
+mod many;
 mod observe;
 mod option;
 mod result;
 mod routed;
-pub use crate::compat::*;
+mod shutdown;
+mod slot;
+mod tee;
+mod transport;
+pub use budget::*;
+pub use cancel::*;
 pub use chain::*;
+pub use context::*;
 pub use cooperate::*;
 pub use coroutine::*;
+pub use deadline::*;
 pub use dispatch::*;
+pub use either::*;
+pub use eof::*;
 pub use functions::*;
+pub use logged::*;
+pub use many::*;
 pub use option::*;
 pub use result::*;
 pub use routed::*;
+pub use shutdown::*;
+pub use tee::*;
+pub use transport::*;
+pub mod actors;
+#[cfg(feature = "metrics")]
+pub mod bench;
+#[cfg(feature = "crossbeam")]
+pub mod channels;
+#[cfg(feature = "devtools")]
+pub mod devtools;
+pub mod env;
 pub mod executor;
+pub mod failable;
 pub mod iterator;
+pub mod machine;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "futures")]
+pub mod migrate;
+pub mod optional;
+pub mod protocol;
+pub mod scheduler;
+pub mod state;
+#[cfg(feature = "futures")]
+pub mod streams;
+pub mod testing;
+pub mod time;
+pub mod util;
 pub use observe::*;
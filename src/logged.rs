@@ -0,0 +1,128 @@
+use crate::{
+    bind, map_output, result, run_step, send, suspend, Coroutine, Recorder, StepResult,
+    UnicastSelect,
+};
+
+/// A coroutine that can send logs of L, as well as outputs of O
+///
+/// Logs are sent on the left, and outputs on the right, so they
+/// are never mixed up. Use run_logged, discard_logs or collect_logs
+/// to get a plain coroutine back.
+pub type LoggedCoroutine<'a, I, O, L, R> = Coroutine<'a, I, UnicastSelect<L, O>, R>;
+
+/// Sends a log
+pub fn send_log<'a, I, O, L>(log: L) -> LoggedCoroutine<'a, I, O, L, ()> {
+    send(UnicastSelect::Left(log))
+}
+
+/// Sends an output, next to the logs
+pub fn send_out<'a, I, O, L>(output: O) -> LoggedCoroutine<'a, I, O, L, ()> {
+    send(UnicastSelect::Right(output))
+}
+
+/// Lifts a coroutine that doesn't log
+pub fn lift_logged<'a, I, O, L, R>(co: Coroutine<'a, I, O, R>) -> LoggedCoroutine<'a, I, O, L, R> {
+    map_output(co, UnicastSelect::Right)
+}
+
+/// Records each log in the recorder, sending only the outputs
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let co: LoggedCoroutine<i32, i32, String, ()> = receive().and_then(|i: i32| {
+///     send_log(format!("got {}", i)).and_then(move |()| send_out(i * 2))
+/// });
+///
+/// let mut logs = Vec::new();
+/// let outputs = as_iterator(run_logged(co, |log: &String| logs.push(log.clone())), vec![3].into_iter()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![6]);
+/// assert_eq!(logs, vec!["got 3"]);
+/// ```
+pub fn run_logged<'a, I, O, L, R, S>(
+    co: LoggedCoroutine<'a, I, O, L, R>,
+    mut recorder: S,
+) -> Coroutine<'a, I, O, R>
+where
+    S: Recorder<L> + Send + 'a,
+    O: Send + 'a,
+    L: Send + 'a,
+    R: Send + 'a,
+{
+    let mut co = co;
+    loop {
+        match run_step(co) {
+            StepResult::Done(value) => return result(value),
+            StepResult::Yield { output, next } => match output {
+                UnicastSelect::Left(log) => {
+                    recorder.record(&log);
+                    co = *next;
+                }
+                UnicastSelect::Right(output) => {
+                    return bind(send(output), move |()| run_logged(*next, recorder))
+                }
+            },
+            StepResult::Next(next) => {
+                return suspend(move |input| run_logged(next(input), recorder))
+            }
+        }
+    }
+}
+
+/// Drops the logs, sending only the outputs
+pub fn discard_logs<'a, I, O, L, R>(co: LoggedCoroutine<'a, I, O, L, R>) -> Coroutine<'a, I, O, R>
+where
+    O: Send + 'a,
+    L: Send + 'a,
+    R: Send + 'a,
+{
+    run_logged(co, |_: &L| ())
+}
+
+/// Keeps the logs, returning them with the result
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let co: LoggedCoroutine<(), &str, &str, i32> =
+///     send_log("starting").and_then(|()| send_out("working")).and_then(|()| result(1));
+///
+/// let mut it = as_iterator(collect_logs(co), std::iter::empty());
+/// assert_eq!(it.by_ref().collect::<Vec<_>>(), vec!["working"]);
+/// assert!(matches!(it.finish(), (Ok((1, logs)), _) if logs == vec!["starting"]));
+/// ```
+pub fn collect_logs<'a, I, O, L, R>(
+    co: LoggedCoroutine<'a, I, O, L, R>,
+) -> Coroutine<'a, I, O, (R, Vec<L>)>
+where
+    O: Send + 'a,
+    L: Send + 'a,
+    R: Send + 'a,
+{
+    fn go<'a, I, O, L, R>(
+        mut co: LoggedCoroutine<'a, I, O, L, R>,
+        mut logs: Vec<L>,
+    ) -> Coroutine<'a, I, O, (R, Vec<L>)>
+    where
+        O: Send + 'a,
+        L: Send + 'a,
+        R: Send + 'a,
+    {
+        loop {
+            match run_step(co) {
+                StepResult::Done(value) => return result((value, logs)),
+                StepResult::Yield { output, next } => match output {
+                    UnicastSelect::Left(log) => {
+                        logs.push(log);
+                        co = *next;
+                    }
+                    UnicastSelect::Right(output) => {
+                        return bind(send(output), move |()| go(*next, logs))
+                    }
+                },
+                StepResult::Next(next) => return suspend(move |input| go(next(input), logs)),
+            }
+        }
+    }
+    go(co, Vec::new())
+}
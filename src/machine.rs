@@ -0,0 +1,324 @@
+//! Flat state machines, that step without allocating
+//!
+//! Coroutines box a closure for every step, which is too costly for some
+//! embedded targets. A small fixed protocol can instead be written as an enum
+//! of its states, implementing [Machine]. Stepping a machine only moves the enum,
+//! so it never allocates. When allocating is fine, [to_coroutine] turns a machine
+//! into a coroutine, so it can still be used with every other combinator.
+//! Coroutines are machines too, so code written for machines can run either.
+//!
+//! There is no builder that turns combinators into a machine, they are written by hand.
+//! ```
+//! use bicoro::machine::*;
+//!
+//! // waits for a ping, then replies with a pong, twice
+//! enum PingPong {
+//!     Waiting(u8),
+//!     Replying(u8),
+//! }
+//!
+//! impl Machine for PingPong {
+//!     type Input = &'static str;
+//!     type Output = &'static str;
+//!     type Result = ();
+//!
+//!     fn step(self) -> Transition<Self> {
+//!         match self {
+//!             PingPong::Waiting(0) => Transition::Done(()),
+//!             PingPong::Waiting(_) => Transition::Await(self),
+//!             PingPong::Replying(left) => Transition::Yield("pong", PingPong::Waiting(left - 1)),
+//!         }
+//!     }
+//!
+//!     fn receive(self, input: &'static str) -> Self {
+//!         match (self, input) {
+//!             (PingPong::Waiting(left), "ping") => PingPong::Replying(left),
+//!             (state, _) => state,
+//!         }
+//!     }
+//! }
+//!
+//! let mut outputs = [""; 2];
+//! let mut sent = 0;
+//! let mut inputs = ["ping", "hello", "ping"].into_iter();
+//! let mut machine = PingPong::Waiting(2);
+//! loop {
+//!     match machine.step() {
+//!         Transition::Done(()) => break,
+//!         Transition::Yield(output, next) => {
+//!             outputs[sent] = output;
+//!             sent += 1;
+//!             machine = next;
+//!         }
+//!         Transition::Await(waiting) => machine = waiting.receive(inputs.next().unwrap()),
+//!     }
+//! }
+//! assert_eq!(outputs, ["pong", "pong"]);
+//! ```
+use crate::{bind, result, send, suspend, Coroutine};
+
+/// What a machine does next
+pub enum Transition<M: Machine> {
+    /// Sends the output, then continues as the next state
+    Yield(M::Output, M),
+    /// Needs an input before it can continue
+    Await(M),
+    /// Has finished with a result
+    Done(M::Result),
+}
+
+/// A protocol written as a flat state machine
+pub trait Machine: Sized {
+    /// What the machine receives
+    type Input;
+    /// What the machine sends
+    type Output;
+    /// What the machine finishes with
+    type Result;
+
+    /// Moves to the next transition, without an input
+    fn step(self) -> Transition<Self>;
+
+    /// Gives an input to a machine that returned Transition::Await
+    fn receive(self, input: Self::Input) -> Self;
+}
+
+/// Runs the machine as a coroutine
+///
+/// This boxes each step like any other coroutine, so it is only for hosts
+/// that can allocate, and want to combine the machine with other coroutines.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use bicoro::machine::*;
+///
+/// // sends a running total, finishing when it receives a zero
+/// enum Sum {
+///     Waiting(i32),
+///     Received(i32, i32),
+/// }
+///
+/// impl Machine for Sum {
+///     type Input = i32;
+///     type Output = i32;
+///     type Result = i32;
+///
+///     fn step(self) -> Transition<Self> {
+///         match self {
+///             Sum::Waiting(_) => Transition::Await(self),
+///             Sum::Received(total, 0) => Transition::Done(total),
+///             Sum::Received(total, i) => Transition::Yield(total + i, Sum::Waiting(total + i)),
+///         }
+///     }
+///
+///     fn receive(self, input: i32) -> Self {
+///         match self {
+///             Sum::Waiting(total) => Sum::Received(total, input),
+///             state => state,
+///         }
+///     }
+/// }
+///
+/// let co = map_output(to_coroutine(Sum::Waiting(0)), |total| total * 10);
+/// let mut it = as_iterator(co, vec![1, 2, 0].into_iter());
+/// assert_eq!(it.by_ref().collect::<Vec<_>>(), vec![10, 30]);
+/// assert!(matches!(it.finish(), (Ok(3), _)));
+/// ```
+pub fn to_coroutine<'a, M>(machine: M) -> Coroutine<'a, M::Input, M::Output, M::Result>
+where
+    M: Machine + Send + 'a,
+    M::Output: Send,
+    M::Result: Send,
+{
+    match machine.step() {
+        Transition::Done(value) => result(value),
+        Transition::Yield(output, next) => bind(send(output), move |()| to_coroutine(next)),
+        Transition::Await(waiting) => suspend(move |input| to_coroutine(waiting.receive(input))),
+    }
+}
+
+/// The transitions of a machine, written out so they can be checked
+///
+/// Machines are written by hand, so nothing stops a state from being
+/// unreachable, or an input from being forgotten. Listing the transitions
+/// alongside the machine lets those mistakes be found in a test, rather
+/// than when the machine gets stuck.
+/// ```
+/// use bicoro::machine::*;
+///
+/// #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// enum Door { Open, Closed, Locked, Broken }
+///
+/// let mut table = Transitions::new();
+/// table.transition(Door::Open, "close", Door::Closed);
+/// table.transition(Door::Closed, "open", Door::Open);
+/// table.transition(Door::Closed, "lock", Door::Locked);
+/// table.transition(Door::Broken, "open", Door::Open);
+///
+/// assert_eq!(table.unreachable(&Door::Open), vec![Door::Broken]);
+/// assert!(table.missing(&["open", "close"]).contains(&(Door::Locked, "open")));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Transitions<S, E> {
+    // In the order they were added, so reports are stable
+    states: Vec<S>,
+    transitions: Vec<(S, E, S)>,
+    finals: Vec<S>,
+}
+
+impl<S, E> Default for Transitions<S, E> {
+    fn default() -> Self {
+        Transitions {
+            states: Vec::new(),
+            transitions: Vec::new(),
+            finals: Vec::new(),
+        }
+    }
+}
+
+impl<S, E> Transitions<S, E>
+where
+    S: Clone + Eq + std::hash::Hash,
+    E: Clone + Eq,
+{
+    /// No states or transitions yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a state, even if no transitions use it
+    pub fn state(&mut self, state: S) {
+        if !self.states.contains(&state) {
+            self.states.push(state);
+        }
+    }
+
+    /// The machine moves from one state to another on the input
+    pub fn transition(&mut self, from: S, on: E, to: S) {
+        self.state(from.clone());
+        self.state(to.clone());
+        self.transitions.push((from, on, to));
+    }
+
+    /// The machine finishes in this state, so it needs no transitions
+    pub fn finish(&mut self, state: S) {
+        self.state(state.clone());
+        if !self.finals.contains(&state) {
+            self.finals.push(state);
+        }
+    }
+
+    /// The states that can't be reached from initial
+    pub fn unreachable(&self, initial: &S) -> Vec<S> {
+        let mut reached = std::collections::HashSet::new();
+        let mut queue = vec![initial.clone()];
+        while let Some(state) = queue.pop() {
+            if reached.insert(state.clone()) {
+                let next = self
+                    .transitions
+                    .iter()
+                    .filter(|(from, _, _)| *from == state);
+                queue.extend(next.map(|(_, _, to)| to.clone()));
+            }
+        }
+        self.states
+            .iter()
+            .filter(|state| !reached.contains(*state))
+            .cloned()
+            .collect()
+    }
+
+    /// Each state and input that has no transition, skipping finished states
+    pub fn missing(&self, inputs: &[E]) -> Vec<(S, E)> {
+        let mut missing = Vec::new();
+        for state in self.states.iter().filter(|s| !self.finals.contains(s)) {
+            for input in inputs {
+                let handled = self
+                    .transitions
+                    .iter()
+                    .any(|(from, on, _)| from == state && on == input);
+                if !handled {
+                    missing.push((state.clone(), input.clone()));
+                }
+            }
+        }
+        missing
+    }
+
+    /// Groups states that behave the same, so each group could be one state
+    ///
+    /// Two states behave the same if both or neither are finished, and every
+    /// input takes them to states that behave the same. Groups with one state
+    /// are left out.
+    /// ```
+    /// use bicoro::machine::*;
+    ///
+    /// // two waiting states that do the same thing
+    /// let mut table = Transitions::new();
+    /// table.transition("start", "a", "wait 1");
+    /// table.transition("start", "b", "wait 2");
+    /// table.transition("wait 1", "go", "done");
+    /// table.transition("wait 2", "go", "done");
+    /// table.finish("done");
+    ///
+    /// assert_eq!(table.equivalent(&["a", "b", "go"]), vec![vec!["wait 1", "wait 2"]]);
+    /// ```
+    pub fn equivalent(&self, inputs: &[E]) -> Vec<Vec<S>> {
+        // refine the groups until no input splits any group
+        let mut group: Vec<usize> = self
+            .states
+            .iter()
+            .map(|state| usize::from(self.finals.contains(state)))
+            .collect();
+        loop {
+            let signature = |index: usize| {
+                let state = &self.states[index];
+                let targets: Vec<Option<usize>> = inputs
+                    .iter()
+                    .map(|input| {
+                        self.transitions
+                            .iter()
+                            .find(|(from, on, _)| from == state && on == input)
+                            .and_then(|(_, _, to)| self.states.iter().position(|s| s == to))
+                            .map(|to| group[to])
+                    })
+                    .collect();
+                (group[index], targets)
+            };
+            let signatures: Vec<_> = (0..self.states.len()).map(signature).collect();
+            let mut distinct: Vec<&(usize, Vec<Option<usize>>)> = Vec::new();
+            let refined: Vec<usize> = signatures
+                .iter()
+                .map(
+                    |signature| match distinct.iter().position(|d| *d == signature) {
+                        Some(index) => index,
+                        None => {
+                            distinct.push(signature);
+                            distinct.len() - 1
+                        }
+                    },
+                )
+                .collect();
+            let groups_before = group.iter().collect::<std::collections::HashSet<_>>().len();
+            let stable = distinct.len() == groups_before;
+            group = refined;
+            if stable {
+                break;
+            }
+        }
+
+        let mut groups: Vec<Vec<S>> = Vec::new();
+        let mut seen: Vec<usize> = Vec::new();
+        for (index, id) in group.iter().enumerate() {
+            match seen.iter().position(|s| s == id) {
+                Some(position) => groups[position].push(self.states[index].clone()),
+                None => {
+                    seen.push(*id);
+                    groups.push(vec![self.states[index].clone()]);
+                }
+            }
+        }
+        groups.retain(|group| group.len() > 1);
+        groups
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::VecDeque;
+
+use crate::slot::{update, Slot};
+
+/// Where an output of a coroutine in a mailbox goes
+pub(crate) enum Delivery<M, T, E> {
+    /// Queued for the coroutine at the index
+    To(usize, M),
+    /// Sent out of the system
+    External(T),
+    /// Stops the system with the error
+    Fail(E),
+}
+
+/// Coroutines that message each other through a shared queue
+///
+/// This is the scheduler behind both actor systems and routed topologies,
+/// which only differ in how an output is addressed.
+pub(crate) struct Mailbox<'a, M, O, R> {
+    pub(crate) slots: Vec<Slot<'a, M, O, R>>,
+    queue: VecDeque<(usize, M)>,
+}
+
+impl<'a, M, O, R> Mailbox<'a, M, O, R> {
+    pub(crate) fn new(slots: Vec<Slot<'a, M, O, R>>) -> Self {
+        Mailbox {
+            slots,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Queues a message for the coroutine at the index
+    pub(crate) fn post(&mut self, index: usize, message: M) {
+        self.queue.push_back((index, message));
+    }
+
+    /// Runs until an output leaves the system, or nothing more can happen
+    ///
+    /// Every coroutine is driven until it is waiting or finished, with route
+    /// deciding where each output goes, then the next message is delivered.
+    /// Messages are delivered in the order they were sent, and dropped if
+    /// their coroutine has finished. None means every message was delivered.
+    pub(crate) fn settle<T, E>(
+        &mut self,
+        mut route: impl FnMut(usize, O) -> Delivery<M, T, E>,
+    ) -> Option<Result<T, E>> {
+        loop {
+            for index in 0..self.slots.len() {
+                while let Slot::Running(_) = self.slots[index] {
+                    let output = update(&mut self.slots, index, Slot::step);
+                    match output.map(|output| route(index, output)) {
+                        Some(Delivery::To(to, message)) => self.queue.push_back((to, message)),
+                        Some(Delivery::External(output)) => return Some(Ok(output)),
+                        Some(Delivery::Fail(error)) => return Some(Err(error)),
+                        None => {}
+                    }
+                }
+            }
+
+            let (index, message) = self.queue.pop_front()?;
+            if index < self.slots.len() {
+                update(&mut self.slots, index, |slot| (slot.resume(message).0, ()));
+            }
+        }
+    }
+
+    /// Whether every coroutine has finished
+    pub(crate) fn is_finished(&self) -> bool {
+        self.slots.iter().all(Slot::is_finished)
+    }
+}
@@ -0,0 +1,712 @@
+//! Combinators that run any number of coroutines together
+//!
+//! These are the N-ary versions of the pairwise combinators in dispatch.
+//! Outputs are tagged with the index of the coroutine that produced them
+//! and results are returned in the same order the coroutines were given.
+use std::{
+    collections::{hash_map::DefaultHasher, VecDeque},
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    bind, receive, result, right, send,
+    slot::{self, Slot},
+    Budget, Coroutine, Reservation,
+};
+
+/// Sends every input to all of the coroutines
+///
+/// Each coroutine is ran until it needs input, before the
+/// next input is read, so no inputs are buffered. Outputs are tagged
+/// with the index of the coroutine that sent them. Completes
+/// once every coroutine has completed.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let add_one: Coroutine<i32, i32, ()> = receive().and_then(|i: i32| send(i + 1));
+/// let add_two: Coroutine<i32, i32, ()> = receive().and_then(|i: i32| send(i + 2));
+///
+/// let co = broadcast_all(vec![add_one, add_two]);
+/// let outputs = as_iterator(co, vec![1].into_iter()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![(0, 2), (1, 3)]);
+/// ```
+pub fn broadcast_all<'a, I, O, R>(
+    consumers: Vec<Coroutine<'a, I, O, R>>,
+) -> Coroutine<'a, I, (usize, O), Vec<R>>
+where
+    I: Clone + 'a,
+    O: Send,
+    R: Send,
+{
+    let slots = consumers.into_iter().map(Slot::Running).collect();
+    broadcast_slots(slots)
+}
+
+fn broadcast_slots<'a, I, O, R>(
+    mut slots: Vec<Slot<'a, I, O, R>>,
+) -> Coroutine<'a, I, (usize, O), Vec<R>>
+where
+    I: Clone + 'a,
+    O: Send,
+    R: Send,
+{
+    for index in 0..slots.len() {
+        while let Slot::Running(_) = slots[index] {
+            if let Some(output) = slot::update(&mut slots, index, Slot::step) {
+                return bind(send((index, output)), move |()| broadcast_slots(slots));
+            }
+        }
+    }
+
+    if slots.iter().all(Slot::is_finished) {
+        return result(finished(slots));
+    }
+    let on_input = move |input: I| {
+        let slots = slots
+            .into_iter()
+            .map(|slot| slot.resume(input.clone()).0)
+            .collect();
+        broadcast_slots(slots)
+    };
+    bind(receive(), on_input)
+}
+
+/// What to do when a consumer's buffer is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Stop reading inputs until the consumer catches up
+    Block,
+    /// Throw away the oldest buffered input to make room
+    DropOldest,
+    /// Throw away the input that didn't fit
+    DropNewest,
+}
+
+/// The outputs of a bounded broadcast
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BroadcastOutput<I, O> {
+    /// A consumer sent an output
+    Output { consumer: usize, output: O },
+    /// An input was discarded, as the consumer had fallen behind
+    Dropped { consumer: usize, input: I },
+}
+
+struct Bounded<'a, I, O, R> {
+    slots: Vec<Slot<'a, I, O, R>>,
+    queues: Vec<VecDeque<(I, Option<Reservation>)>>,
+    cursor: usize,
+    capacity: usize,
+    policy: OverflowPolicy,
+    budget: Option<Budget>,
+}
+
+/// Sends every input to all of the consumers, buffering for slow consumers
+///
+/// Consumers are stepped fairly, a single step each in turn, and
+/// a new input is read whenever any consumer is waiting for one.
+/// A consumer that sends many outputs per input will fall behind,
+/// so its inputs are buffered, up to capacity. The policy decides what
+/// happens when a buffer is full. Dropped inputs are reported as outputs,
+/// so they are visible to the executor.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// // slow sends two outputs per input, so falls behind fast
+/// fn slow<'a>() -> Coroutine<'a, i32, i32, ()> {
+///     receive().and_then(|i: i32| send(i).and_then(move |()| send(i))).and_then(|()| slow())
+/// }
+/// let fast: Coroutine<i32, i32, ()> = receive().and_then(|_| receive()).and_then(|_| receive()).and_then(|_| result(()));
+///
+/// let co = broadcast_all_bounded(vec![fast, slow()], 1, OverflowPolicy::DropNewest);
+/// let outputs = as_iterator(co, vec![1, 2, 3].into_iter()).collect::<Vec<_>>();
+/// assert!(outputs.contains(&BroadcastOutput::Dropped { consumer: 1, input: 3 }));
+/// ```
+pub fn broadcast_all_bounded<'a, I, O, R>(
+    consumers: Vec<Coroutine<'a, I, O, R>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+) -> Coroutine<'a, I, BroadcastOutput<I, O>, Vec<R>>
+where
+    I: Clone + Send + 'a,
+    O: Send,
+    R: Send,
+{
+    broadcast_bounded(Bounded::new(consumers, capacity, policy, None))
+}
+
+/// Bounded broadcast, where buffered inputs also count against a shared budget
+///
+/// Each buffered input uses a single unit of the budget. When the budget is
+/// used up, buffers are treated as full, and the policy applies.
+/// A blocking broadcast that has nothing of its own buffered will go over
+/// budget instead, as waiting would never free anything up.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// fn slow<'a>() -> Coroutine<'a, i32, i32, ()> {
+///     receive().and_then(|i: i32| send(i).and_then(move |()| send(i))).and_then(|()| slow())
+/// }
+/// let fast: Coroutine<i32, i32, ()> = receive().and_then(|_| receive()).and_then(|_| receive()).and_then(|_| result(()));
+///
+/// // the buffer could hold 10, but the budget has no room at all
+/// let budget = Budget::new(0);
+/// let co = broadcast_all_budgeted(vec![fast, slow()], 10, OverflowPolicy::DropNewest, budget);
+/// let outputs = as_iterator(co, vec![1, 2, 3].into_iter()).collect::<Vec<_>>();
+/// assert!(outputs.contains(&BroadcastOutput::Dropped { consumer: 1, input: 2 }));
+/// ```
+pub fn broadcast_all_budgeted<'a, I, O, R>(
+    consumers: Vec<Coroutine<'a, I, O, R>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    budget: Budget,
+) -> Coroutine<'a, I, BroadcastOutput<I, O>, Vec<R>>
+where
+    I: Clone + Send + 'a,
+    O: Send,
+    R: Send,
+{
+    broadcast_bounded(Bounded::new(consumers, capacity, policy, Some(budget)))
+}
+
+impl<'a, I, O, R> Bounded<'a, I, O, R> {
+    fn new(
+        consumers: Vec<Coroutine<'a, I, O, R>>,
+        capacity: usize,
+        policy: OverflowPolicy,
+        budget: Option<Budget>,
+    ) -> Self {
+        let queues = consumers.iter().map(|_| VecDeque::new()).collect();
+        let slots = consumers.into_iter().map(Slot::Running).collect();
+        Bounded {
+            slots,
+            queues,
+            cursor: 0,
+            capacity: capacity.max(1),
+            policy,
+            budget,
+        }
+    }
+
+    // Makes room for a single input in the queue, if there is any
+    fn reserve(&self, queue: &VecDeque<(I, Option<Reservation>)>) -> Option<Option<Reservation>> {
+        if queue.len() >= self.capacity {
+            return None;
+        }
+        match &self.budget {
+            Some(budget) => budget.try_reserve(1).map(Some),
+            None => Some(None),
+        }
+    }
+}
+
+fn broadcast_bounded<'a, I, O, R>(
+    mut state: Bounded<'a, I, O, R>,
+) -> Coroutine<'a, I, BroadcastOutput<I, O>, Vec<R>>
+where
+    I: Clone + Send + 'a,
+    O: Send,
+    R: Send,
+{
+    loop {
+        // A single step for each consumer, resuming from where we left off
+        while state.cursor < state.slots.len() {
+            let consumer = state.cursor;
+            state.cursor += 1;
+            let queue = &mut state.queues[consumer];
+            let output = slot::update(&mut state.slots, consumer, |slot| {
+                if !slot.is_awaiting() {
+                    return slot.step();
+                }
+                match queue.pop_front() {
+                    Some((input, _reservation)) => (slot.resume(input).0, None),
+                    None => (slot, None),
+                }
+            });
+            if state.slots[consumer].is_finished() {
+                state.queues[consumer].clear();
+            }
+            if let Some(output) = output {
+                let output = BroadcastOutput::Output { consumer, output };
+                return bind(send(output), move |()| broadcast_bounded(state));
+            }
+        }
+        state.cursor = 0;
+
+        if state.slots.iter().all(Slot::is_finished) {
+            return result(finished(state.slots));
+        }
+        let hungry = state
+            .slots
+            .iter()
+            .zip(state.queues.iter())
+            .any(|(slot, queue)| slot.is_awaiting() && queue.is_empty());
+        let full = state
+            .queues
+            .iter()
+            .any(|queue| queue.len() >= state.capacity);
+        let holding = state.queues.iter().any(|queue| !queue.is_empty());
+        let exhausted = state
+            .budget
+            .as_ref()
+            .is_some_and(|budget| budget.remaining() == 0);
+        let blocked = state.policy == OverflowPolicy::Block && (full || exhausted && holding);
+        if hungry && !blocked {
+            break;
+        }
+    }
+
+    let on_input = move |input: I| {
+        let mut dropped = Vec::new();
+        for consumer in 0..state.slots.len() {
+            if state.slots[consumer].is_finished() {
+                continue;
+            }
+            let input = input.clone();
+            let reservation = state.reserve(&state.queues[consumer]);
+            let queue = &mut state.queues[consumer];
+            match (reservation, state.policy) {
+                (Some(reservation), _) => queue.push_back((input, reservation)),
+                (None, OverflowPolicy::Block) => {
+                    let reservation = state.budget.as_ref().map(|budget| budget.reserve(1));
+                    queue.push_back((input, reservation));
+                }
+                (None, OverflowPolicy::DropOldest) => match queue.pop_front() {
+                    Some((oldest, reservation)) => {
+                        dropped.push(BroadcastOutput::Dropped {
+                            consumer,
+                            input: oldest,
+                        });
+                        queue.push_back((input, reservation));
+                    }
+                    None => dropped.push(BroadcastOutput::Dropped { consumer, input }),
+                },
+                (None, OverflowPolicy::DropNewest) => {
+                    dropped.push(BroadcastOutput::Dropped { consumer, input })
+                }
+            }
+        }
+        let dropped = dropped
+            .into_iter()
+            .fold(result(()), |co, output| right(co, send(output)));
+        bind(dropped, move |()| broadcast_bounded(state))
+    };
+    bind(receive(), on_input)
+}
+
+/// How merge_all chooses between coroutines that have outputs ready
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Take a single output from each coroutine in turn
+    RoundRobin,
+    /// Take every ready output from the lowest index first
+    Ordered,
+}
+
+/// Runs many coroutines, merging their outputs into one stream
+///
+/// Inputs are tagged with the index of the coroutine they are for.
+/// Inputs for coroutines that have finished, or don't exist, are dropped.
+/// The policy decides the order outputs are merged in when more than one
+/// coroutine has outputs ready. Completes with every result once all the
+/// coroutines have completed.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let twice = |i: i32| -> Coroutine<(), i32, ()> { send(i).and_then(move |()| send(i)) };
+///
+/// let co = merge_all(vec![twice(1), twice(2)], MergePolicy::RoundRobin);
+/// let outputs = as_iterator(co, std::iter::empty()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![1, 2, 1, 2]);
+///
+/// let co = merge_all(vec![twice(1), twice(2)], MergePolicy::Ordered);
+/// let outputs = as_iterator(co, std::iter::empty()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![1, 1, 2, 2]);
+/// ```
+pub fn merge_all<'a, I, O, R>(
+    producers: Vec<Coroutine<'a, I, O, R>>,
+    policy: MergePolicy,
+) -> Coroutine<'a, (usize, I), O, Vec<R>>
+where
+    O: Send,
+    R: Send,
+{
+    let slots = producers.into_iter().map(Slot::Running).collect();
+    merge_slots(slots, 0, policy)
+}
+
+fn merge_slots<'a, I, O, R>(
+    mut slots: Vec<Slot<'a, I, O, R>>,
+    cursor: usize,
+    policy: MergePolicy,
+) -> Coroutine<'a, (usize, I), O, Vec<R>>
+where
+    O: Send,
+    R: Send,
+{
+    let count = slots.len();
+    let start = match policy {
+        MergePolicy::RoundRobin => cursor,
+        MergePolicy::Ordered => 0,
+    };
+    for index in (start..start + count).map(|index| index % count) {
+        while let Slot::Running(_) = slots[index] {
+            if let Some(output) = slot::update(&mut slots, index, Slot::step) {
+                let cursor = (index + 1) % count;
+                return bind(send(output), move |()| merge_slots(slots, cursor, policy));
+            }
+        }
+    }
+
+    if slots.iter().all(Slot::is_finished) {
+        return result(finished(slots));
+    }
+    let on_input = move |(index, input): (usize, I)| {
+        if index < slots.len() {
+            slot::update(&mut slots, index, |slot| (slot.resume(input).0, ()));
+        }
+        merge_slots(slots, cursor, policy)
+    };
+    bind(receive(), on_input)
+}
+
+/// How a worker looks to a routing policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Worker {
+    /// Whether the worker is waiting for an input, rather than finished
+    pub available: bool,
+    /// Inputs given to the worker, less the outputs it has sent
+    ///
+    /// Assumes each input gets one output in reply
+    pub outstanding: usize,
+}
+
+/// Decides which worker balance gives each input to
+pub trait RoutingPolicy<I> {
+    /// Chooses the index of the worker for the input
+    ///
+    /// There is always at least one available worker. If an unavailable
+    /// worker is chosen, the next available one after it is used instead.
+    fn choose(&mut self, input: &I, workers: &[Worker]) -> usize;
+}
+
+/// Gives inputs to each worker in turn
+#[derive(Debug, Clone, Default)]
+pub struct RoundRobin {
+    next: usize,
+}
+
+impl<I> RoutingPolicy<I> for RoundRobin {
+    fn choose(&mut self, _: &I, workers: &[Worker]) -> usize {
+        let chosen = next_available(self.next, workers);
+        self.next = chosen + 1;
+        chosen
+    }
+}
+
+/// Gives inputs to workers in proportion to their weights
+///
+/// Inputs are spread out, rather than sent in bursts, so weights
+/// of 2 and 1 give the first worker every other input, not two in a row.
+/// Workers with no weight are only used if no others are available.
+#[derive(Debug, Clone)]
+pub struct Weighted {
+    weights: Vec<usize>,
+    current: Vec<i64>,
+}
+
+impl Weighted {
+    /// Weights for each worker, by index
+    pub fn new(weights: Vec<usize>) -> Self {
+        let current = vec![0; weights.len()];
+        Weighted { weights, current }
+    }
+}
+
+impl<I> RoutingPolicy<I> for Weighted {
+    fn choose(&mut self, _: &I, workers: &[Worker]) -> usize {
+        // smooth weighted round robin, each candidate gains its weight
+        // and the chosen one pays back the total
+        self.current.resize(workers.len(), 0);
+        let mut total = 0;
+        let mut chosen: Option<usize> = None;
+        for (index, worker) in workers.iter().enumerate() {
+            let weight = self.weights.get(index).copied().unwrap_or(0) as i64;
+            if !worker.available || weight == 0 {
+                continue;
+            }
+            self.current[index] += weight;
+            total += weight;
+            if chosen.is_none_or(|best| self.current[index] > self.current[best]) {
+                chosen = Some(index);
+            }
+        }
+        match chosen {
+            Some(index) => {
+                self.current[index] -= total;
+                index
+            }
+            None => next_available(0, workers),
+        }
+    }
+}
+
+/// Gives inputs to the worker with the fewest outstanding, lowest index first
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LeastOutstanding;
+
+impl<I> RoutingPolicy<I> for LeastOutstanding {
+    fn choose(&mut self, _: &I, workers: &[Worker]) -> usize {
+        workers
+            .iter()
+            .enumerate()
+            .filter(|(_, worker)| worker.available)
+            .min_by_key(|(_, worker)| worker.outstanding)
+            .map(|(index, _)| index)
+            .unwrap_or(0)
+    }
+}
+
+/// Gives inputs with the same key to the same worker
+///
+/// Useful when a worker keeps state for a key, such as a session.
+/// If that worker has finished, the next available one is used.
+#[derive(Debug, Clone)]
+pub struct HashByKey<F> {
+    key: F,
+}
+
+impl<F> HashByKey<F> {
+    /// Hashes the key from each input
+    pub fn new(key: F) -> Self {
+        HashByKey { key }
+    }
+}
+
+impl<I, K, F> RoutingPolicy<I> for HashByKey<F>
+where
+    F: Fn(&I) -> K,
+    K: Hash,
+{
+    fn choose(&mut self, input: &I, workers: &[Worker]) -> usize {
+        let mut hasher = DefaultHasher::new();
+        (self.key)(input).hash(&mut hasher);
+        (hasher.finish() % workers.len() as u64) as usize
+    }
+}
+
+// The first available worker from index, wrapping around
+fn next_available(index: usize, workers: &[Worker]) -> usize {
+    let count = workers.len();
+    (0..count)
+        .map(|offset| (index + offset) % count)
+        .find(|index| workers[*index].available)
+        .unwrap_or(0)
+}
+
+/// Gives each input to one of the workers, chosen by the policy
+///
+/// Outputs are tagged with the index of the worker that sent them.
+/// Completes with every result once all the workers have completed.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// // echos forever
+/// fn echo() -> Coroutine<'static, i32, i32, ()> {
+///     receive().and_then(send).and_then(|()| echo())
+/// }
+///
+/// let co = balance(vec![echo(), echo()], Weighted::new(vec![2, 1]));
+/// let outputs = as_iterator(co, vec![1, 2, 3].into_iter()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![(0, 1), (1, 2), (0, 3)]);
+/// ```
+pub fn balance<'a, I, O, R, P>(
+    workers: Vec<Coroutine<'a, I, O, R>>,
+    policy: P,
+) -> Coroutine<'a, I, (usize, O), Vec<R>>
+where
+    P: RoutingPolicy<I> + Send + 'a,
+    O: Send,
+    R: Send,
+{
+    let outstanding = vec![0; workers.len()];
+    let slots = workers.into_iter().map(Slot::Running).collect();
+    balance_slots(slots, outstanding, policy)
+}
+
+fn balance_slots<'a, I, O, R, P>(
+    mut slots: Vec<Slot<'a, I, O, R>>,
+    mut outstanding: Vec<usize>,
+    mut policy: P,
+) -> Coroutine<'a, I, (usize, O), Vec<R>>
+where
+    P: RoutingPolicy<I> + Send + 'a,
+    O: Send,
+    R: Send,
+{
+    for index in 0..slots.len() {
+        while let Slot::Running(_) = slots[index] {
+            if let Some(output) = slot::update(&mut slots, index, Slot::step) {
+                outstanding[index] = outstanding[index].saturating_sub(1);
+                let next = move |()| balance_slots(slots, outstanding, policy);
+                return bind(send((index, output)), next);
+            }
+        }
+    }
+
+    if slots.iter().all(Slot::is_finished) {
+        return result(finished(slots));
+    }
+    let on_input = move |input: I| {
+        let workers: Vec<Worker> = slots
+            .iter()
+            .zip(&outstanding)
+            .map(|(slot, outstanding)| Worker {
+                available: slot.is_awaiting(),
+                outstanding: *outstanding,
+            })
+            .collect();
+        let chosen = policy.choose(&input, &workers);
+        let chosen = next_available(chosen % workers.len(), &workers);
+        outstanding[chosen] += 1;
+        slot::update(&mut slots, chosen, |slot| (slot.resume(input).0, ()));
+        balance_slots(slots, outstanding, policy)
+    };
+    bind(receive(), on_input)
+}
+
+// Extracts the results, once every slot is finished
+fn finished<I, O, R>(slots: Vec<Slot<I, O, R>>) -> Vec<R> {
+    slots
+        .into_iter()
+        .filter_map(|slot| slot.into_result().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iterator::as_iterator;
+
+    type Co<I, O, R> = Coroutine<'static, I, O, R>;
+
+    // Sends each input three times, forever
+    fn chatty() -> Co<i32, i32, Vec<i32>> {
+        receive()
+            .and_then(|i: i32| {
+                send(i)
+                    .and_then(move |()| send(i))
+                    .and_then(move |()| send(i))
+            })
+            .and_then(|()| chatty())
+    }
+
+    fn take(n: usize) -> Co<i32, i32, Vec<i32>> {
+        fn go(n: usize, mut acc: Vec<i32>) -> Co<i32, i32, Vec<i32>> {
+            if n == 0 {
+                return result(acc);
+            }
+            receive().and_then(move |i| {
+                acc.push(i);
+                go(n - 1, acc)
+            })
+        }
+        go(n, Vec::new())
+    }
+
+    #[test]
+    fn broadcast_all_returns_results_in_order() {
+        let co = broadcast_all(vec![take(2), take(1)]);
+        let mut it = as_iterator(co, vec![1, 2].into_iter());
+        it.by_ref().for_each(drop);
+        let (value, _) = it.finish();
+        assert!(matches!(value, Ok(v) if v == vec![vec![1, 2], vec![1]]));
+    }
+
+    #[test]
+    fn merge_all_routes_inputs_by_index() {
+        let co = merge_all(vec![take(1), take(2)], MergePolicy::Ordered);
+        let inputs = vec![(1, 10), (5, 0), (0, 20), (1, 30)];
+        let mut it = as_iterator(co, inputs.into_iter());
+        it.by_ref().for_each(drop);
+        let (value, _) = it.finish();
+        assert!(matches!(value, Ok(v) if v == vec![vec![20], vec![10, 30]]));
+    }
+
+    #[test]
+    fn drop_oldest_keeps_latest_inputs() {
+        let co = broadcast_all_bounded(vec![take(4), chatty()], 1, OverflowPolicy::DropOldest);
+        let outputs = as_iterator(co, vec![1, 2, 3, 4].into_iter()).collect::<Vec<_>>();
+        let dropped = outputs
+            .iter()
+            .filter_map(|output| match output {
+                BroadcastOutput::Dropped { consumer, input } => Some((*consumer, *input)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(dropped, vec![(1, 2)]);
+    }
+
+    #[test]
+    fn budget_is_released_once_consumed() {
+        let budget = Budget::new(2);
+        let co = broadcast_all_budgeted(
+            vec![take(4), take(4)],
+            4,
+            OverflowPolicy::DropNewest,
+            budget.clone(),
+        );
+        let mut it = as_iterator(co, vec![1, 2, 3, 4].into_iter());
+        it.by_ref().for_each(drop);
+        let (value, _) = it.finish();
+        assert!(value.is_ok());
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn block_never_drops() {
+        let co = broadcast_all_bounded(vec![take(4), chatty()], 1, OverflowPolicy::Block);
+        let outputs = as_iterator(co, vec![1, 2, 3, 4].into_iter()).collect::<Vec<_>>();
+        let sent = outputs
+            .iter()
+            .filter(|output| matches!(output, BroadcastOutput::Output { consumer: 1, .. }))
+            .count();
+        assert_eq!(sent, 12);
+    }
+
+    #[test]
+    fn least_outstanding_spreads_unanswered_inputs() {
+        // replies only to even inputs
+        fn picky() -> Co<i32, i32, ()> {
+            receive()
+                .and_then(|i: i32| if i % 2 == 0 { send(i) } else { result(()) })
+                .and_then(|()| picky())
+        }
+        let co = balance(vec![picky(), picky()], LeastOutstanding);
+        let outputs = as_iterator(co, vec![1, 2, 4, 6].into_iter()).collect::<Vec<_>>();
+        // 1 is never answered, so the first worker stays busier
+        assert_eq!(outputs, vec![(1, 2), (1, 4), (1, 6)]);
+    }
+
+    #[test]
+    fn hash_by_key_keeps_keys_together() {
+        fn echo() -> Co<(u8, i32), (u8, i32), ()> {
+            receive().and_then(send).and_then(|()| echo())
+        }
+        let workers = vec![echo(), echo(), echo()];
+        let co = balance(workers, HashByKey::new(|input: &(u8, i32)| input.0));
+        let inputs = vec![(1, 0), (2, 0), (1, 1), (2, 1), (1, 2)];
+        let outputs = as_iterator(co, inputs.into_iter()).collect::<Vec<_>>();
+
+        let worker_for = |key: u8| {
+            let workers = outputs.iter().filter(|(_, (k, _))| *k == key);
+            workers.map(|(worker, _)| *worker).collect::<Vec<_>>()
+        };
+        assert!(worker_for(1).windows(2).all(|w| w[0] == w[1]));
+        assert!(worker_for(2).windows(2).all(|w| w[0] == w[1]));
+    }
+}
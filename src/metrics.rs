@@ -0,0 +1,108 @@
+//! Measurements of how coroutines are being run
+//!
+//! These are only compiled with the metrics feature,
+//! so hosts that don't need them don't pay for them.
+use std::time::Duration;
+
+// Bucket i holds durations under 2^i microseconds, the last catches the rest
+const BUCKETS: usize = 28;
+
+/// A histogram of latencies, in power of two buckets
+///
+/// Buckets are cheap to record into, and precise enough to tell
+/// microseconds from milliseconds from seconds.
+/// ```
+/// use bicoro::metrics::*;
+/// use std::time::Duration;
+///
+/// let mut latencies = LatencyHistogram::new();
+/// latencies.record(Duration::from_micros(3));
+/// latencies.record(Duration::from_millis(5));
+///
+/// assert_eq!(latencies.count(), 2);
+/// assert_eq!(latencies.max(), Duration::from_millis(5));
+/// assert!(latencies.percentile(0.5) <= Duration::from_micros(4));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            buckets: [0; BUCKETS],
+            count: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    /// An empty histogram
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a latency
+    pub fn record(&mut self, latency: Duration) {
+        let micros = latency.as_micros();
+        let bucket = (u128::BITS - micros.leading_zeros()) as usize;
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    /// How many latencies have been recorded
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The longest latency recorded
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// The average latency, or zero if there are none
+    pub fn mean(&self) -> Duration {
+        match u32::try_from(self.count) {
+            Ok(0) => Duration::ZERO,
+            Ok(count) => self.total / count,
+            Err(_) => Duration::from_secs_f64(self.total.as_secs_f64() / self.count as f64),
+        }
+    }
+
+    /// The upper bound of the bucket holding the quantile, from 0.0 to 1.0
+    ///
+    /// The last bucket has no upper bound, so the max is used instead
+    pub fn percentile(&self, quantile: f64) -> Duration {
+        let target = (quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target.max(1) {
+                return upper_bound(bucket).unwrap_or(self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Each bucket's upper bound, and how many latencies fell into it
+    ///
+    /// The last bucket has no upper bound
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .map(|(bucket, count)| (upper_bound(bucket), *count))
+    }
+}
+
+fn upper_bound(bucket: usize) -> Option<Duration> {
+    (bucket < BUCKETS - 1).then(|| Duration::from_micros(1 << bucket))
+}
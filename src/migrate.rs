@@ -0,0 +1,195 @@
+//! Adapters for moving from channel based async tasks to coroutines
+//!
+//! Actors are often written as an async task reading from one channel
+//! and writing to another. These adapters let such a task run as a coroutine,
+//! and a coroutine run as such a task, so a codebase can move one actor
+//! at a time. Only compiled with the futures feature.
+//! ```
+//! use bicoro::*;
+//! use bicoro::iterator::*;
+//! use bicoro::migrate::*;
+//! use futures::{SinkExt, StreamExt};
+//!
+//! // an existing actor, that doubles what it receives
+//! let doubler = from_task(1, |mut inputs, mut outputs| async move {
+//!     while let Some(i) = inputs.next().await {
+//!         if i == 0 {
+//!             return "stopped";
+//!         }
+//!         outputs.send(i * 2).await.unwrap();
+//!     }
+//!     "closed"
+//! });
+//!
+//! let mut it = as_iterator(doubler, vec![1, 2, 0].into_iter());
+//! assert_eq!(it.by_ref().collect::<Vec<i32>>(), vec![2, 4]);
+//! assert!(matches!(it.finish(), (Ok("stopped"), _)));
+//! ```
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{
+    channel::mpsc::{channel, Receiver, Sender},
+    future::poll_fn,
+    task::noop_waker,
+    StreamExt,
+};
+
+use crate::{bind, receive, result, right, run_step, send, suspend, Coroutine, StepResult};
+
+struct Task<'a, I, O, R> {
+    future: Pin<Box<dyn Future<Output = R> + Send + 'a>>,
+    inputs: Sender<I>,
+    // Inputs that didn't fit in the channel yet
+    pending: VecDeque<I>,
+    outputs: Receiver<O>,
+}
+
+/// Runs an async task, that reads and writes channels, as a coroutine
+///
+/// Inputs to the coroutine are sent to the task's receiver, and whatever
+/// the task sends is output by the coroutine. Each channel holds buffer
+/// messages. The task is polled without a real waker, so it should only
+/// wait on its channels. Anything else it waits on is only checked again
+/// when the next input arrives.
+pub fn from_task<'a, I, O, R, F, Fut>(buffer: usize, task: F) -> Coroutine<'a, I, O, R>
+where
+    F: FnOnce(Receiver<I>, Sender<O>) -> Fut,
+    Fut: Future<Output = R> + Send + 'a,
+    I: Send + 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    let (inputs, task_inputs) = channel(buffer);
+    let (task_outputs, outputs) = channel(buffer);
+    let future = Box::pin(task(task_inputs, task_outputs));
+    let task = Task {
+        future,
+        inputs,
+        pending: VecDeque::new(),
+        outputs,
+    };
+    drive_task(task)
+}
+
+fn drive_task<'a, I, O, R>(mut task: Task<'a, I, O, R>) -> Coroutine<'a, I, O, R>
+where
+    I: Send + 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    let mut fed = false;
+    while let Some(input) = task.pending.pop_front() {
+        if let Err(error) = task.inputs.try_send(input) {
+            // closed channels are never read, so the input is lost either way
+            if error.is_full() {
+                task.pending.push_front(error.into_inner());
+            }
+            break;
+        }
+        fed = true;
+    }
+
+    let waker = noop_waker();
+    let mut context = Context::from_waker(&waker);
+    let poll = task.future.as_mut().poll(&mut context);
+    let mut outputs = Vec::new();
+    while let Ok(output) = task.outputs.try_recv() {
+        outputs.push(output);
+    }
+    // The task may be parked on a full channel, with nothing to wake it
+    // but another poll, so keep polling while it makes progress
+    let progressed = !outputs.is_empty() || (fed && !task.pending.is_empty());
+    let sent = outputs
+        .into_iter()
+        .fold(result(()), |co, output| right(co, send(output)));
+
+    match poll {
+        Poll::Ready(value) => right(sent, result(value)),
+        Poll::Pending if progressed => bind(sent, move |()| drive_task(task)),
+        Poll::Pending => bind(sent, move |()| {
+            bind(receive(), move |input| {
+                task.pending.push_back(input);
+                drive_task(task)
+            })
+        }),
+    }
+}
+
+/// Runs a coroutine as an async task, reading and writing channels
+///
+/// Finishes with the result, or with the coroutine if the inputs close
+/// or the outputs are dropped first, so it can be continued some other way.
+/// ```
+/// use bicoro::*;
+/// use bicoro::migrate::*;
+/// use futures::{channel::mpsc::channel, executor::block_on, SinkExt, StreamExt};
+///
+/// let co: Coroutine<i32, i32, ()> = receive().and_then(|i: i32| send(i + 1));
+/// let (mut inputs, task_inputs) = channel(1);
+/// let (task_outputs, mut outputs) = channel(1);
+///
+/// block_on(async {
+///     inputs.send(41).await.unwrap();
+///     let value = into_task(co, task_inputs, task_outputs).await;
+///     assert!(matches!(value, Ok(())));
+///     assert_eq!(outputs.next().await, Some(42));
+/// });
+/// ```
+pub async fn into_task<'a, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+    mut inputs: Receiver<I>,
+    mut outputs: Sender<O>,
+) -> Result<R, Coroutine<'a, I, O, R>>
+where
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    let mut co = co;
+    loop {
+        match run_step(co) {
+            StepResult::Done(value) => return Ok(value),
+            StepResult::Yield { output, next } => {
+                let ready = poll_fn(|context| outputs.poll_ready(context)).await;
+                let sent = match ready {
+                    Ok(()) => outputs.try_send(output).map_err(|error| error.into_inner()),
+                    Err(_) => Err(output),
+                };
+                match sent {
+                    Ok(()) => co = *next,
+                    // keep the output, so the coroutine can be continued without losing it
+                    Err(output) => return Err(right(send(output), *next)),
+                }
+            }
+            StepResult::Next(next) => match inputs.next().await {
+                Some(input) => co = next(input),
+                None => return Err(suspend(next)),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iterator::as_iterator;
+    use futures::SinkExt;
+
+    #[test]
+    fn outputs_beyond_the_buffer_are_not_held_back() {
+        let burst = from_task(1, |mut inputs, mut outputs| async move {
+            while let Some(i) = inputs.next().await {
+                for offset in 0..5 {
+                    outputs.send(i + offset).await.unwrap();
+                }
+            }
+        });
+
+        let outputs = as_iterator(burst, vec![10].into_iter()).collect::<Vec<i32>>();
+        assert_eq!(outputs, vec![10, 11, 12, 13, 14]);
+    }
+}
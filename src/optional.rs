@@ -0,0 +1,133 @@
+//! Coroutines that may produce nothing
+//!
+//! OptionCoroutine wraps a coroutine whose result is an Option.
+//! Chaining works on the Some value, and a None short-circuits the rest.
+//! ```
+//! use bicoro::*;
+//! use bicoro::iterator::*;
+//! use bicoro::optional::*;
+//!
+//! // reads a key, then looks it up
+//! let co: OptionCoroutine<&str, (), i32> = lift(receive()).and_then(|key: &str| match key {
+//!     "answer" => some(42),
+//!     _ => none(),
+//! });
+//!
+//! let mut it = as_iterator(co.to_coroutine(), vec!["question"].into_iter());
+//! it.by_ref().for_each(drop);
+//! assert!(matches!(it.finish(), (Ok(None), _)));
+//! ```
+use crate::{bind_some, map, map_some, result, Coroutine};
+
+/// A coroutine that produces either Some R or None
+pub struct OptionCoroutine<'a, I, O, R> {
+    co: Coroutine<'a, I, O, Option<R>>,
+}
+
+impl<'a, I, O, R> From<Coroutine<'a, I, O, Option<R>>> for OptionCoroutine<'a, I, O, R> {
+    fn from(co: Coroutine<'a, I, O, Option<R>>) -> Self {
+        OptionCoroutine { co }
+    }
+}
+
+impl<'a, I, O, R> From<OptionCoroutine<'a, I, O, R>> for Coroutine<'a, I, O, Option<R>> {
+    fn from(co: OptionCoroutine<'a, I, O, R>) -> Self {
+        co.co
+    }
+}
+
+impl<'a, I, O, R> OptionCoroutine<'a, I, O, R> {
+    /// Wraps a coroutine that already returns an option
+    pub fn new(co: Coroutine<'a, I, O, Option<R>>) -> Self {
+        OptionCoroutine { co }
+    }
+
+    /// Unwraps into the plain coroutine
+    pub fn to_coroutine(self) -> Coroutine<'a, I, O, Option<R>> {
+        self.co
+    }
+
+    /// Continue with the Some value, or short-circuit with None
+    ///
+    /// see [bind_some](function@crate::bind_some)
+    pub fn and_then<F, B>(self, f: F) -> OptionCoroutine<'a, I, O, B>
+    where
+        F: FnOnce(R) -> OptionCoroutine<'a, I, O, B> + Send + 'a,
+    {
+        let co = bind_some(self.co, move |r| f(r).co);
+        OptionCoroutine { co }
+    }
+
+    /// Maps the Some value
+    ///
+    /// see [map_some](function@crate::map_some)
+    pub fn map<F, B>(self, f: F) -> OptionCoroutine<'a, I, O, B>
+    where
+        F: FnOnce(R) -> B + Send + 'a,
+    {
+        let co = map_some(self.co, f);
+        OptionCoroutine { co }
+    }
+
+    /// Turns None into the error, to continue as a failable coroutine
+    pub fn ok_or<E>(self, error: E) -> crate::failable::ResultCoroutine<'a, I, O, R, E>
+    where
+        E: Send + 'a,
+    {
+        let co = map(self.co, move |r| r.ok_or(error));
+        crate::failable::ResultCoroutine::new(co)
+    }
+}
+
+impl<'a, I, O, R> ::do_notation::Lift<R> for OptionCoroutine<'a, I, O, R> {
+    /// Creates a coroutine with Some value
+    ///
+    /// see [some](function@some)
+    fn lift(a: R) -> Self {
+        some(a)
+    }
+}
+
+/// A coroutine that has Some value
+/// ```
+/// use bicoro::optional::*;
+/// let co: OptionCoroutine<(), (), i32> = some(1);
+/// ```
+pub fn some<'a, I, O, R>(value: R) -> OptionCoroutine<'a, I, O, R> {
+    let co = result(Some(value));
+    OptionCoroutine { co }
+}
+
+/// A coroutine that has no value
+/// ```
+/// use bicoro::optional::*;
+/// let co: OptionCoroutine<(), (), i32> = none();
+/// ```
+pub fn none<'a, I, O, R>() -> OptionCoroutine<'a, I, O, R> {
+    let co = result(None);
+    OptionCoroutine { co }
+}
+
+/// Lifts a coroutine that always has a value
+/// ```
+/// use bicoro::*;
+/// use bicoro::optional::*;
+/// let co: OptionCoroutine<i32, (), i32> = lift(receive());
+/// ```
+pub fn lift<'a, I, O, R>(co: Coroutine<'a, I, O, R>) -> OptionCoroutine<'a, I, O, R> {
+    let co = map(co, Some);
+    OptionCoroutine { co }
+}
+
+/// Continue with the Some value, or short-circuit with None
+///
+/// The same as and_then, as a free function
+pub fn bind<'a, I, O, R, B, F>(
+    co: OptionCoroutine<'a, I, O, R>,
+    f: F,
+) -> OptionCoroutine<'a, I, O, B>
+where
+    F: FnOnce(R) -> OptionCoroutine<'a, I, O, B> + Send + 'a,
+{
+    co.and_then(f)
+}
@@ -0,0 +1,276 @@
+//! Helpers for the opening phases of a protocol
+//!
+//! Most protocols start with some agreement between the peers,
+//! before the main part of the protocol can run. These helpers
+//! describe those phases, so each protocol doesn't need to reinvent them.
+use crate::{
+    bind,
+    failable::{lift, ResultCoroutine},
+    map, receive, result, run_step, send, subroutine_result, suspend, Coroutine, StepResult,
+};
+
+/// Messages for a protocol that starts by agreeing on a version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Negotiated<V, M> {
+    /// The versions the sender supports
+    Versions(Vec<V>),
+    /// A message for the agreed protocol
+    Message(M),
+}
+
+/// Why negotiation failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NegotiationError<V> {
+    /// The peers have no versions in common
+    NoCommonVersion { ours: Vec<V>, theirs: Vec<V> },
+    /// A protocol message arrived before versions were agreed
+    UnexpectedMessage,
+    /// The peer sent versions again after they were agreed
+    UnexpectedVersions(Vec<V>),
+}
+
+/// Creates the protocol coroutine for a version
+pub type ProtocolFactory<'a, I, O, R> = Box<dyn FnOnce() -> Coroutine<'a, I, O, R> + Send + 'a>;
+
+/// Agrees on a version with the peer, then runs that version of the protocol
+///
+/// Both peers send the versions they support, and then pick the highest
+/// version they have in common. As both peers use the same rule, they will
+/// always agree without needing another round trip. The chosen protocol
+/// then runs with its messages wrapped in Negotiated::Message.
+/// ```
+/// use bicoro::*;
+/// use bicoro::protocol::*;
+/// use bicoro::testing::*;
+///
+/// fn peer<'a>(versions: Vec<u8>) -> bicoro::failable::ResultCoroutine<'a, Negotiated<u8, u8>, Negotiated<u8, u8>, u8, NegotiationError<u8>> {
+///     let protocols = versions
+///         .into_iter()
+///         .map(|v| {
+///             let protocol: ProtocolFactory<u8, u8, u8> = Box::new(move || result(v));
+///             (v, protocol)
+///         })
+///         .collect();
+///     negotiate(protocols)
+/// }
+///
+/// let run = loopback(peer(vec![1, 2, 3]).to_coroutine(), peer(vec![2, 3, 4]).to_coroutine());
+/// assert!(matches!(run.client, Ok(Ok(3))));
+/// assert!(matches!(run.server, Ok(Ok(3))));
+/// ```
+pub fn negotiate<'a, V, I, O, R>(
+    versions: Vec<(V, ProtocolFactory<'a, I, O, R>)>,
+) -> ResultCoroutine<'a, Negotiated<V, I>, Negotiated<V, O>, R, NegotiationError<V>>
+where
+    V: Ord + Clone + Send + 'a,
+    I: Send + 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    let ours: Vec<V> = versions
+        .iter()
+        .map(|(version, _)| version.clone())
+        .collect();
+    let hello = send(Negotiated::Versions(ours.clone()));
+    let exchange = bind(hello, |()| receive());
+    let co = bind(exchange, move |message| match message {
+        Negotiated::Message(_) => result(Err(NegotiationError::UnexpectedMessage)),
+        Negotiated::Versions(theirs) => {
+            let chosen = versions
+                .into_iter()
+                .filter(|(version, _)| theirs.contains(version))
+                .max_by(|(a, _), (b, _)| a.cmp(b));
+            match chosen {
+                Some((_, factory)) => run_negotiated(factory()).to_coroutine(),
+                None => result(Err(NegotiationError::NoCommonVersion { ours, theirs })),
+            }
+        }
+    });
+    ResultCoroutine::new(co)
+}
+
+// Runs the protocol, wrapping its messages, failing if the versions are resent
+fn run_negotiated<'a, V, I, O, R>(
+    protocol: Coroutine<'a, I, O, R>,
+) -> ResultCoroutine<'a, Negotiated<V, I>, Negotiated<V, O>, R, NegotiationError<V>>
+where
+    V: Send + 'a,
+    I: Send + 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    let on_input = || {
+        let on_message = |message| match message {
+            Negotiated::Message(input) => result(Ok(input)),
+            Negotiated::Versions(versions) => {
+                result(Err(NegotiationError::UnexpectedVersions(versions)))
+            }
+        };
+        bind(receive(), on_message)
+    };
+    let on_output = |output| bind(send(Negotiated::Message(output)), |()| result(None));
+    let protocol = lift(protocol).to_coroutine();
+    ResultCoroutine::new(subroutine_result(on_input, on_output, protocol))
+}
+
+/// Why a handshake failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandshakeError<E> {
+    /// The handshake itself failed
+    Failed(E),
+    /// The handshake needed more inputs than the timeout allowed
+    TimedOut,
+}
+
+/// Runs a handshake to get a session, before running the main protocol
+///
+/// The handshake can be limited to a number of inputs, so a peer that
+/// never finishes it can't keep the protocol waiting forever. If the handshake
+/// fails or times out, the cleanup coroutine is run before the error is returned,
+/// which is a good place to tell the peer why it was rejected.
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+/// use bicoro::iterator::*;
+/// use bicoro::protocol::*;
+///
+/// // the first input is a password, which must be right to get a session
+/// let login: ResultCoroutine<&str, &str, String, &str> = lift(receive()).and_then(|password| match password {
+///     "hunter2" => ok("alice".to_string()),
+///     _ => err("wrong password"),
+/// });
+/// let greet = |user: String| bind(receive(), move |input: &str| {
+///     let reply = format!("{} said {}", user, input);
+///     result(reply)
+/// });
+/// let co = Handshake::new(login)
+///     .timeout(3)
+///     .cleanup(send("goodbye"))
+///     .then(greet);
+///
+/// let mut it = as_iterator(co.to_coroutine(), vec!["letmein"].into_iter());
+/// assert_eq!(it.next(), Some("goodbye"));
+/// assert_eq!(it.next(), None);
+/// assert!(matches!(it.finish(), (Ok(Err(HandshakeError::Failed("wrong password"))), _)));
+/// ```
+pub struct Handshake<'a, I, O, S, E> {
+    handshake: ResultCoroutine<'a, I, O, S, E>,
+    timeout: Option<usize>,
+    cleanup: Coroutine<'a, I, O, ()>,
+}
+
+impl<'a, I, O, S, E> Handshake<'a, I, O, S, E>
+where
+    I: 'a,
+    O: Send + 'a,
+    S: Send + 'a,
+    E: Send + 'a,
+{
+    /// A handshake with no timeout and nothing to clean up
+    pub fn new(handshake: ResultCoroutine<'a, I, O, S, E>) -> Self {
+        Handshake {
+            handshake,
+            timeout: None,
+            cleanup: result(()),
+        }
+    }
+
+    /// Fails the handshake if it needs more than this many inputs
+    pub fn timeout(self, inputs: usize) -> Self {
+        Handshake {
+            timeout: Some(inputs),
+            ..self
+        }
+    }
+
+    /// Runs cleanup if the handshake fails or times out
+    pub fn cleanup(self, cleanup: Coroutine<'a, I, O, ()>) -> Self {
+        Handshake { cleanup, ..self }
+    }
+
+    /// Runs the handshake, then the main protocol with the session
+    pub fn then<R, F>(self, f: F) -> ResultCoroutine<'a, I, O, R, HandshakeError<E>>
+    where
+        F: FnOnce(S) -> Coroutine<'a, I, O, R> + Send + 'a,
+        R: Send + 'a,
+    {
+        let Handshake {
+            handshake,
+            timeout,
+            cleanup,
+        } = self;
+        let limited = with_timeout(handshake.to_coroutine(), timeout);
+        let co = bind(limited, move |session| match session {
+            Ok(session) => map(f(session), Ok),
+            Err(error) => bind(cleanup, move |()| result(Err(error))),
+        });
+        ResultCoroutine::new(co)
+    }
+}
+
+/// Runs the handshake to get a session, then runs the main protocol
+///
+/// This is Handshake without a timeout or cleanup, see [Handshake] for those
+pub fn handshake<'a, I, O, S, E, R, F>(
+    handshake: ResultCoroutine<'a, I, O, S, E>,
+    then: F,
+) -> ResultCoroutine<'a, I, O, R, HandshakeError<E>>
+where
+    I: 'a,
+    O: Send + 'a,
+    S: Send + 'a,
+    E: Send + 'a,
+    R: Send + 'a,
+    F: FnOnce(S) -> Coroutine<'a, I, O, R> + Send + 'a,
+{
+    Handshake::new(handshake).then(then)
+}
+
+// Runs the handshake, giving up if it wants more than remaining inputs
+fn with_timeout<'a, I, O, S, E>(
+    co: Coroutine<'a, I, O, Result<S, E>>,
+    remaining: Option<usize>,
+) -> Coroutine<'a, I, O, Result<S, HandshakeError<E>>>
+where
+    O: Send + 'a,
+    S: Send + 'a,
+    E: Send + 'a,
+{
+    match run_step(co) {
+        StepResult::Done(value) => result(value.map_err(HandshakeError::Failed)),
+        StepResult::Yield { output, next } => {
+            bind(send(output), move |()| with_timeout(*next, remaining))
+        }
+        StepResult::Next(next) => match remaining {
+            Some(0) => result(Err(HandshakeError::TimedOut)),
+            remaining => {
+                let remaining = remaining.map(|count| count - 1);
+                suspend(move |input| with_timeout(next(input), remaining))
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{failable::ok, iterator::as_iterator};
+
+    type Co<I, O, R> = Coroutine<'static, I, O, R>;
+
+    #[test]
+    fn slow_handshakes_time_out() {
+        let slow = lift(bind(receive(), |_: i32| receive())).and_then(ok::<_, _, i32, ()>);
+        let co = Handshake::new(slow)
+            .timeout(1)
+            .cleanup(send(-1))
+            .then(|session| -> Co<i32, i32, i32> { result(session) });
+
+        let mut it = as_iterator(co.to_coroutine(), vec![1, 2].into_iter());
+        assert_eq!(it.next(), Some(-1));
+        assert_eq!(it.next(), None);
+        let (value, inputs) = it.finish();
+        assert!(matches!(value, Ok(Err(HandshakeError::TimedOut))));
+        assert_eq!(inputs.map(|it| it.collect::<Vec<_>>()), Some(vec![2]));
+    }
+}
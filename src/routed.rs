@@ -1,6 +1,9 @@
 use crate::{
-    bind, inject, receive, result, right, run_step, send, suspend, Coroutine, StepResult,
-    UnicastSelect,
+    bind, inject,
+    mailbox::{Delivery, Mailbox},
+    map, receive, result, right, run_step, send,
+    slot::Slot,
+    suspend, Coroutine, StepResult, UnicastSelect,
 };
 
 pub enum RoutedResult<'a, IA, IB, O, RA, RB> {
@@ -14,6 +17,74 @@ pub enum RoutedResult<'a, IA, IB, O, RA, RB> {
     },
 }
 
+pub type RoutedRoutine<'a, IA, IB, O, RA, RB> =
+    Coroutine<'a, UnicastSelect<IA, IB>, O, RoutedResult<'a, IA, IB, O, RA, RB>>;
+/// The coroutines kept messaging each other, without sending or receiving
+///
+/// Both continuations are kept, so they can be inspected, or run again.
+/// The last message between them has already been delivered.
+pub struct Livelock<A, B> {
+    pub first: A,
+    pub second: B,
+}
+
+/// The continuations of routed coroutines that livelocked
+pub type RoutedLivelock<'a, IA, IB, O, RA, RB> = Livelock<
+    Coroutine<'a, IA, UnicastSelect<IB, O>, RA>,
+    Coroutine<'a, IB, UnicastSelect<IA, O>, RB>,
+>;
+
+/// Routed coroutines, that stop if they exchange too many messages in a row
+pub type BudgetedRoutine<'a, IA, IB, O, RA, RB> = Coroutine<
+    'a,
+    UnicastSelect<IA, IB>,
+    O,
+    Result<RoutedResult<'a, IA, IB, O, RA, RB>, RoutedLivelock<'a, IA, IB, O, RA, RB>>,
+>;
+
+/// How many messages can be exchanged before sending or receiving
+#[derive(Clone, Copy)]
+pub(crate) struct Exchanges {
+    budget: Option<usize>,
+    left: usize,
+}
+
+impl Exchanges {
+    pub(crate) fn unlimited() -> Self {
+        Exchanges {
+            budget: None,
+            left: 0,
+        }
+    }
+
+    pub(crate) fn limited(budget: usize) -> Self {
+        Exchanges {
+            budget: Some(budget),
+            left: budget,
+        }
+    }
+
+    /// Uses up an exchange, none if there were none left
+    pub(crate) fn exchange(self) -> Option<Self> {
+        match self.budget {
+            None => Some(self),
+            Some(_) if self.left == 0 => None,
+            Some(_) => Some(Exchanges {
+                left: self.left - 1,
+                ..self
+            }),
+        }
+    }
+
+    /// Refills the budget, after talking to the outside
+    pub(crate) fn reset(self) -> Self {
+        match self.budget {
+            None => self,
+            Some(budget) => Exchanges::limited(budget),
+        }
+    }
+}
+
 /// Chain and dispatch combined.
 ///
 /// This allows first and second corotines to 'talk to'
@@ -24,7 +95,79 @@ pub enum RoutedResult<'a, IA, IB, O, RA, RB> {
 pub fn routed<'a, IA, IB, O, RA, RB>(
     first: Coroutine<'a, IA, UnicastSelect<IB, O>, RA>,
     second: Coroutine<'a, IB, UnicastSelect<IA, O>, RB>,
-) -> Coroutine<'a, UnicastSelect<IA, IB>, O, RoutedResult<'a, IA, IB, O, RA, RB>>
+) -> RoutedRoutine<'a, IA, IB, O, RA, RB>
+where
+    IA: Send,
+    IB: Send,
+    O: Send,
+    RA: Send,
+    RB: Send,
+{
+    let co = route(first, second, Exchanges::unlimited());
+    map(co, |r| match r {
+        Ok(r) => r,
+        Err(_) => unreachable!("unlimited exchanges never livelock"),
+    })
+}
+
+/// Routed, but stops when the coroutines livelock
+///
+/// Routed coroutines can message each other forever, without sending
+/// an output or asking for an input, hanging whoever is running them.
+/// This allows at most budget exchanges between them in a row, and
+/// returns both continuations as a Livelock if they go over it.
+/// Sending an output or receiving an input refills the budget.
+/// ```
+/// use bicoro::*;
+///
+/// // replies to every message with another message
+/// fn ping() -> Coroutine<'static, (), UnicastSelect<(), ()>, ()> {
+///     send(UnicastSelect::Left(())).and_then(|()| receive()).and_then(|()| ping())
+/// }
+///
+/// let co = routed_with_budget(ping(), ping(), 100);
+/// let (_, value) = bicoro::executor::run_until_input(co);
+/// assert!(matches!(value, Ok(Err(Livelock { .. }))));
+/// ```
+pub fn routed_with_budget<'a, IA, IB, O, RA, RB>(
+    first: Coroutine<'a, IA, UnicastSelect<IB, O>, RA>,
+    second: Coroutine<'a, IB, UnicastSelect<IA, O>, RB>,
+    budget: usize,
+) -> BudgetedRoutine<'a, IA, IB, O, RA, RB>
+where
+    IA: Send,
+    IB: Send,
+    O: Send,
+    RA: Send,
+    RB: Send,
+{
+    route(first, second, Exchanges::limited(budget))
+}
+
+/// Carries on routing after a message between them, if the budget allows it
+fn exchange<'a, IA, IB, O, RA, RB>(
+    first: Coroutine<'a, IA, UnicastSelect<IB, O>, RA>,
+    second: Coroutine<'a, IB, UnicastSelect<IA, O>, RB>,
+    exchanges: Exchanges,
+) -> BudgetedRoutine<'a, IA, IB, O, RA, RB>
+where
+    IA: Send,
+    IB: Send,
+    O: Send,
+    RA: Send,
+    RB: Send,
+{
+    match exchanges.exchange() {
+        Some(exchanges) => route(first, second, exchanges),
+        None => result(Err(Livelock { first, second })),
+    }
+}
+
+fn route<'a, IA, IB, O, RA, RB>(
+    first: Coroutine<'a, IA, UnicastSelect<IB, O>, RA>,
+    second: Coroutine<'a, IB, UnicastSelect<IA, O>, RB>,
+    exchanges: Exchanges,
+) -> BudgetedRoutine<'a, IA, IB, O, RA, RB>
 where
     IA: Send,
     IB: Send,
@@ -34,42 +177,43 @@ where
 {
     let sr1 = run_step(first);
     let sr2 = run_step(second);
+    let outside = exchanges.reset();
 
     match (sr1, sr2) {
         (StepResult::Done(value), StepResult::Done(b)) => {
             let remain = result(b);
             let coop = RoutedResult::Left { value, remain };
-            result(coop)
+            result(Ok(coop))
         }
         (StepResult::Done(value), StepResult::Yield { output, next }) => match output {
             UnicastSelect::Left(i) => {
                 let remain = bind(send(UnicastSelect::Left(i)), |()| *next);
                 let coop = RoutedResult::Left { value, remain };
-                result(coop)
+                result(Ok(coop))
             }
             UnicastSelect::Right(o) => {
                 let remain = *next;
                 let output = send(o);
                 let coop = RoutedResult::Left { value, remain };
-                right(output, result(coop))
+                right(output, result(Ok(coop)))
             }
         },
         (StepResult::Done(value), StepResult::Next(input)) => {
             let remain = suspend(input);
             let coop = RoutedResult::Left { value, remain };
-            result(coop)
+            result(Ok(coop))
         }
         (StepResult::Yield { output, next }, StepResult::Done(value)) => match output {
             UnicastSelect::Left(i) => {
                 let remain = bind(send(UnicastSelect::Left(i)), |()| *next);
                 let coop = RoutedResult::Right { value, remain };
-                result(coop)
+                result(Ok(coop))
             }
             UnicastSelect::Right(o) => {
                 let output = send(o);
                 let remain = *next;
                 let coop = RoutedResult::Right { value, remain };
-                bind(output, |()| result(coop))
+                bind(output, |()| result(Ok(coop)))
             }
         },
         (
@@ -85,27 +229,27 @@ where
             (UnicastSelect::Left(c), UnicastSelect::Left(i)) => {
                 let first = inject(i, *na);
                 let second = inject(c, *nb);
-                routed(first, second)
+                exchange(first, second, exchanges)
             }
             (UnicastSelect::Left(c), UnicastSelect::Right(o)) => {
                 let output = send(o);
                 let first = *na;
                 let second = inject(c, *nb);
-                let next = |()| routed(first, second);
+                let next = move |()| route(first, second, outside);
                 bind(output, next)
             }
             (UnicastSelect::Right(o), UnicastSelect::Left(i)) => {
                 let output = send(o);
                 let first = inject(i, *na);
                 let second = *nb;
-                let next = |()| routed(first, second);
+                let next = move |()| route(first, second, outside);
                 bind(output, next)
             }
             (UnicastSelect::Right(oa), UnicastSelect::Right(ob)) => {
                 let output = right(send(oa), send(ob));
                 let first = *na;
                 let second = *nb;
-                let next = |()| routed(first, second);
+                let next = move |()| route(first, second, outside);
                 bind(output, next)
             }
         },
@@ -113,40 +257,40 @@ where
             UnicastSelect::Left(c) => {
                 let first = *next;
                 let second = input(c);
-                routed(first, second)
+                exchange(first, second, exchanges)
             }
             UnicastSelect::Right(o) => {
                 let output = send(o);
                 let first = *next;
                 let second = suspend(input);
-                let next = |()| routed(first, second);
+                let next = move |()| route(first, second, outside);
                 bind(output, next)
             }
         },
         (StepResult::Next(input), StepResult::Done(value)) => {
             let remain = suspend(input);
             let coop = RoutedResult::Right { value, remain };
-            result(coop)
+            result(Ok(coop))
         }
         (StepResult::Next(input), StepResult::Yield { output, next }) => match output {
             UnicastSelect::Left(i) => {
                 let first = input(i);
                 let second = *next;
-                routed(first, second)
+                exchange(first, second, exchanges)
             }
             UnicastSelect::Right(o) => {
                 let output = send(o);
 
-                let on_input = |i| match i {
+                let on_input = move |i| match i {
                     UnicastSelect::Left(ia) => {
                         let first = input(ia);
                         let second = *next;
-                        routed(first, second)
+                        route(first, second, outside)
                     }
                     UnicastSelect::Right(ib) => {
                         let first = suspend(input);
                         let second = inject(ib, *next);
-                        routed(first, second)
+                        route(first, second, outside)
                     }
                 };
                 let next = |()| bind(receive(), on_input);
@@ -154,19 +298,275 @@ where
             }
         },
         (StepResult::Next(input_a), StepResult::Next(input_b)) => {
-            let on_input = |i| match i {
+            let on_input = move |i| match i {
                 UnicastSelect::Left(ia) => {
                     let first = input_a(ia);
                     let second = suspend(input_b);
-                    routed(first, second)
+                    route(first, second, outside)
                 }
                 UnicastSelect::Right(ib) => {
                     let first = suspend(input_a);
                     let second = input_b(ib);
-                    routed(first, second)
+                    route(first, second, outside)
                 }
             };
             bind(receive(), on_input)
         }
     }
 }
+
+/// An output of a peer run by RoutedMany
+pub enum Route<K, M, O> {
+    /// A message for the peer at the address
+    To { address: K, message: M },
+    /// An output for outside the topology
+    External(O),
+}
+
+/// Why a topology can't be run, or stopped running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TopologyError<K> {
+    /// More than one peer claims the address
+    DuplicateAddress(K),
+    /// A peer talks to an address that no peer handles
+    MissingHandler { from: K, to: K },
+    /// A peer sent to an address it didn't declare it talks to
+    Undeclared { from: K, to: K },
+    /// An input arrived for an address that no peer handles
+    UnknownAddress(K),
+}
+
+/// A peer in a topology, receiving messages and sending routes
+pub type Peer<'a, K, M, O, R> = Coroutine<'a, M, Route<K, M, O>, R>;
+
+/// A running topology, returning each peer's result once they have all finished
+pub type RoutedManyRoutine<'a, K, M, O, R> =
+    Coroutine<'a, (K, M), O, Result<Vec<(K, R)>, TopologyError<K>>>;
+
+/// Builds a topology of many peers that route messages to each other
+///
+/// This is routed for any number of coroutines. Every peer has an address,
+/// and declares the addresses it talks to. The wiring is checked when built,
+/// so a missing or duplicated peer is an error up front, rather than
+/// messages going missing when it runs.
+/// ```
+/// use bicoro::*;
+///
+/// // the greeter passes names on to the shouter
+/// let greeter: Peer<&str, String, String, ()> = receive().and_then(|name: String| {
+///     send(Route::To { address: "shouter", message: format!("hello {}", name) })
+/// });
+/// let shouter: Peer<&str, String, String, ()> =
+///     receive().and_then(|s: String| send(Route::External(s.to_uppercase())));
+///
+/// let topology = RoutedMany::new()
+///     .peer("greeter", vec!["shouter"], greeter)
+///     .peer("shouter", vec![], shouter)
+///     .build()
+///     .expect("wiring is valid");
+///
+/// let inputs = vec![("greeter", "bob".to_string())];
+/// let outputs = bicoro::iterator::as_iterator(topology, inputs.into_iter()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec!["HELLO BOB".to_string()]);
+///
+/// // nobody handles the shouter's address here
+/// let missing = RoutedMany::<_, String, String, ()>::new()
+///     .peer("greeter", vec!["shouter"], receive().and_then(|_| result(())))
+///     .build();
+/// assert!(matches!(missing, Err(TopologyError::MissingHandler { from: "greeter", to: "shouter" })));
+/// ```
+pub struct RoutedMany<'a, K, M, O, R> {
+    addresses: Vec<K>,
+    talks_to: Vec<Vec<K>>,
+    peers: Vec<Peer<'a, K, M, O, R>>,
+}
+
+impl<'a, K, M, O, R> Default for RoutedMany<'a, K, M, O, R> {
+    fn default() -> Self {
+        RoutedMany {
+            addresses: Vec::new(),
+            talks_to: Vec::new(),
+            peers: Vec::new(),
+        }
+    }
+}
+
+impl<'a, K, M, O, R> RoutedMany<'a, K, M, O, R>
+where
+    K: PartialEq + Clone + Send + 'a,
+    M: Send + 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    /// A topology with no peers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a peer at the address, that sends to the talks_to addresses
+    pub fn peer(
+        mut self,
+        address: K,
+        talks_to: impl IntoIterator<Item = K>,
+        peer: Peer<'a, K, M, O, R>,
+    ) -> Self {
+        self.addresses.push(address);
+        self.talks_to.push(talks_to.into_iter().collect());
+        self.peers.push(peer);
+        self
+    }
+
+    /// Checks the wiring, and if it is valid, returns the running topology
+    ///
+    /// Each peer is driven until it waits, and messages between peers are
+    /// delivered before asking for another input, like the actor system.
+    /// Messages for peers that have finished are dropped.
+    pub fn build(self) -> Result<RoutedManyRoutine<'a, K, M, O, R>, TopologyError<K>> {
+        let addresses = self.addresses;
+        for (index, address) in addresses.iter().enumerate() {
+            if addresses[..index].contains(address) {
+                return Err(TopologyError::DuplicateAddress(address.clone()));
+            }
+        }
+        let mut talks_to = Vec::with_capacity(self.talks_to.len());
+        for (from, targets) in addresses.iter().zip(self.talks_to) {
+            let mut indexes = Vec::with_capacity(targets.len());
+            for to in targets {
+                match addresses.iter().position(|address| *address == to) {
+                    Some(index) => indexes.push(index),
+                    None => {
+                        let from = from.clone();
+                        return Err(TopologyError::MissingHandler { from, to });
+                    }
+                }
+            }
+            talks_to.push(indexes);
+        }
+        let slots = self.peers.into_iter().map(Slot::Running).collect();
+        let network = Network {
+            addresses,
+            talks_to,
+            mailbox: Mailbox::new(slots),
+        };
+        Ok(run_network(network))
+    }
+}
+
+struct Network<'a, K, M, O, R> {
+    addresses: Vec<K>,
+    talks_to: Vec<Vec<usize>>,
+    mailbox: Mailbox<'a, M, Route<K, M, O>, R>,
+}
+
+fn run_network<'a, K, M, O, R>(
+    mut network: Network<'a, K, M, O, R>,
+) -> RoutedManyRoutine<'a, K, M, O, R>
+where
+    K: PartialEq + Clone + Send + 'a,
+    M: Send + 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    let delivered = network.mailbox.settle(|index, route| match route {
+        Route::To { address, message } => {
+            let to = network.talks_to[index]
+                .iter()
+                .copied()
+                .find(|to| network.addresses[*to] == address);
+            match to {
+                Some(to) => Delivery::To(to, message),
+                None => {
+                    let from = network.addresses[index].clone();
+                    Delivery::Fail(TopologyError::Undeclared { from, to: address })
+                }
+            }
+        }
+        Route::External(output) => Delivery::External(output),
+    });
+    match delivered {
+        Some(Ok(output)) => return bind(send(output), move |()| run_network(network)),
+        Some(Err(error)) => return result(Err(error)),
+        None => {}
+    }
+
+    if network.mailbox.is_finished() {
+        let results = network
+            .addresses
+            .into_iter()
+            .zip(network.mailbox.slots)
+            .filter_map(|(address, slot)| slot.into_result().ok().map(|value| (address, value)))
+            .collect();
+        result(Ok(results))
+    } else {
+        let on_input = move |(address, message): (K, M)| match network
+            .addresses
+            .iter()
+            .position(|a| *a == address)
+        {
+            Some(index) => {
+                network.mailbox.post(index, message);
+                run_network(network)
+            }
+            None => result(Err(TopologyError::UnknownAddress(address))),
+        };
+        bind(receive(), on_input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iterator::as_iterator;
+
+    #[test]
+    fn undeclared_routes_stop_the_topology() {
+        let sneaky: Peer<u8, (), (), ()> = send(Route::To {
+            address: 2,
+            message: (),
+        });
+        let other: Peer<u8, (), (), ()> = receive();
+        let topology = RoutedMany::new()
+            .peer(1, vec![], sneaky)
+            .peer(2, vec![], other)
+            .build()
+            .ok()
+            .unwrap();
+
+        let mut it = as_iterator(topology, std::iter::empty());
+        assert!(it.next().is_none());
+        let (value, _) = it.finish();
+        assert!(matches!(
+            value,
+            Ok(Err(TopologyError::Undeclared { from: 1, to: 2 }))
+        ));
+    }
+
+    #[test]
+    fn outputs_refill_the_exchange_budget() {
+        type Side = Coroutine<'static, (), UnicastSelect<(), u8>, ()>;
+        // three messages to the other side, then one output, twice over
+        fn chatty(rounds: u8) -> Side {
+            if rounds == 0 {
+                return result(());
+            }
+            let talk = |()| send(UnicastSelect::Left(()));
+            let chat = bind(bind(bind(talk(()), talk), talk), move |()| {
+                send(UnicastSelect::Right(rounds))
+            });
+            bind(chat, move |()| chatty(rounds - 1))
+        }
+        fn listen() -> Side {
+            bind(receive(), |()| listen())
+        }
+
+        let co = routed_with_budget(chatty(2), listen(), 3);
+        let (outputs, value) = crate::executor::run_until_input(co);
+        assert_eq!(outputs, vec![2, 1]);
+        assert!(matches!(value, Ok(Ok(RoutedResult::Left { .. }))));
+
+        let co = routed_with_budget(chatty(2), listen(), 2);
+        let (outputs, value) = crate::executor::run_until_input(co);
+        assert!(outputs.is_empty());
+        assert!(matches!(value, Ok(Err(Livelock { .. }))));
+    }
+}
@@ -0,0 +1,529 @@
+//! Runs many independent coroutines, on a pool of threads or on this one
+//!
+//! Each coroutine spawned on the scheduler is a task, with its own queue
+//! of inputs. Worker threads take turns stepping whichever tasks have
+//! something to do, and everything the tasks output is merged into
+//! a single stream of events. This suits servers running a handler
+//! per session, where the handlers don't talk to each other.
+//! The LocalScheduler does the same on the current thread.
+//! ```
+//! use bicoro::*;
+//! use bicoro::scheduler::*;
+//!
+//! // greets whoever connects
+//! let greeter = || receive().and_then(|name: String| send(format!("hello {}", name)));
+//!
+//! let scheduler = Scheduler::new(2);
+//! let alice = scheduler.spawn(greeter());
+//! let bob = scheduler.spawn(greeter());
+//! scheduler.send(bob, "bob".to_string()).unwrap();
+//! scheduler.send(alice, "alice".to_string()).unwrap();
+//!
+//! let mut greetings = Vec::new();
+//! for event in scheduler.events().iter().take(4) {
+//!     if let Event::Output { task, output } = event {
+//!         greetings.push((task, output));
+//!     }
+//! }
+//! greetings.sort_by_key(|(task, _)| task.id());
+//! assert_eq!(greetings, vec![(alice, "hello alice".to_string()), (bob, "hello bob".to_string())]);
+//! ```
+use std::{
+    collections::{HashMap, VecDeque},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{run_step, slot::Slot, suspend, Coroutine, StepResult};
+
+/// How many steps a worker takes on a task before giving others a turn
+const STEPS_PER_TURN: usize = 64;
+
+/// A coroutine running on a scheduler
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TaskId(usize);
+
+impl TaskId {
+    /// The index of the task, in spawn order
+    pub fn id(&self) -> usize {
+        self.0
+    }
+}
+
+impl From<usize> for TaskId {
+    fn from(id: usize) -> Self {
+        TaskId(id)
+    }
+}
+
+/// Something a task did, merged from every task in the scheduler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event<O, R> {
+    /// The task sent an output
+    Output { task: TaskId, output: O },
+    /// The task finished with a result
+    Finished { task: TaskId, result: R },
+    /// The task panicked, and was dropped
+    Panicked { task: TaskId },
+}
+
+enum TaskState<I: 'static, O: 'static, R: 'static> {
+    /// Has steps to take, and is queued for a worker
+    Ready(Coroutine<'static, I, O, R>),
+    /// Waiting for an input to be sent to it
+    Waiting(Coroutine<'static, I, O, R>),
+    /// A worker is stepping it
+    Running,
+}
+
+struct Task<I: 'static, O: 'static, R: 'static> {
+    state: TaskState<I, O, R>,
+    inputs: VecDeque<I>,
+}
+
+struct State<I: 'static, O: 'static, R: 'static> {
+    // Tasks are removed once they finish, so only unfinished ids are here
+    tasks: HashMap<usize, Task<I, O, R>>,
+    next: usize,
+    ready: VecDeque<usize>,
+    stopping: bool,
+}
+
+struct Shared<I: 'static, O: 'static, R: 'static> {
+    state: Mutex<State<I, O, R>>,
+    work: Condvar,
+}
+
+impl<I: 'static, O: 'static, R: 'static> Shared<I, O, R> {
+    fn lock(&self) -> MutexGuard<'_, State<I, O, R>> {
+        // A panicking task only loses itself, the queues are still whole
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A pool of worker threads, stepping the tasks spawned on it
+///
+/// Dropping the scheduler stops the workers once they finish their
+/// current turn. Tasks that haven't finished are dropped with it.
+/// A task that panics is dropped, and reported as [Event::Panicked],
+/// without stopping the worker or any other task.
+pub struct Scheduler<I: 'static, O: 'static, R: 'static> {
+    shared: Arc<Shared<I, O, R>>,
+    workers: Vec<JoinHandle<()>>,
+    events: Receiver<Event<O, R>>,
+}
+
+impl<I, O, R> Scheduler<I, O, R>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+    R: Send + 'static,
+{
+    /// Starts a scheduler, with at least one worker thread
+    pub fn new(workers: usize) -> Self {
+        let state = State {
+            tasks: HashMap::new(),
+            next: 0,
+            ready: VecDeque::new(),
+            stopping: false,
+        };
+        let shared = Arc::new(Shared {
+            state: Mutex::new(state),
+            work: Condvar::new(),
+        });
+        let (sender, events) = channel();
+        let workers = (0..workers.max(1))
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                let sender = sender.clone();
+                thread::spawn(move || work(&shared, &sender))
+            })
+            .collect();
+        Scheduler {
+            shared,
+            workers,
+            events,
+        }
+    }
+
+    /// Adds a coroutine, that starts running straight away
+    pub fn spawn(&self, co: Coroutine<'static, I, O, R>) -> TaskId {
+        let mut state = self.shared.lock();
+        let id = state.next;
+        state.next += 1;
+        let task = Task {
+            state: TaskState::Ready(co),
+            inputs: VecDeque::new(),
+        };
+        state.tasks.insert(id, task);
+        state.ready.push_back(id);
+        drop(state);
+        self.shared.work.notify_one();
+        TaskId(id)
+    }
+
+    /// Queues an input for the task
+    ///
+    /// The input is handed back if the task has already finished
+    pub fn send(&self, task: TaskId, input: I) -> Result<(), I> {
+        let mut state = self.shared.lock();
+        let state = &mut *state;
+        let entry = match state.tasks.get_mut(&task.0) {
+            Some(entry) => entry,
+            None => return Err(input),
+        };
+        match std::mem::replace(&mut entry.state, TaskState::Running) {
+            TaskState::Waiting(co) => {
+                entry.state = TaskState::Ready(co);
+                state.ready.push_back(task.0);
+                self.shared.work.notify_one();
+            }
+            other => entry.state = other,
+        }
+        entry.inputs.push_back(input);
+        Ok(())
+    }
+
+    /// The outputs and results of every task, in the order they happened
+    pub fn events(&self) -> &Receiver<Event<O, R>> {
+        &self.events
+    }
+
+    /// Whether the task has finished, or panicked
+    pub fn is_finished(&self, task: TaskId) -> bool {
+        let state = self.shared.lock();
+        task.0 < state.next && !state.tasks.contains_key(&task.0)
+    }
+}
+
+impl<I: 'static, O: 'static, R: 'static> Drop for Scheduler<I, O, R> {
+    fn drop(&mut self) {
+        self.shared.lock().stopping = true;
+        self.shared.work.notify_all();
+        for worker in self.workers.drain(..) {
+            // Task panics are caught by the worker, so this can't fail
+            let _ = worker.join();
+        }
+    }
+}
+
+/// A worker's loop, taking turns on ready tasks until the scheduler stops
+fn work<I: 'static, O: 'static, R: 'static>(
+    shared: &Shared<I, O, R>,
+    events: &Sender<Event<O, R>>,
+) {
+    loop {
+        let (id, co) = {
+            let mut state = shared.lock();
+            loop {
+                if state.stopping {
+                    return;
+                }
+                if let Some(id) = state.ready.pop_front() {
+                    let Some(entry) = state.tasks.get_mut(&id) else {
+                        continue;
+                    };
+                    match std::mem::replace(&mut entry.state, TaskState::Running) {
+                        TaskState::Ready(co) => break (id, co),
+                        other => entry.state = other,
+                    }
+                } else {
+                    state = shared
+                        .work
+                        .wait(state)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                }
+            }
+        };
+        let turn = panic::catch_unwind(AssertUnwindSafe(|| take_turn(shared, events, id, co)));
+        if turn.is_err() {
+            // The coroutine was lost with the panic, so the task can't go on
+            shared.lock().tasks.remove(&id);
+            let _ = events.send(Event::Panicked { task: TaskId(id) });
+        }
+    }
+}
+
+/// Steps the task for a turn, then puts it back where it belongs
+fn take_turn<I: 'static, O: 'static, R: 'static>(
+    shared: &Shared<I, O, R>,
+    events: &Sender<Event<O, R>>,
+    id: usize,
+    mut co: Coroutine<'static, I, O, R>,
+) {
+    let task = TaskId(id);
+    for _ in 0..STEPS_PER_TURN {
+        match run_step(co) {
+            StepResult::Done(result) => {
+                shared.lock().tasks.remove(&id);
+                // Nobody is listening if the scheduler is being dropped
+                let _ = events.send(Event::Finished { task, result });
+                return;
+            }
+            StepResult::Yield { output, next } => {
+                let _ = events.send(Event::Output { task, output });
+                co = *next;
+            }
+            StepResult::Next(next) => {
+                let mut state = shared.lock();
+                let entry = task_entry(&mut state, id);
+                match entry.inputs.pop_front() {
+                    Some(input) => {
+                        drop(state);
+                        co = next(input);
+                    }
+                    None => {
+                        entry.state = TaskState::Waiting(suspend(next));
+                        return;
+                    }
+                }
+            }
+        }
+    }
+    let mut state = shared.lock();
+    task_entry(&mut state, id).state = TaskState::Ready(co);
+    state.ready.push_back(id);
+    drop(state);
+    shared.work.notify_one();
+}
+
+// Only the worker running a task removes it, so it is there for the whole turn
+fn task_entry<I: 'static, O: 'static, R: 'static>(
+    state: &mut State<I, O, R>,
+    id: usize,
+) -> &mut Task<I, O, R> {
+    state
+        .tasks
+        .get_mut(&id)
+        .expect("a running task is never removed")
+}
+
+/// A task on a local scheduler, and the inputs queued for it
+struct Local<'a, I, O, R> {
+    slot: Slot<'a, I, O, R>,
+    inputs: VecDeque<I>,
+}
+
+/// Runs many coroutines on the current thread, taking turns
+///
+/// Each round, every task takes a single step, so a task that never
+/// waits for input can't starve the others. Tasks waiting for input take
+/// the next one from their own queue. Outputs and results are tagged with
+/// the task they came from.
+pub struct LocalScheduler<'a, I, O, R> {
+    tasks: Vec<Option<Local<'a, I, O, R>>>,
+}
+
+/// Starts a local scheduler, running the coroutines in turn
+///
+/// The coroutines get task ids in the order they are given
+/// ```
+/// use bicoro::*;
+/// use bicoro::scheduler::*;
+///
+/// let echo = || receive().and_then(|i: i32| send(i));
+/// let mut scheduler = round_robin(vec![echo(), echo()]);
+///
+/// let late = scheduler.spawn(send(0));
+/// scheduler.send(TaskId::from(1), 2).unwrap();
+///
+/// let events = scheduler.run_until_idle();
+/// assert_eq!(events, vec![
+///     Event::Output { task: late, output: 0 },
+///     Event::Output { task: TaskId::from(1), output: 2 },
+///     Event::Finished { task: late, result: () },
+///     Event::Finished { task: TaskId::from(1), result: () },
+/// ]);
+///
+/// // the first echo is still waiting, and can be taken back
+/// assert!(scheduler.remove(TaskId::from(0)).is_some());
+/// assert!(scheduler.is_empty());
+/// ```
+pub fn round_robin<'a, I, O, R>(
+    coroutines: Vec<Coroutine<'a, I, O, R>>,
+) -> LocalScheduler<'a, I, O, R> {
+    let mut scheduler = LocalScheduler { tasks: Vec::new() };
+    for co in coroutines {
+        scheduler.spawn(co);
+    }
+    scheduler
+}
+
+impl<'a, I, O, R> LocalScheduler<'a, I, O, R> {
+    /// Adds a coroutine, that takes its first step in the next round
+    pub fn spawn(&mut self, co: Coroutine<'a, I, O, R>) -> TaskId {
+        self.tasks.push(Some(Local {
+            slot: Slot::Running(co),
+            inputs: VecDeque::new(),
+        }));
+        TaskId(self.tasks.len() - 1)
+    }
+
+    /// Takes the task off the scheduler, with the inputs it hadn't read
+    ///
+    /// Returns none if the task has finished, or was already removed
+    pub fn remove(&mut self, task: TaskId) -> Option<(Coroutine<'a, I, O, R>, VecDeque<I>)> {
+        let local = self.tasks.get_mut(task.0)?.take()?;
+        // Finished tasks are never kept, so this is always a coroutine
+        let co = local.slot.into_result().err()?;
+        Some((co, local.inputs))
+    }
+
+    /// Queues an input for the task
+    ///
+    /// The input is handed back if the task isn't on the scheduler
+    pub fn send(&mut self, task: TaskId, input: I) -> Result<(), I> {
+        match self.tasks.get_mut(task.0) {
+            Some(Some(local)) => {
+                local.inputs.push_back(input);
+                Ok(())
+            }
+            _ => Err(input),
+        }
+    }
+
+    /// Whether every task has finished, or been removed
+    pub fn is_empty(&self) -> bool {
+        self.tasks.iter().all(Option::is_none)
+    }
+
+    /// Whether no task can take a step, until it is sent an input
+    pub fn is_idle(&self) -> bool {
+        self.tasks
+            .iter()
+            .flatten()
+            .all(|local| local.slot.is_awaiting() && local.inputs.is_empty())
+    }
+
+    /// Runs a single round, where every task that can takes one step
+    pub fn tick(&mut self) -> Vec<Event<O, R>> {
+        (0..self.tasks.len())
+            .filter_map(|id| self.step_task(id))
+            .collect()
+    }
+
+    /// Runs rounds until every task is waiting for input, or has finished
+    ///
+    /// This never returns if a task runs forever without waiting
+    pub fn run_until_idle(&mut self) -> Vec<Event<O, R>> {
+        let mut events = Vec::new();
+        while !self.is_idle() {
+            events.extend(self.tick());
+        }
+        events
+    }
+
+    fn step_task(&mut self, id: usize) -> Option<Event<O, R>> {
+        let task = TaskId(id);
+        let mut local = self.tasks[id].take()?;
+        if local.slot.is_awaiting() {
+            match local.inputs.pop_front() {
+                Some(input) => local.slot = local.slot.resume(input).0,
+                None => {
+                    self.tasks[id] = Some(local);
+                    return None;
+                }
+            }
+        }
+        let (slot, output) = local.slot.step();
+        match slot {
+            Slot::Finished(result) => Some(Event::Finished { task, result }),
+            slot => {
+                local.slot = slot;
+                self.tasks[id] = Some(local);
+                output.map(|output| Event::Output { task, output })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bind, receive, send};
+
+    #[test]
+    fn busy_tasks_take_turns() {
+        // counts forever, without ever waiting for an input
+        fn count(from: u64) -> Coroutine<'static, (), u64, ()> {
+            bind(send(from), move |()| count(from + 1))
+        }
+        let quick = || bind(receive(), |()| send(0));
+
+        let scheduler = Scheduler::new(1);
+        scheduler.spawn(count(0));
+        let waiting = scheduler.spawn(quick());
+        scheduler.send(waiting, ()).unwrap();
+
+        let finished = scheduler.events().iter().any(|event| {
+            event
+                == Event::Finished {
+                    task: waiting,
+                    result: (),
+                }
+        });
+        assert!(finished);
+        assert!(scheduler.is_finished(waiting));
+        assert_eq!(scheduler.send(waiting, ()), Err(()));
+    }
+
+    #[test]
+    fn panicking_tasks_are_dropped_without_stopping_the_worker() {
+        let panics: Coroutine<'static, (), (), ()> =
+            bind(receive(), |()| panic!("a task went wrong"));
+        let quick = || bind(receive(), |()| send(()));
+
+        let scheduler = Scheduler::new(1);
+        let broken = scheduler.spawn(panics);
+        let working = scheduler.spawn(quick());
+        scheduler.send(broken, ()).unwrap();
+        scheduler.send(working, ()).unwrap();
+
+        let events = scheduler.events().iter().take(3).collect::<Vec<_>>();
+        assert!(events.contains(&Event::Panicked { task: broken }));
+        assert!(events.contains(&Event::Finished {
+            task: working,
+            result: ()
+        }));
+        assert!(scheduler.is_finished(broken));
+        assert_eq!(scheduler.send(broken, ()), Err(()));
+        assert!(scheduler.shared.lock().tasks.is_empty());
+    }
+
+    #[test]
+    fn local_tasks_step_once_per_round() {
+        fn count(from: u64) -> Coroutine<'static, (), u64, ()> {
+            bind(send(from), move |()| count(from + 1))
+        }
+        let mut scheduler = round_robin(vec![count(0), count(10)]);
+
+        let rounds = (0..4).flat_map(|_| scheduler.tick()).collect::<Vec<_>>();
+        let outputs = rounds
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Output { task, output } => Some((task.id(), output)),
+                Event::Finished { .. } | Event::Panicked { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outputs,
+            vec![
+                (0, 0),
+                (1, 10),
+                (0, 1),
+                (1, 11),
+                (0, 2),
+                (1, 12),
+                (0, 3),
+                (1, 13)
+            ]
+        );
+        assert!(!scheduler.is_idle());
+    }
+}
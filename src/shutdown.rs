@@ -0,0 +1,325 @@
+//! Shutting down compositions of coroutines cleanly
+//!
+//! Each branch receives [Signal]s, so it can react to a shutdown itself,
+//! such as by sending a goodbye before it finishes. The `_with_shutdown`
+//! combinators give a shutdown to every branch, and wait for all of them
+//! to finish, collecting their results.
+use crate::{
+    bind, dispatch, dispatch::only, inject, map, map_input, map_output, receive, result, routed,
+    run_step, send, Coroutine, DispatchResult, RoutedResult, Select, StepResult, UnicastSelect,
+};
+
+/// An input, or a request to shut down
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signal<I> {
+    /// An ordinary input
+    Input(I),
+    /// The coroutine should finish up
+    Shutdown,
+}
+
+// Something sent to both branches of a dispatch
+#[derive(Clone)]
+enum Both<I> {
+    Input(I),
+    Shutdown,
+}
+
+impl<I, X: Into<I>> From<Both<X>> for Signal<I> {
+    fn from(both: Both<X>) -> Self {
+        match both {
+            Both::Input(input) => Signal::Input(input.into()),
+            Both::Shutdown => Signal::Shutdown,
+        }
+    }
+}
+
+// The shutdown, which is the only thing a unicast sends to both
+#[derive(Clone)]
+struct ShutdownBoth;
+
+impl<I> From<ShutdownBoth> for Signal<I> {
+    fn from(_: ShutdownBoth) -> Self {
+        Signal::Shutdown
+    }
+}
+
+/// Two coroutines that are shut down together, finishing with both results
+pub type ShutdownRoutine<'a, I, OA, OB, A, B> =
+    Coroutine<'a, Signal<I>, UnicastSelect<OA, OB>, (A, B)>;
+
+type Dispatched<'a, In, IA, IB, OA, OB, A, B> = Coroutine<
+    'a,
+    In,
+    UnicastSelect<OA, OB>,
+    DispatchResult<'a, Signal<IA>, Signal<IB>, OA, OB, A, B>,
+>;
+
+// Once one branch finishes, runs the other on just its inputs
+fn finish_both<'a, In, IA, IB, OA, OB, A, B>(
+    both: Dispatched<'a, In, IA, IB, OA, OB, A, B>,
+    pick_a: fn(In) -> Option<Signal<IA>>,
+    pick_b: fn(In) -> Option<Signal<IB>>,
+) -> Coroutine<'a, In, UnicastSelect<OA, OB>, (A, B)>
+where
+    In: 'a,
+    IA: 'a,
+    IB: 'a,
+    OA: Send + 'a,
+    OB: Send + 'a,
+    A: Send + 'a,
+    B: Send + 'a,
+{
+    bind(both, move |finished| match finished {
+        DispatchResult::Left { value, remaining } => {
+            let remaining = map_output(only(remaining, pick_b), UnicastSelect::Right);
+            map(remaining, move |b| (value, b))
+        }
+        DispatchResult::Right { value, remaining } => {
+            let remaining = map_output(only(remaining, pick_a), UnicastSelect::Left);
+            map(remaining, move |a| (a, value))
+        }
+    })
+}
+
+/// Dispatch, where a shutdown is given to both coroutines
+///
+/// see [dispatch](function@crate::dispatch). Once one finishes, the
+/// other is run until it finishes too, so both results are returned.
+pub fn dispatch_with_shutdown<'a, IA, IB, IAB, OA, OB, A, B>(
+    first: Coroutine<'a, Signal<IA>, OA, A>,
+    second: Coroutine<'a, Signal<IB>, OB, B>,
+) -> ShutdownRoutine<'a, Select<IA, IB, IAB>, OA, OB, A, B>
+where
+    IA: 'a,
+    IB: 'a,
+    IAB: Into<IA> + Into<IB> + Clone + 'a,
+    OA: Send + 'a,
+    OB: Send + 'a,
+    A: Send + 'a,
+    B: Send + 'a,
+{
+    let both = map_input(dispatch(first, second), |signal| match signal {
+        Signal::Input(Select::Left(a)) => Select::Left(Signal::Input(a)),
+        Signal::Input(Select::Right(b)) => Select::Right(Signal::Input(b)),
+        Signal::Input(Select::Both(ab)) => Select::Both(Both::Input(ab)),
+        Signal::Shutdown => Select::Both(Both::Shutdown),
+    });
+    let pick_a = |signal: Signal<Select<IA, IB, IAB>>| match signal {
+        Signal::Input(Select::Left(a)) => Some(Signal::Input(a)),
+        Signal::Input(Select::Both(ab)) => Some(Signal::Input(ab.into())),
+        Signal::Input(Select::Right(_)) => None,
+        Signal::Shutdown => Some(Signal::Shutdown),
+    };
+    let pick_b = |signal: Signal<Select<IA, IB, IAB>>| match signal {
+        Signal::Input(Select::Right(b)) => Some(Signal::Input(b)),
+        Signal::Input(Select::Both(ab)) => Some(Signal::Input(ab.into())),
+        Signal::Input(Select::Left(_)) => None,
+        Signal::Shutdown => Some(Signal::Shutdown),
+    };
+    finish_both(both, pick_a, pick_b)
+}
+
+/// Broadcast, where a shutdown is given to both coroutines
+///
+/// see [broadcast](function@crate::broadcast)
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// // echoes until shut down, then says goodbye
+/// let echo = |name: &'static str| -> Coroutine<Signal<i32>, String, &str> {
+///     receive().and_then(move |signal| match signal {
+///         Signal::Input(i) => send(format!("{name} {i}")).and_then(move |()| receive()).and_then(move |_| result(name)),
+///         Signal::Shutdown => send(format!("{name} bye")).and_then(move |()| result(name)),
+///     })
+/// };
+///
+/// let co = broadcast_with_shutdown(echo("a"), echo("b"));
+/// let mut it = as_iterator(co, vec![Signal::Shutdown].into_iter());
+/// assert_eq!(it.by_ref().count(), 2);
+/// assert!(matches!(it.finish(), (Ok(("a", "b")), _)));
+/// ```
+pub fn broadcast_with_shutdown<'a, I, OA, OB, A, B>(
+    first: Coroutine<'a, Signal<I>, OA, A>,
+    second: Coroutine<'a, Signal<I>, OB, B>,
+) -> ShutdownRoutine<'a, I, OA, OB, A, B>
+where
+    I: Clone + 'a,
+    OA: Send + 'a,
+    OB: Send + 'a,
+    A: Send + 'a,
+    B: Send + 'a,
+{
+    let both = map_input(dispatch(first, second), |signal| match signal {
+        Signal::Input(input) => Select::Both(Both::Input(input)),
+        Signal::Shutdown => Select::Both(Both::Shutdown),
+    });
+    finish_both(both, Some, Some)
+}
+
+/// Unicast, where a shutdown is given to both coroutines
+///
+/// see [unicast](function@crate::unicast)
+pub fn unicast_with_shutdown<'a, IA, IB, OA, OB, A, B>(
+    first: Coroutine<'a, Signal<IA>, OA, A>,
+    second: Coroutine<'a, Signal<IB>, OB, B>,
+) -> ShutdownRoutine<'a, UnicastSelect<IA, IB>, OA, OB, A, B>
+where
+    IA: 'a,
+    IB: 'a,
+    OA: Send + 'a,
+    OB: Send + 'a,
+    A: Send + 'a,
+    B: Send + 'a,
+{
+    let both = map_input(dispatch(first, second), |signal| match signal {
+        Signal::Input(UnicastSelect::Left(a)) => Select::Left(Signal::Input(a)),
+        Signal::Input(UnicastSelect::Right(b)) => Select::Right(Signal::Input(b)),
+        Signal::Shutdown => Select::Both(ShutdownBoth),
+    });
+    let pick_a = |signal| match signal {
+        Signal::Input(UnicastSelect::Left(a)) => Some(Signal::Input(a)),
+        Signal::Input(UnicastSelect::Right(_)) => None,
+        Signal::Shutdown => Some(Signal::Shutdown),
+    };
+    let pick_b = |signal| match signal {
+        Signal::Input(UnicastSelect::Right(b)) => Some(Signal::Input(b)),
+        Signal::Input(UnicastSelect::Left(_)) => None,
+        Signal::Shutdown => Some(Signal::Shutdown),
+    };
+    finish_both(both, pick_a, pick_b)
+}
+
+/// Routed, where a shutdown is given to both coroutines
+///
+/// see [routed](function@crate::routed). Messages between the two arrive
+/// as ordinary inputs. Once one finishes, messages the other sends it are dropped.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// // counts the messages it gets from the other, until shut down
+/// fn counter<'a, M>(count: usize) -> Coroutine<'a, Signal<M>, UnicastSelect<(), usize>, usize> {
+///     receive().and_then(move |signal| match signal {
+///         Signal::Input(_) => counter(count + 1),
+///         Signal::Shutdown => send(UnicastSelect::Right(count)).and_then(move |()| result(count)),
+///     })
+/// }
+/// let ping = send(UnicastSelect::Left(())).and_then(|()| counter(0));
+///
+/// let co = routed_with_shutdown(ping, counter(0));
+/// let mut it = as_iterator(co, vec![Signal::Shutdown].into_iter());
+/// assert_eq!(it.by_ref().collect::<Vec<_>>(), vec![0, 1]);
+/// assert!(matches!(it.finish(), (Ok((0, 1)), _)));
+/// ```
+pub fn routed_with_shutdown<'a, IA, IB, O, RA, RB>(
+    first: Coroutine<'a, Signal<IA>, UnicastSelect<IB, O>, RA>,
+    second: Coroutine<'a, Signal<IB>, UnicastSelect<IA, O>, RB>,
+) -> Coroutine<'a, Signal<UnicastSelect<IA, IB>>, O, (RA, RB)>
+where
+    IA: Send + 'a,
+    IB: Send + 'a,
+    O: Send + 'a,
+    RA: Send + 'a,
+    RB: Send + 'a,
+{
+    type Pair<'a, IA, IB, O, RA, RB> = Coroutine<
+        'a,
+        UnicastSelect<Signal<IA>, Signal<IB>>,
+        O,
+        RoutedResult<'a, Signal<IA>, Signal<IB>, O, RA, RB>,
+    >;
+
+    // Shutdowns are given to both, one after the other, so the second
+    // is pending until the pair asks for its next input
+    fn signalling<'a, IA, IB, O, RA, RB>(
+        pair: Pair<'a, IA, IB, O, RA, RB>,
+        pending: bool,
+    ) -> Coroutine<'a, Signal<UnicastSelect<IA, IB>>, O, (RA, RB)>
+    where
+        IA: Send + 'a,
+        IB: Send + 'a,
+        O: Send + 'a,
+        RA: Send + 'a,
+        RB: Send + 'a,
+    {
+        match run_step(pair) {
+            StepResult::Done(RoutedResult::Left { value, remain }) => {
+                let remain = if pending {
+                    inject(Signal::Shutdown, remain)
+                } else {
+                    remain
+                };
+                let remain = only(survivor(remain), pick_b);
+                map(remain, move |b| (value, b))
+            }
+            StepResult::Done(RoutedResult::Right { value, remain }) => {
+                let remain = only(survivor(remain), pick_a);
+                map(remain, move |a| (a, value))
+            }
+            StepResult::Yield { output, next } => {
+                bind(send(output), move |()| signalling(*next, pending))
+            }
+            StepResult::Next(next) if pending => {
+                signalling(next(UnicastSelect::Right(Signal::Shutdown)), false)
+            }
+            StepResult::Next(next) => bind(receive(), move |signal| match signal {
+                Signal::Input(UnicastSelect::Left(a)) => {
+                    signalling(next(UnicastSelect::Left(Signal::Input(a))), false)
+                }
+                Signal::Input(UnicastSelect::Right(b)) => {
+                    signalling(next(UnicastSelect::Right(Signal::Input(b))), false)
+                }
+                Signal::Shutdown => signalling(next(UnicastSelect::Left(Signal::Shutdown)), true),
+            }),
+        }
+    }
+
+    // The survivor's messages to the finished coroutine are dropped
+    fn survivor<'a, I, M, O, R>(
+        co: Coroutine<'a, I, UnicastSelect<M, O>, R>,
+    ) -> Coroutine<'a, I, O, R>
+    where
+        M: Send + 'a,
+        O: Send + 'a,
+        R: Send + 'a,
+    {
+        match run_step(co) {
+            StepResult::Done(value) => result(value),
+            StepResult::Yield { output, next } => match output {
+                UnicastSelect::Left(_) => survivor(*next),
+                UnicastSelect::Right(output) => bind(send(output), move |()| survivor(*next)),
+            },
+            StepResult::Next(next) => bind(receive(), move |input| survivor(next(input))),
+        }
+    }
+
+    fn pick_a<IA, IB>(signal: Signal<UnicastSelect<IA, IB>>) -> Option<Signal<IA>> {
+        match signal {
+            Signal::Input(UnicastSelect::Left(a)) => Some(Signal::Input(a)),
+            Signal::Input(UnicastSelect::Right(_)) => None,
+            Signal::Shutdown => Some(Signal::Shutdown),
+        }
+    }
+
+    fn pick_b<IA, IB>(signal: Signal<UnicastSelect<IA, IB>>) -> Option<Signal<IB>> {
+        match signal {
+            Signal::Input(UnicastSelect::Right(b)) => Some(Signal::Input(b)),
+            Signal::Input(UnicastSelect::Left(_)) => None,
+            Signal::Shutdown => Some(Signal::Shutdown),
+        }
+    }
+
+    // Messages to the other coroutine are ordinary inputs to it
+    fn message<M, O>(output: UnicastSelect<M, O>) -> UnicastSelect<Signal<M>, O> {
+        match output {
+            UnicastSelect::Left(message) => UnicastSelect::Left(Signal::Input(message)),
+            UnicastSelect::Right(output) => UnicastSelect::Right(output),
+        }
+    }
+
+    let first = map_output(first, message);
+    let second = map_output(second, message);
+    signalling(routed(first, second), false)
+}
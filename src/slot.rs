@@ -0,0 +1,82 @@
+use crate::{run_step, suspend, Coroutine, StepResult};
+
+/// A coroutine held by a combinator that runs many coroutines
+///
+/// Keeps track of whether the coroutine is ready to run,
+/// waiting on an input, or has finished
+pub(crate) enum Slot<'a, I, O, R> {
+    Running(Coroutine<'a, I, O, R>),
+    Awaiting(Box<dyn FnOnce(I) -> Coroutine<'a, I, O, R> + Send + 'a>),
+    Finished(R),
+}
+
+impl<'a, I, O, R> Slot<'a, I, O, R> {
+    /// Runs a single step, returning the output if there was one
+    pub(crate) fn step(self) -> (Self, Option<O>) {
+        match self {
+            Slot::Running(co) => match run_step(co) {
+                StepResult::Done(value) => (Slot::Finished(value), None),
+                StepResult::Next(next) => (Slot::Awaiting(next), None),
+                StepResult::Yield { output, next } => (Slot::Running(*next), Some(output)),
+            },
+            other => (other, None),
+        }
+    }
+
+    /// Runs until waiting or finished, passing outputs to on_output
+    pub(crate) fn drive(self, mut on_output: impl FnMut(O)) -> Self {
+        let mut slot = self;
+        while let Slot::Running(_) = slot {
+            let (next, output) = slot.step();
+            if let Some(output) = output {
+                on_output(output);
+            }
+            slot = next;
+        }
+        slot
+    }
+
+    /// Gives the input to the coroutine if it is waiting for one
+    ///
+    /// Otherwise the input is handed back
+    pub(crate) fn resume(self, input: I) -> (Self, Option<I>) {
+        match self {
+            Slot::Awaiting(next) => (Slot::Running(next(input)), None),
+            other => (other, Some(input)),
+        }
+    }
+
+    pub(crate) fn is_awaiting(&self) -> bool {
+        matches!(self, Slot::Awaiting(_))
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        matches!(self, Slot::Finished(_))
+    }
+
+    /// The result if finished, otherwise the coroutine so it can be continued
+    pub(crate) fn into_result(self) -> Result<R, Coroutine<'a, I, O, R>> {
+        match self {
+            Slot::Running(co) => Err(co),
+            Slot::Awaiting(next) => Err(suspend(next)),
+            Slot::Finished(value) => Ok(value),
+        }
+    }
+}
+
+/// Replaces the slot at index with the one returned by f
+///
+/// Slots can't be moved out of a vec in place, so this swaps
+/// the slot out and back in again
+pub(crate) fn update<'a, I, O, R, T>(
+    slots: &mut Vec<Slot<'a, I, O, R>>,
+    index: usize,
+    f: impl FnOnce(Slot<'a, I, O, R>) -> (Slot<'a, I, O, R>, T),
+) -> T {
+    let slot = slots.swap_remove(index);
+    let (slot, value) = f(slot);
+    slots.push(slot);
+    let last = slots.len() - 1;
+    slots.swap(index, last);
+    value
+}
@@ -0,0 +1,146 @@
+//! Coroutines that carry state
+//!
+//! StateCoroutine threads a state value through a coroutine, so it doesn't
+//! need to be passed through every function and closure by hand.
+//! The state is read with get, and changed with put or modify.
+//! ```
+//! use bicoro::*;
+//! use bicoro::iterator::*;
+//! use bicoro::state::*;
+//!
+//! // counts coins until a push, then reports how many were paid
+//! fn turnstile<'a>() -> StateCoroutine<'a, u32, &'static str, (), u32> {
+//!     lift(receive()).and_then(|input| match input {
+//!         "coin" => modify(|coins: u32| coins + 1).and_then(|()| turnstile()),
+//!         _ => get(),
+//!     })
+//! }
+//!
+//! let co = run_state(turnstile(), 0);
+//! let mut it = as_iterator(co, vec!["coin", "coin", "push"].into_iter());
+//! it.by_ref().for_each(drop);
+//! assert!(matches!(it.finish(), (Ok((2, 2)), _)));
+//! ```
+use crate::{bind, map, result, Coroutine};
+
+type Stateful<'a, S, I, O, R> = Box<dyn FnOnce(S) -> Coroutine<'a, I, O, (S, R)> + Send + 'a>;
+
+/// A coroutine that produces R, while reading and changing a state S
+pub struct StateCoroutine<'a, S, I, O, R> {
+    run: Stateful<'a, S, I, O, R>,
+}
+
+impl<'a, S, I, O, R> StateCoroutine<'a, S, I, O, R> {
+    /// Wraps a function from the starting state to a coroutine returning the final state
+    pub fn new<F>(f: F) -> Self
+    where
+        F: FnOnce(S) -> Coroutine<'a, I, O, (S, R)> + Send + 'a,
+    {
+        StateCoroutine { run: Box::new(f) }
+    }
+
+    /// Continue with the value, and the state as it is now
+    pub fn and_then<F, B>(self, f: F) -> StateCoroutine<'a, S, I, O, B>
+    where
+        F: FnOnce(R) -> StateCoroutine<'a, S, I, O, B> + Send + 'a,
+        S: 'a,
+        I: 'a,
+        O: 'a,
+        R: 'a,
+    {
+        StateCoroutine::new(move |state| {
+            bind((self.run)(state), move |(state, value)| {
+                (f(value).run)(state)
+            })
+        })
+    }
+
+    /// Maps the value
+    pub fn map<F, B>(self, f: F) -> StateCoroutine<'a, S, I, O, B>
+    where
+        F: FnOnce(R) -> B + Send + 'a,
+        S: 'a,
+        I: 'a,
+        O: 'a,
+        R: 'a,
+    {
+        StateCoroutine::new(move |state| {
+            map((self.run)(state), move |(state, value)| (state, f(value)))
+        })
+    }
+}
+
+impl<'a, S, I, O, R> ::do_notation::Lift<R> for StateCoroutine<'a, S, I, O, R>
+where
+    R: Send + 'a,
+{
+    /// Creates a coroutine that returns the value, leaving the state alone
+    ///
+    /// see [value](function@value)
+    fn lift(a: R) -> Self {
+        value(a)
+    }
+}
+
+/// Runs the coroutine from the initial state, returning the final state and value
+pub fn run_state<'a, S, I, O, R>(
+    co: StateCoroutine<'a, S, I, O, R>,
+    initial: S,
+) -> Coroutine<'a, I, O, (S, R)> {
+    (co.run)(initial)
+}
+
+/// A coroutine that returns the value, leaving the state alone
+/// ```
+/// use bicoro::state::*;
+/// let co: StateCoroutine<u32, (), (), &str> = value("done");
+/// ```
+pub fn value<'a, S, I, O, R>(value: R) -> StateCoroutine<'a, S, I, O, R>
+where
+    R: Send + 'a,
+{
+    StateCoroutine::new(move |state| result((state, value)))
+}
+
+/// Lifts a coroutine that doesn't use the state
+/// ```
+/// use bicoro::*;
+/// use bicoro::state::*;
+/// let co: StateCoroutine<u32, i32, (), i32> = lift(receive());
+/// ```
+pub fn lift<'a, S, I, O, R>(co: Coroutine<'a, I, O, R>) -> StateCoroutine<'a, S, I, O, R>
+where
+    S: Send + 'a,
+    I: 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    StateCoroutine::new(move |state| map(co, move |value| (state, value)))
+}
+
+/// Returns a copy of the state
+pub fn get<'a, S, I, O>() -> StateCoroutine<'a, S, I, O, S>
+where
+    S: Clone,
+{
+    StateCoroutine::new(|state: S| {
+        let copy = state.clone();
+        result((state, copy))
+    })
+}
+
+/// Replaces the state
+pub fn put<'a, S, I, O>(state: S) -> StateCoroutine<'a, S, I, O, ()>
+where
+    S: Send + 'a,
+{
+    StateCoroutine::new(move |_| result((state, ())))
+}
+
+/// Changes the state with f
+pub fn modify<'a, S, I, O, F>(f: F) -> StateCoroutine<'a, S, I, O, ()>
+where
+    F: FnOnce(S) -> S + Send + 'a,
+{
+    StateCoroutine::new(move |state| result((f(state), ())))
+}
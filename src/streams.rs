@@ -0,0 +1,392 @@
+//! Adapters from coroutines to futures streams and sinks
+//!
+//! A coroutine that only produces outputs can be consumed by anything
+//! that reads a stream, and one that only consumes inputs can be written
+//! to like any other sink, without writing a driver for either. Coroutines
+//! that do both can be used as a duplex, that is both a sink and a stream.
+//! Stepping a coroutine never waits, so they are always ready.
+//! Only compiled with the futures feature.
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use futures::{Sink, Stream};
+
+use crate::{run_step, slot::Slot, suspend, Coroutine, Never, StepResult};
+
+/// A stream of a coroutine's outputs
+///
+/// The stream ends when the coroutine finishes, or when it waits for
+/// an input that can't be given to it.
+pub struct OutputStream<'a, I, O, R> {
+    state: Option<Result<R, Coroutine<'a, I, O, R>>>,
+    feed: fn() -> Option<I>,
+}
+
+// The coroutine is only ever moved, never pinned in place
+impl<'a, I, O, R> Unpin for OutputStream<'a, I, O, R> {}
+
+/// Streams the outputs of a coroutine that only ever receives ()
+/// ```
+/// use bicoro::*;
+/// use bicoro::streams::*;
+/// use futures::{executor::block_on, StreamExt};
+///
+/// fn count(from: i32, to: i32) -> Coroutine<'static, (), i32, &'static str> {
+///     if from > to {
+///         return result("done");
+///     }
+///     send(from).and_then(move |()| count(from + 1, to))
+/// }
+///
+/// let mut stream = into_stream(count(1, 3));
+/// let outputs = block_on(stream.by_ref().collect::<Vec<_>>());
+/// assert_eq!(outputs, vec![1, 2, 3]);
+/// assert!(matches!(stream.into_result(), Ok("done")));
+/// ```
+pub fn into_stream<'a, O, R>(co: Coroutine<'a, (), O, R>) -> OutputStream<'a, (), O, R> {
+    OutputStream {
+        state: Some(Err(co)),
+        feed: || Some(()),
+    }
+}
+
+/// Streams the outputs of a coroutine that can never receive an input
+///
+/// If the coroutine waits for an input, the stream ends, as it can never continue
+pub fn never_stream<'a, O, R>(co: Coroutine<'a, Never, O, R>) -> OutputStream<'a, Never, O, R> {
+    OutputStream {
+        state: Some(Err(co)),
+        feed: || None,
+    }
+}
+
+impl<'a, I, O, R> OutputStream<'a, I, O, R> {
+    /// The result if the coroutine finished, otherwise the coroutine
+    pub fn into_result(self) -> Result<R, Coroutine<'a, I, O, R>> {
+        self.state
+            .expect("the state is always put back after stepping")
+    }
+}
+
+impl<'a, I, O, R> Stream for OutputStream<'a, I, O, R> {
+    type Item = O;
+
+    fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<O>> {
+        let this = self.get_mut();
+        let mut co = match this.state.take() {
+            Some(Err(co)) => co,
+            finished => {
+                this.state = finished;
+                return Poll::Ready(None);
+            }
+        };
+        loop {
+            match run_step(co) {
+                StepResult::Done(value) => {
+                    this.state = Some(Ok(value));
+                    return Poll::Ready(None);
+                }
+                StepResult::Yield { output, next } => {
+                    this.state = Some(Err(*next));
+                    return Poll::Ready(Some(output));
+                }
+                StepResult::Next(next) => match (this.feed)() {
+                    Some(input) => co = next(input),
+                    None => {
+                        this.state = Some(Err(suspend(next)));
+                        return Poll::Ready(None);
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// The coroutine has finished, so the input was handed back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Closed<I>(pub I);
+
+/// A sink feeding a coroutine that never sends an output
+///
+/// Once the coroutine finishes, sending to the sink fails, which stops
+/// whatever is forwarding to it.
+pub struct InputSink<'a, I, R> {
+    slot: Option<Slot<'a, I, Never, R>>,
+}
+
+// The coroutine is only ever moved, never pinned in place
+impl<'a, I, R> Unpin for InputSink<'a, I, R> {}
+
+/// Sends everything written to the sink to the coroutine
+/// ```
+/// use bicoro::*;
+/// use bicoro::streams::*;
+/// use futures::{executor::block_on, stream, StreamExt};
+///
+/// // adds up numbers until it has three
+/// fn sum(count: u8, total: i32) -> Coroutine<'static, i32, Never, i32> {
+///     if count == 3 {
+///         return result(total);
+///     }
+///     receive().and_then(move |i: i32| sum(count + 1, total + i))
+/// }
+///
+/// let mut sink = into_sink(sum(0, 0));
+/// let inputs = stream::iter(vec![1, 2, 3, 4, 5]).map(Ok);
+/// let forwarded = block_on(inputs.forward(&mut sink));
+/// // the fourth number was turned away, as the sum had finished
+/// assert_eq!(forwarded, Err(Closed(4)));
+/// assert!(matches!(sink.into_result(), Ok(6)));
+/// ```
+pub fn into_sink<'a, I, R>(co: Coroutine<'a, I, Never, R>) -> InputSink<'a, I, R> {
+    InputSink {
+        slot: Some(Slot::Running(co)),
+    }
+}
+
+impl<'a, I, R> InputSink<'a, I, R> {
+    /// The result if the coroutine finished, otherwise the coroutine
+    pub fn into_result(self) -> Result<R, Coroutine<'a, I, Never, R>> {
+        self.slot
+            .expect("the slot is always put back after driving")
+            .into_result()
+    }
+
+    /// Runs the coroutine until it waits for input, or finishes
+    fn drive(&mut self) {
+        let slot = self
+            .slot
+            .take()
+            .expect("the slot is always put back after driving");
+        self.slot = Some(slot.drive(|never| match never {}));
+    }
+}
+
+impl<'a, I, R> Sink<I> for InputSink<'a, I, R> {
+    type Error = Closed<I>;
+
+    fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Closed<I>>> {
+        // A finished coroutine is reported when the input is sent
+        self.get_mut().drive();
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, input: I) -> Result<(), Closed<I>> {
+        let this = self.get_mut();
+        let slot = this
+            .slot
+            .take()
+            .expect("the slot is always put back after driving");
+        let (slot, rejected) = slot.resume(input);
+        this.slot = Some(slot);
+        match rejected {
+            Some(input) => Err(Closed(input)),
+            None => Ok(()),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Closed<I>>> {
+        self.get_mut().drive();
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Result<(), Closed<I>>> {
+        self.poll_flush(context)
+    }
+}
+
+/// A sink of a coroutine's inputs, that is also a stream of its outputs
+///
+/// Each input is run through the coroutine straight away, and its outputs
+/// are buffered until they are read from the stream. The stream ends once
+/// the buffered outputs are read, and either the coroutine has finished,
+/// or the sink has been closed while it waits for input.
+pub struct Duplex<'a, I, O, R> {
+    slot: Option<Slot<'a, I, O, R>>,
+    outputs: VecDeque<O>,
+    capacity: Option<usize>,
+    closed: bool,
+    // The stream's waker, if it is waiting on an input to be sent
+    reader: Option<Waker>,
+    // The sink's waker, if it is waiting on outputs to be read
+    writer: Option<Waker>,
+}
+
+/// The unread outputs of a duplex, and the result or the coroutine
+pub type DuplexParts<'a, I, O, R> = (VecDeque<O>, Result<R, Coroutine<'a, I, O, R>>);
+
+// The coroutine is only ever moved, never pinned in place
+impl<'a, I, O, R> Unpin for Duplex<'a, I, O, R> {}
+
+/// Wraps the coroutine so it can be written to and read from
+/// ```
+/// use bicoro::*;
+/// use bicoro::streams::*;
+/// use futures::{executor::block_on, SinkExt, StreamExt};
+///
+/// // echoes twice, then stops
+/// let echo = || receive().and_then(|i: i32| send(i));
+/// let mut duplex = into_duplex(echo().and_then(move |()| echo()));
+///
+/// block_on(async {
+///     duplex.send(1).await.unwrap();
+///     duplex.send(2).await.unwrap();
+///     assert_eq!(duplex.send(3).await, Err(Closed(3)));
+///     assert_eq!(duplex.by_ref().collect::<Vec<_>>().await, vec![1, 2]);
+/// });
+/// ```
+pub fn into_duplex<'a, I, O, R>(co: Coroutine<'a, I, O, R>) -> Duplex<'a, I, O, R> {
+    Duplex {
+        slot: Some(Slot::Running(co)),
+        outputs: VecDeque::new(),
+        capacity: None,
+        closed: false,
+        reader: None,
+        writer: None,
+    }
+}
+
+impl<'a, I, O, R> Duplex<'a, I, O, R> {
+    /// Only ready for an input while fewer than capacity outputs are unread
+    ///
+    /// Without this, outputs are buffered however slowly they are read.
+    /// A single input can still make more outputs than the capacity.
+    /// ```
+    /// use bicoro::*;
+    /// use bicoro::streams::*;
+    /// use futures::{executor::block_on, join, stream, StreamExt};
+    ///
+    /// let echo: Coroutine<i32, i32, Never> = forever(|| receive().and_then(send));
+    /// let (sink, reader) = into_duplex(echo).bounded(1).split();
+    ///
+    /// // the writer waits for each output to be read before sending more
+    /// let writer = stream::iter(vec![1, 2, 3]).map(Ok).forward(sink);
+    /// let (written, read) = block_on(async { join!(writer, reader.take(3).collect::<Vec<_>>()) });
+    /// assert!(written.is_ok());
+    /// assert_eq!(read, vec![1, 2, 3]);
+    /// ```
+    pub fn bounded(self, capacity: usize) -> Self {
+        assert!(capacity > 0, "a bounded duplex needs room for an output");
+        Duplex {
+            capacity: Some(capacity),
+            ..self
+        }
+    }
+
+    /// The outputs that haven't been read, and the result or the coroutine
+    pub fn into_parts(self) -> DuplexParts<'a, I, O, R> {
+        let slot = self
+            .slot
+            .expect("the slot is always put back after driving");
+        (self.outputs, slot.into_result())
+    }
+
+    /// Runs the coroutine until it waits for input, or finishes
+    fn drive(&mut self) {
+        let slot = self
+            .slot
+            .take()
+            .expect("the slot is always put back after driving");
+        let outputs = &mut self.outputs;
+        self.slot = Some(slot.drive(|output| outputs.push_back(output)));
+    }
+}
+
+impl<'a, I, O, R> Sink<I> for Duplex<'a, I, O, R> {
+    type Error = Closed<I>;
+
+    fn poll_ready(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Result<(), Closed<I>>> {
+        let this = self.get_mut();
+        this.drive();
+        // A finished coroutine is reported when the input is sent
+        match this.capacity {
+            Some(capacity) if this.outputs.len() >= capacity => {
+                this.writer = Some(context.waker().clone());
+                Poll::Pending
+            }
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, input: I) -> Result<(), Closed<I>> {
+        let this = self.get_mut();
+        this.drive();
+        let slot = this
+            .slot
+            .take()
+            .expect("the slot is always put back after driving");
+        let (slot, rejected) = slot.resume(input);
+        this.slot = Some(slot);
+        if let Some(input) = rejected {
+            return Err(Closed(input));
+        }
+        this.drive();
+        if let Some(reader) = this.reader.take() {
+            reader.wake();
+        }
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Closed<I>>> {
+        self.get_mut().drive();
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Closed<I>>> {
+        let this = self.get_mut();
+        this.drive();
+        // No more inputs are coming, so the stream can end once it is read
+        this.closed = true;
+        if let Some(reader) = this.reader.take() {
+            reader.wake();
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'a, I, O, R> Stream for Duplex<'a, I, O, R> {
+    type Item = O;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<O>> {
+        let this = self.get_mut();
+        this.drive();
+        match this.outputs.pop_front() {
+            Some(output) => {
+                if let Some(writer) = this.writer.take() {
+                    writer.wake();
+                }
+                Poll::Ready(Some(output))
+            }
+            // Driving leaves it waiting for input, or finished
+            None if this.closed => Poll::Ready(None),
+            None if matches!(this.slot, Some(Slot::Finished(_))) => Poll::Ready(None),
+            None => {
+                this.reader = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{receive, send};
+    use futures::{executor::block_on, SinkExt, StreamExt};
+
+    #[test]
+    fn closing_the_sink_ends_the_stream() {
+        let echo = || receive().and_then(|i: i32| send(i));
+        let mut duplex = into_duplex(echo().and_then(move |()| echo()));
+
+        block_on(async {
+            duplex.send(1).await.unwrap();
+            duplex.close().await.unwrap();
+            assert_eq!(duplex.by_ref().collect::<Vec<_>>().await, vec![1]);
+        });
+        assert!(duplex.into_parts().1.is_err());
+    }
+}
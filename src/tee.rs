@@ -0,0 +1,164 @@
+use crate::{
+    bind, intercept_output, result, right, run_step, send, suspend, Coroutine, StepResult,
+};
+
+/// Somewhere to copy values to, such as a channel or a file
+///
+/// Recording should never affect the coroutine, so recorders
+/// can't fail. A recorder that has nowhere to put a value drops it.
+pub trait Recorder<T> {
+    /// Records a copy of the item
+    fn record(&mut self, item: &T);
+}
+
+impl<T, F> Recorder<T> for F
+where
+    F: FnMut(&T),
+{
+    fn record(&mut self, item: &T) {
+        self(item)
+    }
+}
+
+impl<T: Clone> Recorder<T> for std::sync::mpsc::Sender<T> {
+    fn record(&mut self, item: &T) {
+        // if nobody is listening, the copy isn't needed
+        let _ = self.send(item.clone());
+    }
+}
+
+/// Writes each item as a line of JSON
+///
+/// Useful for capturing traffic to a file, to replay later.
+/// Errors writing are ignored, so a full disk won't stop the coroutine.
+#[cfg(feature = "serde_json")]
+pub struct JsonLines<W> {
+    writer: W,
+}
+
+#[cfg(feature = "serde_json")]
+impl<W: std::io::Write> JsonLines<W> {
+    /// Writes lines to the writer
+    pub fn new(writer: W) -> Self {
+        JsonLines { writer }
+    }
+
+    /// Gives back the writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl<T, W> Recorder<T> for JsonLines<W>
+where
+    T: serde::Serialize,
+    W: std::io::Write,
+{
+    fn record(&mut self, item: &T) {
+        if serde_json::to_writer(&mut self.writer, item).is_ok() {
+            let _ = self.writer.write_all(b"\n");
+        }
+    }
+}
+
+/// Copies every input the coroutine receives into the recorder
+///
+/// The coroutine behaves exactly as before, this only watches.
+/// Inputs are recorded as they are delivered, so inputs the coroutine
+/// never asked for are not recorded. This is cheap enough to leave running
+/// in production, to capture real traffic to replay in tests.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use std::sync::mpsc::channel;
+///
+/// let (sender, captured) = channel();
+/// let co: Coroutine<i32, i32, ()> = receive().and_then(|a: i32| receive().and_then(move |b: i32| send(a + b)));
+///
+/// let outputs = as_iterator(tee_inputs(co, sender), vec![1, 2, 3].into_iter()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![3]);
+/// assert_eq!(captured.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+/// ```
+pub fn tee_inputs<'a, I, O, R, S>(
+    co: Coroutine<'a, I, O, R>,
+    mut recorder: S,
+) -> Coroutine<'a, I, O, R>
+where
+    S: Recorder<I> + Send + 'a,
+    O: Send,
+    R: Send,
+{
+    match run_step(co) {
+        StepResult::Done(value) => result(value),
+        StepResult::Yield { output, next } => {
+            bind(send(output), move |()| tee_inputs(*next, recorder))
+        }
+        StepResult::Next(next) => suspend(move |input| {
+            recorder.record(&input);
+            tee_inputs(next(input), recorder)
+        }),
+    }
+}
+
+/// Handles every output of the coroutine twice, first with on_left then on_right
+///
+/// Both handlers can send, and can receive inputs, so this works for
+/// both sides being protocols, not just one side watching. Each output
+/// is cloned for on_left.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let co: Coroutine<(), i32, ()> = send(1).and_then(|()| send(2));
+/// let log = |i: i32| send(format!("log {}", i));
+/// let forward = |i: i32| send(format!("forward {}", i));
+///
+/// let outputs = as_iterator(tee(co, log, forward), std::iter::empty()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec!["log 1", "forward 1", "log 2", "forward 2"]);
+/// ```
+pub fn tee<'a, I, O, O2, R, FL, FR>(
+    co: Coroutine<'a, I, O, R>,
+    on_left: FL,
+    on_right: FR,
+) -> Coroutine<'a, I, O2, R>
+where
+    FL: Fn(O) -> Coroutine<'a, I, O2, ()> + Send + 'a,
+    FR: Fn(O) -> Coroutine<'a, I, O2, ()> + Send + 'a,
+    O: Clone + Send,
+    O2: Send,
+    R: Send,
+{
+    intercept_output(co, move |output: O| {
+        let left = on_left(output.clone());
+        right(left, on_right(output))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{iterator::as_iterator, receive};
+
+    #[test]
+    fn recorders_see_each_input_without_changing_the_outputs() {
+        let mut recorded = Vec::new();
+        let double: Coroutine<i32, i32, ()> = bind(receive(), |i: i32| send(i * 2));
+        let co = tee_inputs(double, |input: &i32| recorded.push(*input));
+        let outputs = as_iterator(co, vec![1, 2].into_iter()).collect::<Vec<_>>();
+
+        assert_eq!(outputs, vec![2]);
+        assert_eq!(recorded, vec![1]);
+    }
+
+    #[test]
+    #[cfg(feature = "serde_json")]
+    fn json_lines_writes_each_input() {
+        let mut buffer = Vec::new();
+        let co: Coroutine<i32, (), i32> = bind(receive(), |_: i32| receive());
+        let co = tee_inputs(co, JsonLines::new(&mut buffer));
+        as_iterator(co, vec![1, 2].into_iter()).for_each(drop);
+
+        assert_eq!(String::from_utf8(buffer).unwrap(), "1\n2\n");
+    }
+}
@@ -0,0 +1,1012 @@
+//! Helpers for testing coroutines against each other
+//!
+//! Protocols usually have two ends, a client and a server.
+//! These helpers run both ends in memory, so a protocol can be tested
+//! without any real IO.
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug, Display, Formatter},
+    ops::ControlFlow,
+};
+
+use crate::{
+    bind, iterate, iterator::as_iterator, map_output, receive, result, right, run_step, send,
+    slot::Slot, suspend, Coroutine, StepResult,
+};
+
+/// A message in flight between the client and the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet<C, S> {
+    /// Sent by the client, for the server
+    FromClient(C),
+    /// Sent by the server, for the client
+    FromServer(S),
+}
+
+/// What happens to a packet while it is in transit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The packet arrives as normal
+    Deliver,
+    /// The packet never arrives
+    Drop,
+    /// The packet arrives twice
+    Duplicate,
+    /// The packet arrives after this many ticks, a delay of 0 is no delay
+    Delay(usize),
+}
+
+/// Something that was observed on the transport
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event<C, S> {
+    /// A packet was emitted by one of the ends
+    Sent(Packet<C, S>),
+    /// A packet was received by one of the ends
+    Delivered(Packet<C, S>),
+    /// A packet was lost in transit
+    Dropped(Packet<C, S>),
+    /// Time advanced, as there was nothing else to deliver
+    Tick,
+}
+
+/// The outcome of running both ends
+///
+/// Each end is either finished, with its result, or the paused
+/// coroutine is returned, so it can be inspected or continued.
+pub struct Loopback<'a, C, S, RC, RS> {
+    /// The client result, or the suspended client
+    pub client: Result<RC, Coroutine<'a, S, C, RC>>,
+    /// The server result, or the suspended server
+    pub server: Result<RS, Coroutine<'a, C, S, RS>>,
+    /// Everything that happened on the transport, in order
+    pub transcript: Vec<Event<C, S>>,
+}
+
+/// Runs a client and server against each other, over a perfect transport
+///
+/// Every output of one end becomes the input of the other.
+/// This runs until both have finished, or neither can make progress.
+/// ```
+/// use bicoro::*;
+/// use bicoro::testing::*;
+///
+/// // client sends a number and expects it back doubled
+/// let client: Coroutine<i32, i32, i32> = send(21).and_then(|()| receive());
+/// let server: Coroutine<i32, i32, ()> = receive().and_then(|i: i32| send(i * 2));
+///
+/// let run = loopback(client, server);
+/// assert!(matches!(run.client, Ok(42)));
+/// assert!(matches!(run.server, Ok(())));
+/// assert_eq!(run.transcript[0], Event::Sent(Packet::FromClient(21)));
+/// ```
+pub fn loopback<'a, C, S, RC, RS>(
+    client: Coroutine<'a, S, C, RC>,
+    server: Coroutine<'a, C, S, RS>,
+) -> Loopback<'a, C, S, RC, RS>
+where
+    C: Clone,
+    S: Clone,
+{
+    loopback_with_faults(client, server, |_| Fault::Deliver)
+}
+
+/// Runs a client and server against each other, over an unreliable transport
+///
+/// The faults function decides what happens to every packet sent,
+/// and may also modify the packet to simulate corruption.
+/// Delayed packets are held until nothing else can happen, at which point
+/// time advances a tick. This is deterministic, so can be used in tests.
+/// ```
+/// use bicoro::*;
+/// use bicoro::testing::*;
+///
+/// let client: Coroutine<(), i32, ()> = send(1).and_then(|()| send(2));
+/// let server: Coroutine<i32, (), i32> = receive();
+///
+/// // lose the first packet
+/// let run = loopback_with_faults(client, server, |packet| match packet {
+///     Packet::FromClient(1) => Fault::Drop,
+///     _ => Fault::Deliver,
+/// });
+/// assert!(matches!(run.server, Ok(2)));
+/// ```
+pub fn loopback_with_faults<'a, C, S, RC, RS, F>(
+    client: Coroutine<'a, S, C, RC>,
+    server: Coroutine<'a, C, S, RS>,
+    mut faults: F,
+) -> Loopback<'a, C, S, RC, RS>
+where
+    C: Clone,
+    S: Clone,
+    F: FnMut(&mut Packet<C, S>) -> Fault,
+{
+    let mut client = Slot::Running(client);
+    let mut server = Slot::Running(server);
+    let mut to_client = VecDeque::new();
+    let mut to_server = VecDeque::new();
+    let mut delayed: Vec<(usize, Packet<C, S>)> = Vec::new();
+    let mut transcript = Vec::new();
+
+    loop {
+        // Run both ends until they are waiting, queuing what they send
+        let mut sent = Vec::new();
+        client = client.drive(|c| sent.push(Packet::FromClient(c)));
+        server = server.drive(|s| sent.push(Packet::FromServer(s)));
+        for mut packet in sent {
+            transcript.push(Event::Sent(packet.clone()));
+            match faults(&mut packet) {
+                Fault::Deliver | Fault::Delay(0) => enqueue(packet, &mut to_server, &mut to_client),
+                Fault::Duplicate => {
+                    enqueue(packet.clone(), &mut to_server, &mut to_client);
+                    enqueue(packet, &mut to_server, &mut to_client);
+                }
+                Fault::Drop => transcript.push(Event::Dropped(packet)),
+                Fault::Delay(ticks) => delayed.push((ticks, packet)),
+            }
+        }
+
+        // Deliver a single packet, then go back to running the ends
+        let delivered;
+        (server, delivered) = deliver(server, &mut to_server);
+        if let Some(c) = delivered {
+            transcript.push(Event::Delivered(Packet::FromClient(c)));
+            continue;
+        }
+        let delivered;
+        (client, delivered) = deliver(client, &mut to_client);
+        if let Some(s) = delivered {
+            transcript.push(Event::Delivered(Packet::FromServer(s)));
+            continue;
+        }
+
+        // Nothing to deliver, so advance time if anything is delayed
+        if delayed.is_empty() {
+            break;
+        }
+        transcript.push(Event::Tick);
+        let (due, waiting): (Vec<_>, Vec<_>) = delayed
+            .into_iter()
+            .map(|(ticks, packet)| (ticks.saturating_sub(1), packet))
+            .partition(|(ticks, _)| *ticks == 0);
+        delayed = waiting;
+        for (_, packet) in due {
+            enqueue(packet, &mut to_server, &mut to_client);
+        }
+    }
+
+    Loopback {
+        client: client.into_result(),
+        server: server.into_result(),
+        transcript,
+    }
+}
+
+// Places the packet on the queue for its destination
+fn enqueue<C, S>(packet: Packet<C, S>, to_server: &mut VecDeque<C>, to_client: &mut VecDeque<S>) {
+    match packet {
+        Packet::FromClient(c) => to_server.push_back(c),
+        Packet::FromServer(s) => to_client.push_back(s),
+    }
+}
+
+// Resumes the end with the next queued input, if it is waiting for one
+fn deliver<'a, I, O, R>(
+    slot: Slot<'a, I, O, R>,
+    queue: &mut VecDeque<I>,
+) -> (Slot<'a, I, O, R>, Option<I>)
+where
+    I: Clone,
+{
+    if !slot.is_awaiting() {
+        return (slot, None);
+    }
+    match queue.pop_front() {
+        Some(input) => (slot.resume(input.clone()).0, Some(input)),
+        None => (slot, None),
+    }
+}
+
+type Corrupt<M> = Box<dyn FnMut(&mut M) + Send>;
+
+/// A reproducible source of faults
+///
+/// Faults are chosen using a pseudo random generator seeded on creation,
+/// so the same seed will always give the same faults for the same messages.
+/// ```
+/// use bicoro::testing::*;
+///
+/// let mut injector = FaultInjector::new(42).drop_every(2).corrupt(1.0, |m: &mut i32| *m += 1);
+///
+/// let mut first = 1;
+/// assert_eq!(injector.fault(&mut first), Fault::Deliver);
+/// assert_eq!(first, 2);
+/// assert_eq!(injector.fault(&mut 1), Fault::Drop);
+/// ```
+pub struct FaultInjector<M> {
+    rng: u64,
+    count: usize,
+    drop_every: Option<usize>,
+    drop_rate: f64,
+    duplicate_rate: f64,
+    delay_rate: f64,
+    max_delay: usize,
+    corrupt_rate: f64,
+    corrupt: Option<Corrupt<M>>,
+}
+
+impl<M> FaultInjector<M> {
+    /// Creates an injector that delivers everything, until configured otherwise
+    pub fn new(seed: u64) -> Self {
+        FaultInjector {
+            // xorshift gets stuck on zero
+            rng: seed.max(1),
+            count: 0,
+            drop_every: None,
+            drop_rate: 0.0,
+            duplicate_rate: 0.0,
+            delay_rate: 0.0,
+            max_delay: 0,
+            corrupt_rate: 0.0,
+            corrupt: None,
+        }
+    }
+
+    /// Drops every nth message
+    pub fn drop_every(self, n: usize) -> Self {
+        let drop_every = Some(n).filter(|n| *n > 0);
+        FaultInjector { drop_every, ..self }
+    }
+
+    /// Drops messages with the given probability
+    pub fn drop_rate(self, drop_rate: f64) -> Self {
+        FaultInjector { drop_rate, ..self }
+    }
+
+    /// Duplicates messages with the given probability
+    pub fn duplicate_rate(self, duplicate_rate: f64) -> Self {
+        FaultInjector {
+            duplicate_rate,
+            ..self
+        }
+    }
+
+    /// Delays messages with the given probability, by up to max_delay ticks
+    pub fn delay_rate(self, delay_rate: f64, max_delay: usize) -> Self {
+        FaultInjector {
+            delay_rate,
+            max_delay,
+            ..self
+        }
+    }
+
+    /// Modifies messages with the given probability, using the corrupt function
+    pub fn corrupt<F>(self, corrupt_rate: f64, corrupt: F) -> Self
+    where
+        F: FnMut(&mut M) + Send + 'static,
+    {
+        let corrupt: Option<Corrupt<M>> = Some(Box::new(corrupt));
+        FaultInjector {
+            corrupt_rate,
+            corrupt,
+            ..self
+        }
+    }
+
+    /// Decides the fault for this message, possibly corrupting it
+    pub fn fault(&mut self, message: &mut M) -> Fault {
+        self.count += 1;
+        if self.chance(self.corrupt_rate) {
+            if let Some(corrupt) = self.corrupt.as_mut() {
+                corrupt(message);
+            }
+        }
+        if matches!(self.drop_every, Some(n) if self.count.is_multiple_of(n))
+            || self.chance(self.drop_rate)
+        {
+            Fault::Drop
+        } else if self.chance(self.duplicate_rate) {
+            Fault::Duplicate
+        } else if self.max_delay > 0 && self.chance(self.delay_rate) {
+            let ticks = 1 + (self.next() % self.max_delay as u64) as usize;
+            Fault::Delay(ticks)
+        } else {
+            Fault::Deliver
+        }
+    }
+
+    fn chance(&mut self, rate: f64) -> bool {
+        // Top 53 bits give a uniform value in [0,1)
+        rate > 0.0 && ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < rate
+    }
+
+    // xorshift64*
+    fn next(&mut self) -> u64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+/// Applies faults to the outputs of a coroutine
+///
+/// This can wrap one end of a protocol to make its channel unreliable.
+/// Time is measured in inputs, so an output delayed by a tick
+/// is emitted once the coroutine has received another input.
+/// Any delayed outputs are emitted when the coroutine finishes.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use bicoro::testing::*;
+///
+/// let co: Coroutine<(), i32, ()> = send(1).and_then(|()| send(2)).and_then(|()| send(3));
+/// let co = inject_faults(co, FaultInjector::new(7).drop_every(2));
+///
+/// let outputs = as_iterator(co, std::iter::empty()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![1, 3]);
+/// ```
+pub fn inject_faults<'a, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+    injector: FaultInjector<O>,
+) -> Coroutine<'a, I, O, R>
+where
+    O: Clone + Send + 'a,
+    R: Send,
+{
+    inject_delayed(co, injector, Vec::new())
+}
+
+fn inject_delayed<'a, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+    mut injector: FaultInjector<O>,
+    mut delayed: Vec<(usize, O)>,
+) -> Coroutine<'a, I, O, R>
+where
+    O: Clone + Send + 'a,
+    R: Send,
+{
+    match run_step(co) {
+        StepResult::Done(value) => {
+            let flush = send_all(delayed.into_iter().map(|(_, output)| output));
+            right(flush, result(value))
+        }
+        StepResult::Yield { mut output, next } => match injector.fault(&mut output) {
+            Fault::Deliver | Fault::Delay(0) => bind(send(output), move |()| {
+                inject_delayed(*next, injector, delayed)
+            }),
+            Fault::Duplicate => {
+                let outputs = right(send(output.clone()), send(output));
+                bind(outputs, move |()| inject_delayed(*next, injector, delayed))
+            }
+            Fault::Drop => inject_delayed(*next, injector, delayed),
+            Fault::Delay(ticks) => {
+                delayed.push((ticks, output));
+                inject_delayed(*next, injector, delayed)
+            }
+        },
+        StepResult::Next(next) => {
+            let on_input = move |input| {
+                let (due, delayed): (Vec<_>, Vec<_>) = delayed
+                    .into_iter()
+                    .map(|(ticks, output)| (ticks.saturating_sub(1), output))
+                    .partition(|(ticks, _)| *ticks == 0);
+                let due = send_all(due.into_iter().map(|(_, output)| output));
+                let co = next(input);
+                bind(due, move |()| inject_delayed(co, injector, delayed))
+            };
+            bind(receive(), on_input)
+        }
+    }
+}
+
+// Sends each output in order
+fn send_all<'a, I, O>(outputs: impl Iterator<Item = O>) -> Coroutine<'a, I, O, ()>
+where
+    O: Send + 'a,
+{
+    outputs.fold(result(()), |co, output| right(co, send(output)))
+}
+
+/// An output of a state machine run by trace_states
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Traced<O, S> {
+    /// An output sent by the machine
+    Output(O),
+    /// The machine moved into this state
+    State(S),
+}
+
+/// Runs a state machine like iterate, sending each state it moves into
+///
+/// The initial state is sent first, then every state a step continues with.
+/// This lets tests check the states a machine went through, not just its outputs.
+pub fn trace_states<'a, I, O, S, R, F>(initial: S, step: F) -> Coroutine<'a, I, Traced<O, S>, R>
+where
+    F: Fn(S) -> Coroutine<'a, I, O, ControlFlow<R, S>> + Send + 'a,
+    S: Clone + Send + 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    iterate(initial, move |state| {
+        let entered = send(Traced::State(state.clone()));
+        let run = map_output(step(state), Traced::Output);
+        bind(entered, move |()| run)
+    })
+}
+
+/// Describes how the actual states differ from the expected ones
+///
+/// Matching states are shown once, and mismatches are shown as
+/// an expected (-) and actual (+) pair. Returns None if they match.
+pub fn diff_states<S: PartialEq + Debug>(expected: &[S], actual: &[S]) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+    let mut diff = String::new();
+    for index in 0..expected.len().max(actual.len()) {
+        match (expected.get(index), actual.get(index)) {
+            (Some(e), Some(a)) if e == a => diff += &format!("  {}: {:?}\n", index, e),
+            (e, a) => {
+                if let Some(e) = e {
+                    diff += &format!("- {}: {:?}\n", index, e);
+                }
+                if let Some(a) = a {
+                    diff += &format!("+ {}: {:?}\n", index, a);
+                }
+            }
+        }
+    }
+    Some(diff)
+}
+
+/// Runs a traced machine over the inputs, asserting it went through the expected states
+///
+/// Panics with a diff of the states if they don't match,
+/// otherwise the outputs are returned so they can be checked too.
+/// ```
+/// use bicoro::*;
+/// use bicoro::testing::*;
+/// use std::ops::ControlFlow;
+///
+/// #[derive(Debug, Clone, PartialEq)]
+/// enum Turnstile { Locked, Unlocked }
+///
+/// // a coin unlocks it, and anything else locks it
+/// let machine = trace_states(Turnstile::Locked, |_| {
+///     receive().and_then(|input: &str| {
+///         let next = match input {
+///             "coin" => Turnstile::Unlocked,
+///             _ => Turnstile::Locked,
+///         };
+///         send(input.len()).and_then(move |()| result(ControlFlow::<(), _>::Continue(next)))
+///     })
+/// });
+///
+/// let expected = [Turnstile::Locked, Turnstile::Unlocked, Turnstile::Locked];
+/// let outputs = assert_states(machine, vec!["coin", "push"], &expected);
+/// assert_eq!(outputs, vec![4, 4]);
+/// ```
+pub fn assert_states<'a, I, O, S, R>(
+    co: Coroutine<'a, I, Traced<O, S>, R>,
+    inputs: impl IntoIterator<Item = I>,
+    expected: &[S],
+) -> Vec<O>
+where
+    S: PartialEq + Debug,
+{
+    let mut states = Vec::new();
+    let mut outputs = Vec::new();
+    for traced in as_iterator(co, inputs.into_iter()) {
+        match traced {
+            Traced::Output(output) => outputs.push(output),
+            Traced::State(state) => states.push(state),
+        }
+    }
+    if let Some(diff) = diff_states(expected, &states) {
+        panic!("states differ from expected:\n{}", diff);
+    }
+    outputs
+}
+
+/// Something that happened while recording a coroutine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exchange<I, O> {
+    /// The coroutine received this input
+    Input(I),
+    /// The coroutine sent this output
+    Output(O),
+}
+
+/// The inputs and outputs of a coroutine, in the order they happened
+///
+/// Displays as two aligned columns, inputs on the left and outputs
+/// on the right, which is easier to read in a failing test than the Debug form.
+/// ```
+/// use bicoro::*;
+/// use bicoro::testing::*;
+///
+/// let echo: Coroutine<i32, String, ()> = receive().and_then(|i: i32| send(format!("got {}", i)));
+///
+/// let (transcript, _) = record(echo, vec![10]);
+/// assert_eq!(transcript.to_string(), "input | output\n------+-------\n10    |\n      | got 10\n");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transcript<I, O> {
+    entries: Vec<Exchange<I, O>>,
+}
+
+impl<I, O> Transcript<I, O> {
+    /// Everything that happened, in order
+    pub fn entries(&self) -> &[Exchange<I, O>] {
+        &self.entries
+    }
+}
+
+impl<I, O> From<Vec<Exchange<I, O>>> for Transcript<I, O> {
+    fn from(entries: Vec<Exchange<I, O>>) -> Self {
+        Transcript { entries }
+    }
+}
+
+impl<I: Display, O: Display> Display for Transcript<I, O> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let rows = self.entries.iter().map(|entry| match entry {
+            Exchange::Input(input) => (input.to_string(), String::new()),
+            Exchange::Output(output) => (String::new(), output.to_string()),
+        });
+        write_columns(f, ("input", "output"), rows)
+    }
+}
+
+/// A transcript, with the result or the unfinished coroutine
+pub type Recording<'a, I, O, R> = (Transcript<I, O>, Result<R, Coroutine<'a, I, O, R>>);
+
+/// Runs the coroutine over the inputs, recording everything it receives and sends
+///
+/// Returns the transcript, along with the result, or the coroutine
+/// if it ran out of inputs
+pub fn record<'a, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+    inputs: impl IntoIterator<Item = I>,
+) -> Recording<'a, I, O, R>
+where
+    I: Clone,
+{
+    let mut inputs = inputs.into_iter();
+    let mut entries = Vec::new();
+    let mut co = co;
+    let result = loop {
+        match run_step(co) {
+            StepResult::Done(value) => break Ok(value),
+            StepResult::Yield { output, next } => {
+                entries.push(Exchange::Output(output));
+                co = *next;
+            }
+            StepResult::Next(next) => match inputs.next() {
+                Some(input) => {
+                    entries.push(Exchange::Input(input.clone()));
+                    co = next(input);
+                }
+                None => break Err(suspend(next)),
+            },
+        }
+    };
+    (Transcript { entries }, result)
+}
+
+/// A loopback transcript, displayed as client and server columns
+///
+/// Packets sent are shown with ->, received with <-, and lost with x,
+/// in the column of the end that sent or received them
+pub struct EventLog<'t, C, S>(pub &'t [Event<C, S>]);
+
+impl<C: Display, S: Display> Display for EventLog<'_, C, S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let rows = self.0.iter().map(|event| match event {
+            Event::Sent(Packet::FromClient(c)) => (format!("-> {}", c), String::new()),
+            Event::Sent(Packet::FromServer(s)) => (String::new(), format!("-> {}", s)),
+            Event::Delivered(Packet::FromClient(c)) => (String::new(), format!("<- {}", c)),
+            Event::Delivered(Packet::FromServer(s)) => (format!("<- {}", s), String::new()),
+            Event::Dropped(Packet::FromClient(c)) => (format!("x {}", c), String::new()),
+            Event::Dropped(Packet::FromServer(s)) => (String::new(), format!("x {}", s)),
+            Event::Tick => ("tick".to_string(), "tick".to_string()),
+        });
+        write_columns(f, ("client", "server"), rows)
+    }
+}
+
+impl<'a, C, S, RC, RS> Loopback<'a, C, S, RC, RS> {
+    /// The transcript, ready to display
+    pub fn display_transcript(&self) -> EventLog<'_, C, S> {
+        EventLog(&self.transcript)
+    }
+}
+
+// Writes the rows as two columns, with the left padded to line up
+fn write_columns(
+    f: &mut Formatter<'_>,
+    headings: (&str, &str),
+    rows: impl Iterator<Item = (String, String)>,
+) -> fmt::Result {
+    let rows: Vec<_> = rows.collect();
+    let width = rows
+        .iter()
+        .map(|(left, _)| left.chars().count())
+        .chain(std::iter::once(headings.0.len()))
+        .max()
+        .unwrap_or(0);
+    let line = |f: &mut Formatter<'_>, left: &str, right: &str| {
+        let line = format!("{:width$} | {}", left, right, width = width);
+        writeln!(f, "{}", line.trim_end())
+    };
+    line(f, headings.0, headings.1)?;
+    writeln!(
+        f,
+        "{}+{}",
+        "-".repeat(width + 1),
+        "-".repeat(headings.1.len() + 1)
+    )?;
+    for (left, right) in rows {
+        line(f, &left, &right)?;
+    }
+    Ok(())
+}
+
+/// Inputs where only some have to arrive in a particular order
+///
+/// Each input can say which inputs must arrive before it.
+/// Any order that respects those is a valid way for them to arrive.
+#[derive(Debug, Clone, Default)]
+pub struct Script<I> {
+    inputs: Vec<I>,
+    after: Vec<Vec<usize>>,
+}
+
+impl<I> Script<I> {
+    /// A script with no inputs
+    pub fn new() -> Self {
+        Script {
+            inputs: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+
+    /// Adds an input that can arrive at any time, returning its id
+    pub fn input(&mut self, input: I) -> usize {
+        self.input_after(input, &[])
+    }
+
+    /// Adds an input that must arrive after the inputs with these ids
+    pub fn input_after(&mut self, input: I, after: &[usize]) -> usize {
+        let id = self.inputs.len();
+        assert!(
+            after.iter().all(|before| *before < id),
+            "inputs can only come after inputs already in the script"
+        );
+        self.inputs.push(input);
+        self.after.push(after.to_vec());
+        id
+    }
+
+    /// Up to limit orders the inputs could arrive in, as ids
+    pub fn orderings(&self, limit: usize) -> Vec<Vec<usize>> {
+        let mut orderings = Vec::new();
+        let mut order = Vec::with_capacity(self.inputs.len());
+        let mut used = vec![false; self.inputs.len()];
+        self.orderings_from(&mut order, &mut used, limit, &mut orderings);
+        orderings
+    }
+
+    fn orderings_from(
+        &self,
+        order: &mut Vec<usize>,
+        used: &mut Vec<bool>,
+        limit: usize,
+        orderings: &mut Vec<Vec<usize>>,
+    ) {
+        if orderings.len() >= limit {
+            return;
+        }
+        if order.len() == self.inputs.len() {
+            orderings.push(order.clone());
+            return;
+        }
+        for id in 0..self.inputs.len() {
+            let ready = !used[id] && self.after[id].iter().all(|before| used[*before]);
+            if ready {
+                used[id] = true;
+                order.push(id);
+                self.orderings_from(order, used, limit, orderings);
+                order.pop();
+                used[id] = false;
+            }
+        }
+    }
+}
+
+/// What a coroutine did with one ordering of the inputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome<O, R> {
+    /// Everything it sent, in order
+    pub outputs: Vec<O>,
+    /// The result, or None if it was still waiting for input
+    pub result: Option<R>,
+}
+
+/// Two orderings of the same inputs that gave different outcomes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence<I, O, R> {
+    /// The first ordering tried, and what happened
+    pub expected: (Vec<I>, Outcome<O, R>),
+    /// The ordering that behaved differently, and what happened
+    pub actual: (Vec<I>, Outcome<O, R>),
+}
+
+/// Runs fresh coroutines over different valid orderings of the script
+///
+/// Every ordering should give the same outputs and result, otherwise
+/// the coroutine depends on an order the script said didn't matter.
+/// Up to limit orderings are tried, as there can be very many.
+/// ```
+/// use bicoro::*;
+/// use bicoro::testing::*;
+///
+/// // sums three inputs, so the order shouldn't matter
+/// let sum = || -> Coroutine<i32, (), i32> { map(receive_n(3), |inputs: Vec<i32>| inputs.iter().sum()) };
+///
+/// let mut script = Script::new();
+/// let first = script.input(1);
+/// script.input(2);
+/// script.input_after(3, &[first]);
+///
+/// let outcome = run_scripted(sum, &script, 10).expect("order doesn't matter");
+/// assert_eq!(outcome.result, Some(6));
+///
+/// // but taking the first input does depend on the order
+/// let first_input = || -> Coroutine<i32, (), i32> { receive() };
+/// assert!(run_scripted(first_input, &script, 10).is_err());
+/// ```
+pub fn run_scripted<'a, I, O, R, F>(
+    factory: F,
+    script: &Script<I>,
+    limit: usize,
+) -> Result<Outcome<O, R>, Divergence<I, O, R>>
+where
+    F: Fn() -> Coroutine<'a, I, O, R>,
+    I: Clone + 'a,
+    O: PartialEq + 'a,
+    R: PartialEq + 'a,
+{
+    let run = |order: &[usize]| {
+        let inputs: Vec<I> = order.iter().map(|id| script.inputs[*id].clone()).collect();
+        let mut it = as_iterator(factory(), inputs.clone().into_iter());
+        let outputs = it.by_ref().collect();
+        let (result, _) = it.finish();
+        let result = result.ok();
+        (inputs, Outcome { outputs, result })
+    };
+
+    let mut orderings = script.orderings(limit).into_iter();
+    let expected = match orderings.next() {
+        Some(order) => run(&order),
+        None => run(&[]),
+    };
+    for order in orderings {
+        let actual = run(&order);
+        if actual.1 != expected.1 {
+            return Err(Divergence { expected, actual });
+        }
+    }
+    Ok(expected.1)
+}
+
+/// What a coroutine did with some inputs, as a value to assert against
+///
+/// Equality only looks at the outputs and the result, the paused coroutine is
+/// kept so the run can be continued, but is never compared.
+pub struct PartialRun<'a, I, O, R> {
+    /// Everything it sent, in order
+    pub outputs: Vec<O>,
+    /// The result, or None if it was still waiting for input
+    pub result: Option<R>,
+    /// The coroutine, if it was still waiting for input
+    pub paused: Option<Coroutine<'a, I, O, R>>,
+}
+
+impl<'a, I, O, R> PartialRun<'a, I, O, R> {
+    /// Expects the coroutine to have finished with result, after sending outputs
+    pub fn finished(outputs: Vec<O>, result: R) -> Self {
+        PartialRun {
+            outputs,
+            result: Some(result),
+            paused: None,
+        }
+    }
+
+    /// Expects the coroutine to be waiting for input, after sending outputs
+    pub fn paused(outputs: Vec<O>) -> Self {
+        PartialRun {
+            outputs,
+            result: None,
+            paused: None,
+        }
+    }
+}
+
+impl<I, O: PartialEq, R: PartialEq> PartialEq for PartialRun<'_, I, O, R> {
+    fn eq(&self, other: &Self) -> bool {
+        self.outputs == other.outputs && self.result == other.result
+    }
+}
+
+impl<I, O: Debug, R: Debug> Debug for PartialRun<'_, I, O, R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PartialRun")
+            .field("outputs", &self.outputs)
+            .field("result", &self.result)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Runs the coroutine over the inputs, capturing everything it did
+///
+/// Handy for table driven tests, as each case is a single assert_eq.
+/// ```
+/// use bicoro::*;
+/// use bicoro::testing::*;
+///
+/// let double = || -> Coroutine<i32, i32, ()> { receive().and_then(|i: i32| send(i * 2)) };
+///
+/// let cases = [
+///     (vec![], PartialRun::paused(vec![])),
+///     (vec![2], PartialRun::finished(vec![4], ())),
+///     (vec![2, 3], PartialRun::finished(vec![4], ())),
+/// ];
+/// for (inputs, expected) in cases {
+///     assert_eq!(run_partial(double(), inputs), expected);
+/// }
+/// ```
+pub fn run_partial<'a, I, O, R, Is>(
+    co: Coroutine<'a, I, O, R>,
+    inputs: Is,
+) -> PartialRun<'a, I, O, R>
+where
+    Is: IntoIterator<Item = I>,
+{
+    let mut it = as_iterator(co, inputs.into_iter());
+    let outputs = it.by_ref().collect();
+    match it.finish() {
+        (Ok(result), _) => PartialRun {
+            outputs,
+            result: Some(result),
+            paused: None,
+        },
+        (Err(co), _) => PartialRun {
+            outputs,
+            result: None,
+            paused: Some(co),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{receive, send};
+
+    type Co<I, O, R> = Coroutine<'static, I, O, R>;
+
+    #[test]
+    fn duplicated_packets_arrive_twice() {
+        let client: Co<(), i32, ()> = send(1);
+        let server: Co<i32, (), (i32, i32)> =
+            receive().and_then(|a| receive().and_then(move |b| crate::result((a, b))));
+
+        let run = loopback_with_faults(client, server, |_| Fault::Duplicate);
+
+        assert!(matches!(run.server, Ok((1, 1))));
+    }
+
+    #[test]
+    fn delayed_packets_arrive_after_ticks() {
+        let client: Co<(), i32, ()> = send(1).and_then(|()| send(2));
+        let server: Co<i32, (), i32> = receive();
+
+        let run = loopback_with_faults(client, server, |packet| match packet {
+            Packet::FromClient(1) => Fault::Delay(2),
+            _ => Fault::Deliver,
+        });
+
+        assert!(matches!(run.server, Ok(2)));
+        assert_eq!(
+            run.transcript,
+            vec![
+                Event::Sent(Packet::FromClient(1)),
+                Event::Sent(Packet::FromClient(2)),
+                Event::Delivered(Packet::FromClient(2)),
+                Event::Tick,
+                Event::Tick,
+            ]
+        );
+    }
+
+    #[test]
+    fn packets_delayed_by_no_ticks_arrive_straight_away() {
+        let client: Co<(), i32, ()> = send(1);
+        let server: Co<i32, (), i32> = receive();
+
+        let run = loopback_with_faults(client, server, |_| Fault::Delay(0));
+
+        assert!(matches!(run.server, Ok(1)));
+        assert!(!run.transcript.contains(&Event::Tick));
+    }
+
+    #[test]
+    fn same_seed_gives_same_faults() {
+        let faults = |seed| {
+            let mut injector = FaultInjector::new(seed)
+                .drop_rate(0.3)
+                .duplicate_rate(0.3)
+                .delay_rate(0.3, 4);
+            (0..32)
+                .map(|mut i| injector.fault(&mut i))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(faults(3), faults(3));
+        assert!(faults(3).contains(&Fault::Drop));
+    }
+
+    #[test]
+    fn delayed_outputs_are_sent_after_inputs() {
+        let co: Co<i32, i32, ()> = send(1).and_then(|()| receive()).and_then(send);
+        let co = inject_faults(co, FaultInjector::new(1).delay_rate(1.0, 1));
+
+        let outputs = crate::iterator::as_iterator(co, vec![2].into_iter()).collect::<Vec<_>>();
+
+        // 1 is released by the input, and 2 when the coroutine finishes
+        assert_eq!(outputs, vec![1, 2]);
+    }
+
+    #[test]
+    fn stalled_ends_are_returned() {
+        let client: Co<i32, (), i32> = receive();
+        let server: Co<(), i32, ()> = crate::result(());
+
+        let run = loopback(client, server);
+
+        assert!(run.client.is_err());
+        assert!(matches!(run.server, Ok(())));
+    }
+
+    #[test]
+    fn state_diffs_show_expected_and_actual() {
+        assert_eq!(diff_states(&[1, 2], &[1, 2]), None);
+
+        let diff = diff_states(&[1, 2, 3], &[1, 4]).unwrap();
+        assert_eq!(diff, "  0: 1\n- 1: 2\n+ 1: 4\n- 2: 3\n");
+    }
+
+    #[test]
+    fn event_log_shows_each_end_in_its_column() {
+        let client: Co<i32, i32, i32> = send(21).and_then(|()| receive());
+        let server: Co<i32, i32, ()> = receive().and_then(|i: i32| send(i * 2));
+
+        let run = loopback(client, server);
+        let log = run.display_transcript().to_string();
+        let expected = "client | server\n-------+-------\n-> 21  |\n       | <- 21\n       | -> 42\n<- 42  |\n";
+        assert_eq!(log, expected);
+    }
+
+    #[test]
+    fn orderings_respect_dependencies() {
+        let mut script = Script::new();
+        let a = script.input('a');
+        script.input('b');
+        script.input_after('c', &[a]);
+
+        let orderings = script.orderings(10);
+        assert_eq!(orderings, vec![vec![0, 1, 2], vec![0, 2, 1], vec![1, 0, 2]]);
+        assert_eq!(script.orderings(2).len(), 2);
+    }
+}
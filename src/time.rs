@@ -0,0 +1,440 @@
+//! Timers, driven by ticks sent as inputs
+//!
+//! Coroutines can't read a clock, so time is an input like any other.
+//! The convention is that a timed coroutine receives [Timed] inputs, and the
+//! host sends [Timed::Tick] at whatever rate it chooses. Timers can be run
+//! beside a protocol with [dispatch](function@crate::dispatch), or their
+//! inputs converted with [map_input](function@crate::map_input).
+//! Combinators take the coroutine first, then its ticks, always as a usize.
+use std::collections::VecDeque;
+
+use crate::{
+    bind, failable::ResultCoroutine, map, receive, result, run_step, send, suspend, Coroutine,
+    Never, StepResult,
+};
+
+pub use crate::{with_timeout, Timed, TimedOut};
+
+/// Waits for n ticks, finishing with the inputs that arrived meanwhile
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use bicoro::time::{after, Timed};
+///
+/// let co: Coroutine<Timed<&str>, (), Vec<&str>> = after(2);
+/// let inputs = vec![Timed::Tick, Timed::Input("early"), Timed::Tick, Timed::Input("late")];
+/// let mut it = as_iterator(co, inputs.into_iter());
+/// it.by_ref().for_each(drop);
+/// assert!(matches!(it.finish(), (Ok(early), _) if early == vec!["early"]));
+/// ```
+pub fn after<'a, I, O>(n: usize) -> Coroutine<'a, Timed<I>, O, Vec<I>>
+where
+    I: Send + 'a,
+{
+    fn waiting<'a, I, O>(left: usize, mut received: Vec<I>) -> Coroutine<'a, Timed<I>, O, Vec<I>>
+    where
+        I: Send + 'a,
+    {
+        if left == 0 {
+            return result(received);
+        }
+        bind(receive(), move |input| match input {
+            Timed::Tick => waiting(left - 1, received),
+            Timed::Input(input) => {
+                received.push(input);
+                waiting(left, received)
+            }
+        })
+    }
+    waiting(n, Vec::new())
+}
+
+/// Sends how many intervals have passed, every n ticks
+///
+/// Other inputs are ignored, so it can be given everything a protocol receives
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use bicoro::time::{interval, Timed};
+///
+/// let co: Coroutine<Timed<()>, usize, Never> = interval(2);
+/// let ticks = std::iter::repeat(Timed::Tick).take(5);
+/// assert_eq!(as_iterator(co, ticks).collect::<Vec<_>>(), vec![1, 2]);
+/// ```
+pub fn interval<'a, I>(n: usize) -> Coroutine<'a, Timed<I>, usize, Never>
+where
+    I: 'a,
+{
+    fn counting<'a, I>(
+        n: usize,
+        left: usize,
+        elapsed: usize,
+    ) -> Coroutine<'a, Timed<I>, usize, Never>
+    where
+        I: 'a,
+    {
+        if left == 0 {
+            let elapsed = elapsed + 1;
+            return bind(send(elapsed), move |()| counting(n, n.max(1), elapsed));
+        }
+        bind(receive(), move |input| match input {
+            Timed::Tick => counting(n, left - 1, elapsed),
+            Timed::Input(_) => counting(n, left, elapsed),
+        })
+    }
+    counting(n, n.max(1), 0)
+}
+
+/// Fails the coroutine if it hasn't finished within the ticks
+///
+/// Unlike [with_timeout], the ticks are counted over the whole run.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use bicoro::time::{deadline, Timed, TimedOut};
+///
+/// let co: Coroutine<i32, (), i32> = receive().and_then(|a: i32| receive().and_then(move |b: i32| result(a + b)));
+/// let co = deadline(co, 2);
+///
+/// let inputs = vec![Timed::Tick, Timed::Input(1), Timed::Tick, Timed::Input(2)];
+/// let mut it = as_iterator(co, inputs.into_iter());
+/// it.by_ref().for_each(drop);
+/// assert!(matches!(it.finish(), (Ok(Err(TimedOut)), _)));
+/// ```
+pub fn deadline<'a, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+    ticks: usize,
+) -> Coroutine<'a, Timed<I>, O, Result<R, TimedOut>>
+where
+    I: 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    match run_step(co) {
+        StepResult::Done(value) => result(Ok(value)),
+        StepResult::Yield { output, next } => bind(send(output), move |()| deadline(*next, ticks)),
+        StepResult::Next(next) => bind(receive(), move |input| match input {
+            Timed::Input(input) => deadline(next(input), ticks),
+            Timed::Tick if ticks <= 1 => result(Err(TimedOut)),
+            Timed::Tick => deadline(suspend(next), ticks - 1),
+        }),
+    }
+}
+
+/// Only sends an output once the coroutine has been quiet for the ticks
+///
+/// Outputs sent closer together replace each other, so only the last of
+/// a burst is sent. Anything held back is sent when the coroutine finishes.
+/// Outputs sent without waiting for an input between them are one burst,
+/// so a coroutine that never waits is never quiet, and is run without end.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use bicoro::time::{debounce, Timed};
+///
+/// let echo: Coroutine<i32, i32, Never> = forever(|| receive().and_then(send));
+/// let co = debounce(echo, 2);
+///
+/// let inputs = vec![Timed::Input(1), Timed::Input(2), Timed::Tick, Timed::Tick, Timed::Input(3)];
+/// assert_eq!(as_iterator(co, inputs.into_iter()).collect::<Vec<_>>(), vec![2]);
+/// ```
+pub fn debounce<'a, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+    quiet_ticks: usize,
+) -> Coroutine<'a, Timed<I>, O, R>
+where
+    I: 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    fn quieting<'a, I, O, R>(
+        mut co: Coroutine<'a, I, O, R>,
+        quiet_ticks: usize,
+        mut held: Option<(O, usize)>,
+    ) -> Coroutine<'a, Timed<I>, O, R>
+    where
+        I: 'a,
+        O: Send + 'a,
+        R: Send + 'a,
+    {
+        // Outputs that replace each other are stepped over in a loop, so a
+        // long burst doesn't grow the stack
+        loop {
+            match run_step(co) {
+                StepResult::Done(value) => {
+                    return match held {
+                        Some((output, _)) => bind(send(output), move |()| result(value)),
+                        None => result(value),
+                    }
+                }
+                StepResult::Yield { output, next } if quiet_ticks == 0 => {
+                    return bind(send(output), move |()| quieting(*next, quiet_ticks, None))
+                }
+                StepResult::Yield { output, next } => {
+                    held = Some((output, quiet_ticks));
+                    co = *next;
+                }
+                StepResult::Next(next) => {
+                    return bind(receive(), move |input| match (input, held) {
+                        (Timed::Input(input), held) => quieting(next(input), quiet_ticks, held),
+                        (Timed::Tick, Some((output, left))) if left <= 1 => {
+                            bind(send(output), move |()| {
+                                quieting(suspend(next), quiet_ticks, None)
+                            })
+                        }
+                        (Timed::Tick, held) => {
+                            let held = held.map(|(output, left)| (output, left - 1));
+                            quieting(suspend(next), quiet_ticks, held)
+                        }
+                    })
+                }
+            }
+        }
+    }
+    quieting(co, quiet_ticks, None)
+}
+
+// How many outputs throttle can send in the current window
+struct Window {
+    max: usize,
+    ticks: usize,
+    sent: usize,
+    left: usize,
+}
+
+impl Window {
+    // Counts a tick, starting a new window once it has passed
+    fn tick(&mut self) {
+        self.left = self.left.saturating_sub(1);
+        if self.left == 0 {
+            self.left = self.ticks;
+            self.sent = 0;
+        }
+    }
+}
+
+/// Sends at most max outputs every ticks, holding the rest back until later
+///
+/// Outputs are never dropped. Once max have been sent, the coroutine isn't
+/// run again until the next window, so only the output it is sending is held
+/// back. Inputs that arrive meanwhile are queued until it asks for them,
+/// and any it never asks for are dropped once it finishes.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use bicoro::time::{throttle, Timed};
+///
+/// let burst: Coroutine<(), i32, ()> = send(1).and_then(|()| send(2)).and_then(|()| send(3));
+/// let co = throttle(burst, 2, 1);
+///
+/// let mut it = as_iterator(co, vec![Timed::Tick].into_iter());
+/// assert_eq!(it.by_ref().take(2).collect::<Vec<_>>(), vec![1, 2]);
+/// // the third waits for the next tick
+/// assert_eq!(it.by_ref().collect::<Vec<_>>(), vec![3]);
+/// ```
+pub fn throttle<'a, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+    max: usize,
+    ticks: usize,
+) -> Coroutine<'a, Timed<I>, O, R>
+where
+    I: Send + 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    fn limiting<'a, I, O, R>(
+        mut co: Coroutine<'a, I, O, R>,
+        mut window: Window,
+        mut inputs: VecDeque<I>,
+    ) -> Coroutine<'a, Timed<I>, O, R>
+    where
+        I: Send + 'a,
+        O: Send + 'a,
+        R: Send + 'a,
+    {
+        // Queued inputs are given in a loop, so a long queue doesn't grow the stack
+        loop {
+            match run_step(co) {
+                StepResult::Done(value) => return result(value),
+                StepResult::Yield { output, next } => {
+                    return holding(output, *next, window, inputs)
+                }
+                StepResult::Next(next) => match inputs.pop_front() {
+                    Some(input) => co = next(input),
+                    None => {
+                        return bind(receive(), move |input| match input {
+                            Timed::Input(input) => limiting(next(input), window, inputs),
+                            Timed::Tick => {
+                                window.tick();
+                                limiting(suspend(next), window, inputs)
+                            }
+                        })
+                    }
+                },
+            }
+        }
+    }
+
+    // Sends the output once the window has room, then carries on
+    fn holding<'a, I, O, R>(
+        output: O,
+        co: Coroutine<'a, I, O, R>,
+        mut window: Window,
+        mut inputs: VecDeque<I>,
+    ) -> Coroutine<'a, Timed<I>, O, R>
+    where
+        I: Send + 'a,
+        O: Send + 'a,
+        R: Send + 'a,
+    {
+        if window.sent < window.max {
+            window.sent += 1;
+            return bind(send(output), move |()| limiting(co, window, inputs));
+        }
+        bind(receive(), move |input| {
+            match input {
+                Timed::Input(input) => inputs.push_back(input),
+                Timed::Tick => window.tick(),
+            }
+            holding(output, co, window, inputs)
+        })
+    }
+
+    let window = Window {
+        max: max.max(1),
+        ticks: ticks.max(1),
+        sent: 0,
+        left: ticks.max(1),
+    };
+    limiting(co, window, VecDeque::new())
+}
+
+/// Sends on_stall whenever the coroutine goes max_ticks without sending an output
+///
+/// The count starts again after each output, including the alert, so a
+/// coroutine that stays stalled is alerted about every max_ticks.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use bicoro::time::{watchdog, Timed};
+///
+/// let echo: Coroutine<i32, i32, Never> = forever(|| receive().and_then(send));
+/// let co = watchdog(echo, 2, -1);
+///
+/// let inputs = vec![Timed::Tick, Timed::Input(1), Timed::Tick, Timed::Tick];
+/// assert_eq!(as_iterator(co, inputs.into_iter()).collect::<Vec<_>>(), vec![1, -1]);
+/// ```
+pub fn watchdog<'a, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+    max_ticks: usize,
+    on_stall: O,
+) -> Coroutine<'a, Timed<I>, O, R>
+where
+    I: 'a,
+    O: Clone + Send + 'a,
+    R: Send + 'a,
+{
+    let alert = move || on_stall.clone();
+    let co = watching(co, max_ticks.max(1), max_ticks.max(1), Some(alert));
+    bind(co, |value| match value {
+        Ok(value) => result(value),
+        Err(TimedOut) => unreachable!("stalls are only alerted about"),
+    })
+}
+
+/// The failable version of watchdog, failing with the error when the coroutine stalls
+/// ```
+/// use bicoro::*;
+/// use bicoro::failable::*;
+/// use bicoro::executor::RunOutcome;
+/// use bicoro::time::{try_watchdog, Timed};
+///
+/// let co: ResultCoroutine<i32, i32, (), &str> = lift(receive()).and_then(|_: i32| lift(receive())).and_then(|i: i32| lift(send(i)));
+/// let co = try_watchdog(co, 2, "stalled");
+///
+/// let inputs = vec![Timed::Input(1), Timed::Tick, Timed::Tick];
+/// let mut it = as_result_iterator(co, inputs.into_iter());
+/// it.by_ref().for_each(drop);
+/// assert!(matches!(it.finish().0, RunOutcome::Failed("stalled")));
+/// ```
+pub fn try_watchdog<'a, I, O, R, E>(
+    co: ResultCoroutine<'a, I, O, R, E>,
+    max_ticks: usize,
+    error: E,
+) -> ResultCoroutine<'a, Timed<I>, O, R, E>
+where
+    I: 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+    E: Send + 'a,
+{
+    let no_alert = None::<fn() -> O>;
+    let co = watching(
+        co.to_coroutine(),
+        max_ticks.max(1),
+        max_ticks.max(1),
+        no_alert,
+    );
+    map(co, |value| value.unwrap_or(Err(error))).into()
+}
+
+// Runs the coroutine, alerting or timing out once it has been quiet for too long
+fn watching<'a, I, O, R, F>(
+    co: Coroutine<'a, I, O, R>,
+    max_ticks: usize,
+    left: usize,
+    on_stall: Option<F>,
+) -> Coroutine<'a, Timed<I>, O, Result<R, TimedOut>>
+where
+    F: Fn() -> O + Send + 'a,
+    I: 'a,
+    O: Send + 'a,
+    R: Send + 'a,
+{
+    match run_step(co) {
+        StepResult::Done(value) => result(Ok(value)),
+        StepResult::Yield { output, next } => bind(send(output), move |()| {
+            watching(*next, max_ticks, max_ticks, on_stall)
+        }),
+        StepResult::Next(next) => bind(receive(), move |input| match (input, on_stall) {
+            (Timed::Input(input), on_stall) => watching(next(input), max_ticks, left, on_stall),
+            (Timed::Tick, None) if left <= 1 => result(Err(TimedOut)),
+            (Timed::Tick, Some(alert)) if left <= 1 => bind(send(alert()), move |()| {
+                watching(suspend(next), max_ticks, max_ticks, Some(alert))
+            }),
+            (Timed::Tick, on_stall) => watching(suspend(next), max_ticks, left - 1, on_stall),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{iterator::as_iterator, util::constant};
+
+    type Co<I, O, R> = Coroutine<'static, I, O, R>;
+
+    fn burst(n: i32) -> Co<(), i32, ()> {
+        crate::iterate(0, move |i| match i {
+            i if i == n => result(std::ops::ControlFlow::Break(())),
+            i => bind(send(i), move |()| {
+                result(std::ops::ControlFlow::Continue(i + 1))
+            }),
+        })
+    }
+
+    #[test]
+    fn long_bursts_are_debounced_without_growing_the_stack() {
+        let co = debounce(burst(100_000), 2);
+        let outputs = as_iterator(co, std::iter::empty()).collect::<Vec<_>>();
+        assert_eq!(outputs, vec![99_999]);
+    }
+
+    #[test]
+    fn throttled_producers_wait_for_the_next_window() {
+        let co: Co<Timed<()>, i32, Never> = throttle(constant(1), 1, 1);
+        let ticks = std::iter::repeat_n(Timed::Tick, 2);
+        let outputs = as_iterator(co, ticks).collect::<Vec<_>>();
+        assert_eq!(outputs, vec![1, 1, 1]);
+    }
+}
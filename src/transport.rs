@@ -0,0 +1,151 @@
+use std::collections::VecDeque;
+
+use crate::{bind, receive, result, run_step, send, suspend, Coroutine, StepResult};
+
+/// An input from a transport that can only take so much at once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport<I> {
+    /// An input for the protocol
+    Input(I),
+    /// How much the transport can take right now
+    Capacity(usize),
+}
+
+/// An output that can be split into smaller pieces, such as bytes
+pub trait Chunk: Sized {
+    /// The size of the chunk, in the same units as the capacity
+    fn size(&self) -> usize;
+    /// Removes and returns the first at units, keeping the rest
+    fn split_front(&mut self, at: usize) -> Self;
+}
+
+impl<T> Chunk for Vec<T> {
+    fn size(&self) -> usize {
+        self.len()
+    }
+
+    fn split_front(&mut self, at: usize) -> Self {
+        let rest = self.split_off(at);
+        std::mem::replace(self, rest)
+    }
+}
+
+/// Only sends as much as the transport says it can take
+///
+/// The host tells the coroutine how much it can write with Transport::Capacity,
+/// which replaces whatever capacity was left. Outputs too big to fit are split,
+/// and the rest is sent once there is capacity. The coroutine is paused until then,
+/// so it can't get ahead of the transport. Inputs that arrive while it is paused
+/// are kept, and given to it in order once it continues.
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+///
+/// let greeting: Coroutine<(), Vec<u8>, ()> = send(b"hello".to_vec());
+/// let co = send_within_budget(greeting, 2);
+///
+/// let inputs = vec![Transport::Capacity(10)];
+/// let outputs = as_iterator(co, inputs.into_iter()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![b"he".to_vec(), b"llo".to_vec()]);
+/// ```
+pub fn send_within_budget<'a, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+    capacity: usize,
+) -> Coroutine<'a, Transport<I>, O, R>
+where
+    I: Send + 'a,
+    O: Chunk + Send + 'a,
+    R: Send + 'a,
+{
+    let budgeted = Budgeted {
+        capacity,
+        pending: None,
+        inputs: VecDeque::new(),
+    };
+    run_budgeted(co, budgeted)
+}
+
+struct Budgeted<I, O> {
+    capacity: usize,
+    // The part of an output that hasn't fit yet
+    pending: Option<O>,
+    // Inputs that arrived while paused
+    inputs: VecDeque<I>,
+}
+
+fn run_budgeted<'a, I, O, R>(
+    co: Coroutine<'a, I, O, R>,
+    mut budgeted: Budgeted<I, O>,
+) -> Coroutine<'a, Transport<I>, O, R>
+where
+    I: Send + 'a,
+    O: Chunk + Send + 'a,
+    R: Send + 'a,
+{
+    if let Some(mut pending) = budgeted.pending.take() {
+        let size = pending.size();
+        if size <= budgeted.capacity {
+            budgeted.capacity -= size;
+            return bind(send(pending), move |()| run_budgeted(co, budgeted));
+        }
+        if budgeted.capacity > 0 {
+            let chunk = pending.split_front(budgeted.capacity);
+            budgeted.capacity = 0;
+            budgeted.pending = Some(pending);
+            return bind(send(chunk), move |()| run_budgeted(co, budgeted));
+        }
+        budgeted.pending = Some(pending);
+        return bind(receive(), move |input| match input {
+            Transport::Capacity(capacity) => {
+                budgeted.capacity = capacity;
+                run_budgeted(co, budgeted)
+            }
+            Transport::Input(input) => {
+                budgeted.inputs.push_back(input);
+                run_budgeted(co, budgeted)
+            }
+        });
+    }
+
+    match run_step(co) {
+        StepResult::Done(value) => result(value),
+        StepResult::Yield { output, next } => {
+            budgeted.pending = Some(output);
+            run_budgeted(*next, budgeted)
+        }
+        StepResult::Next(next) => match budgeted.inputs.pop_front() {
+            Some(input) => run_budgeted(next(input), budgeted),
+            None => bind(receive(), move |input| match input {
+                Transport::Capacity(capacity) => {
+                    budgeted.capacity = capacity;
+                    run_budgeted(suspend(next), budgeted)
+                }
+                Transport::Input(input) => run_budgeted(next(input), budgeted),
+            }),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iterator::as_iterator;
+
+    type Co<I, O, R> = Coroutine<'static, I, O, R>;
+
+    #[test]
+    fn inputs_while_paused_are_kept() {
+        let co: Co<u8, Vec<u8>, u8> =
+            bind(receive(), |i: u8| bind(send(vec![i; 3]), |()| receive()));
+        let inputs = vec![
+            Transport::Input(1),
+            Transport::Input(2),
+            Transport::Capacity(5),
+        ];
+
+        let mut it = as_iterator(send_within_budget(co, 0), inputs.into_iter());
+        assert_eq!(it.next(), Some(vec![1, 1, 1]));
+        assert_eq!(it.next(), None);
+        assert!(matches!(it.finish(), (Ok(2), _)));
+    }
+}
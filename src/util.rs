@@ -0,0 +1,77 @@
+//! Small coroutines to build with, or to stand in for the real thing
+//!
+//! These are handy in tests and when wiring coroutines together, where
+//! one end of a combinator just needs to be something sensible. None of
+//! them finish, so they are shown with a Never result.
+use crate::{bind, forever, receive, send, Coroutine, Never};
+
+/// Sends every input straight back out
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use bicoro::util::echo;
+///
+/// let outputs = as_iterator(echo(), vec![1, 2].into_iter()).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec![1, 2]);
+/// ```
+pub fn echo<'a, I>() -> Coroutine<'a, I, I, Never>
+where
+    I: Send + 'a,
+{
+    forever(|| bind(receive(), send))
+}
+
+/// Gives every input to f, never sending anything
+///
+/// f can fold the inputs into anything it captures
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use bicoro::util::sink;
+/// use std::sync::mpsc::channel;
+///
+/// let (sender, received) = channel();
+/// let co: Coroutine<i32, (), Never> = sink(move |i| sender.send(i).unwrap());
+/// as_iterator(co, vec![1, 2].into_iter()).for_each(drop);
+/// assert_eq!(received.try_iter().sum::<i32>(), 3);
+/// ```
+pub fn sink<'a, I, O, F>(mut f: F) -> Coroutine<'a, I, O, Never>
+where
+    F: FnMut(I) + Send + 'a,
+{
+    bind(receive(), move |input| {
+        f(input);
+        sink(f)
+    })
+}
+
+/// Sends the value over and over, without reading any inputs
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use bicoro::util::constant;
+///
+/// let co: Coroutine<(), &str, Never> = constant("tick");
+/// let outputs = as_iterator(co, std::iter::empty()).take(2).collect::<Vec<_>>();
+/// assert_eq!(outputs, vec!["tick", "tick"]);
+/// ```
+pub fn constant<'a, I, O>(output: O) -> Coroutine<'a, I, O, Never>
+where
+    O: Clone + Send + 'a,
+{
+    forever(move || send(output.clone()))
+}
+
+/// Reads inputs forever, throwing them away and never sending anything
+/// ```
+/// use bicoro::*;
+/// use bicoro::iterator::*;
+/// use bicoro::util::silent;
+///
+/// let co: Coroutine<i32, (), Never> = silent();
+/// let mut it = as_iterator(co, vec![1, 2].into_iter());
+/// assert_eq!(it.next(), None);
+/// ```
+pub fn silent<'a, I, O>() -> Coroutine<'a, I, O, Never> {
+    sink(drop)
+}